@@ -0,0 +1,11 @@
+#version 150 core
+
+in vec2 v_uv;
+
+out vec4 Target0;
+
+uniform sampler2D source;
+
+void main() {
+    Target0 = texture(source, v_uv);
+}
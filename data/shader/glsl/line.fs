@@ -0,0 +1,11 @@
+#version 150 core
+
+in vec4 v_color;
+
+out vec4 Target0;
+
+void main() {
+    // The colours are already as they should appear on screen, so they skip
+    // the tone mapping the lit geometry goes through.
+    Target0 = v_color;
+}
@@ -0,0 +1,15 @@
+#version 150 core
+
+in vec3 position;
+in vec4 color;
+
+out vec4 v_color;
+
+layout (std140) uniform line_locals {
+    mat4 view_projection;
+};
+
+void main() {
+    v_color = color;
+    gl_Position = view_projection * vec4(position, 1.0);
+}
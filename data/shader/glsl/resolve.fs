@@ -0,0 +1,15 @@
+#version 150 core
+
+out vec4 Target0;
+
+uniform sampler2DMS source;
+uniform int samples;
+
+void main() {
+    ivec2 coord = ivec2(gl_FragCoord.xy);
+    vec4 sum = vec4(0.0);
+    for (int i = 0; i < samples; ++i) {
+        sum += texelFetch(source, coord, i);
+    }
+    Target0 = sum / float(samples);
+}
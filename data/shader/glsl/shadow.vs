@@ -0,0 +1,16 @@
+#version 150 core
+
+in vec3 position;
+
+layout (std140) uniform model_locals {
+    mat4 model_matrix;
+    mat4 normal_matrix;
+};
+
+layout (std140) uniform shadow_locals {
+    mat4 shadow_transform;
+};
+
+void main() {
+    gl_Position = shadow_transform * model_matrix * vec4(position, 1.0);
+}
@@ -0,0 +1,59 @@
+#version 150 core
+
+const float PI = 3.14159265359;
+
+const uint SKY_GRADIENT = 0u;
+const uint SKY_EQUIRECTANGULAR = 1u;
+
+in vec4 v_direction;
+
+out vec4 Target0;
+
+uniform sampler2D equirect_map;
+uniform samplerCube cube_map;
+
+layout (std140) uniform sky_locals {
+    mat4 inverse_view_projection;
+    vec4 zenith_color;
+    vec4 horizon_color;
+    vec4 ground_color;
+    uint sky_mode;
+    float sky_intensity;
+};
+
+vec3 srgb_to_linear(vec3 color) {
+    return pow(color, vec3(2.2));
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    return pow(color, vec3(1.0 / 2.2));
+}
+
+vec2 equirect_uv(vec3 direction) {
+    vec3 d = normalize(direction);
+    return vec2(0.5 + atan(d.x, -d.z) / (2.0 * PI), acos(clamp(d.y, -1.0, 1.0)) / PI);
+}
+
+void main() {
+    vec3 direction = normalize(v_direction.xyz / v_direction.w);
+
+    vec3 color;
+    if (sky_mode == SKY_GRADIENT) {
+        float height = direction.y;
+        if (height >= 0.0) {
+            color = mix(horizon_color.rgb, zenith_color.rgb, sqrt(height));
+        } else {
+            color = mix(horizon_color.rgb, ground_color.rgb, sqrt(-height));
+        }
+    } else if (sky_mode == SKY_EQUIRECTANGULAR) {
+        color = texture(equirect_map, equirect_uv(direction)).rgb;
+    } else {
+        color = srgb_to_linear(texture(cube_map, direction).rgb);
+    }
+    color *= sky_intensity;
+
+    // The same tone mapping as the lit geometry, so the two match.
+    color = color / (color + vec3(1.0));
+
+    Target0 = vec4(linear_to_srgb(color), 1.0);
+}
@@ -0,0 +1,22 @@
+#version 150 core
+
+in vec2 position;
+
+out vec4 v_direction;
+
+layout (std140) uniform sky_locals {
+    mat4 inverse_view_projection;
+    vec4 zenith_color;
+    vec4 horizon_color;
+    vec4 ground_color;
+    uint sky_mode;
+    float sky_intensity;
+};
+
+void main() {
+    // The direction is left unnormalized, since only the division by w after
+    // interpolation gives the right direction for each fragment.
+    v_direction = inverse_view_projection * vec4(position, 1.0, 1.0);
+    // z equal to w puts the triangle on the far plane, behind everything else.
+    gl_Position = vec4(position, 1.0, 1.0);
+}
@@ -1,62 +1,266 @@
 #version 150 core
 
 const int MAX_LIGHTS = 10;
+const int MAX_SHADOW_LAYERS = 16;
+const int CASCADE_COUNT = 3;
+const float PI = 3.14159265359;
+
+const uint LIGHT_DIRECTIONAL = uint(1);
+
+// Dielectrics reflect roughly 4% of light head-on.
+const vec3 DIELECTRIC_F0 = vec3(0.04);
 
 in vec2 v_tex_coord;
 in vec3 frag_position_world;
 in vec3 normal_camera;
-in mat4 model_view_matrix;
 
 out vec4 Target0;
 
 uniform sampler2D color_texture;
+uniform sampler2D metallic_roughness_texture;
+uniform sampler2D occlusion_texture;
+uniform sampler2D emissive_texture;
+uniform sampler2DArrayShadow shadow_map;
+uniform sampler2D specular_map;
 
 struct Light {
     vec4 color;
     vec3 position;
     float power;
+    vec3 direction;
+    uint kind;
+    int shadow_index;
+};
+
+struct ShadowTransform {
+    mat4 shadow_matrix;
 };
 
-layout (std140) uniform shared_locals {
+layout (std140) uniform frame_locals {
+    mat4 projection_matrix;
+    mat4 view_matrix;
+    vec4 camera_position;
+    vec4 cascade_splits;
+    float time;
     uint num_lights;
 };
 
+layout (std140) uniform material_locals {
+    vec4 base_color_factor;
+    vec3 emissive_factor;
+    float metallic_factor;
+    float roughness_factor;
+    float occlusion_strength;
+};
+
 layout (std140) uniform lights_array {
     Light lights[MAX_LIGHTS];
 };
 
-vec4 extract_camera_position(mat4 model_view) {
-    mat4 view_model = inverse(model_view);
-    return view_model[3];
+layout (std140) uniform environment_locals {
+    vec4 irradiance_sh0;
+    vec4 irradiance_sh1;
+    vec4 irradiance_sh2;
+    vec4 irradiance_sh3;
+    vec4 irradiance_sh4;
+    vec4 irradiance_sh5;
+    vec4 irradiance_sh6;
+    vec4 irradiance_sh7;
+    vec4 irradiance_sh8;
+    float specular_max_lod;
+    float environment_intensity;
+};
+
+layout (std140) uniform shadow_transforms_array {
+    ShadowTransform shadow_transforms[MAX_SHADOW_LAYERS];
+};
+
+vec3 srgb_to_linear(vec3 color) {
+    return pow(color, vec3(2.2));
+}
+
+vec3 linear_to_srgb(vec3 color) {
+    return pow(color, vec3(1.0 / 2.2));
+}
+
+// Trowbridge-Reitz GGX normal distribution function.
+float distribution_ggx(float n_dot_h, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
+}
+
+float geometry_schlick_ggx(float n_dot_x, float roughness) {
+    float r = roughness + 1.0;
+    float k = (r * r) / 8.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// Smith's method, combining the masking and shadowing terms.
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+    return geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+}
+
+vec3 fresnel_schlick(float cos_theta, vec3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 f0, float roughness) {
+    return f0 + (max(vec3(1.0 - roughness), f0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Must be kept in sync with `direction_to_equirect` in `environment.rs`.
+vec2 equirect_uv(vec3 direction) {
+    vec3 d = normalize(direction);
+    return vec2(0.5 + atan(d.x, -d.z) / (2.0 * PI), acos(clamp(d.y, -1.0, 1.0)) / PI);
+}
+
+// The irradiance arriving at a surface with the normal `n`, divided by pi.
+vec3 environment_irradiance(vec3 n) {
+    vec3 irradiance = irradiance_sh0.rgb * 0.282095
+        + irradiance_sh1.rgb * 0.488603 * n.y
+        + irradiance_sh2.rgb * 0.488603 * n.z
+        + irradiance_sh3.rgb * 0.488603 * n.x
+        + irradiance_sh4.rgb * 1.092548 * n.x * n.y
+        + irradiance_sh5.rgb * 1.092548 * n.y * n.z
+        + irradiance_sh6.rgb * 0.315392 * (3.0 * n.z * n.z - 1.0)
+        + irradiance_sh7.rgb * 1.092548 * n.x * n.z
+        + irradiance_sh8.rgb * 0.546274 * (n.x * n.x - n.y * n.y);
+    return max(irradiance, vec3(0.0));
+}
+
+// Analytical fit of the split-sum environment BRDF, from Karis' "Physically
+// Based Shading on Mobile".
+vec2 environment_brdf(float roughness, float n_dot_v) {
+    const vec4 c0 = vec4(-1.0, -0.0275, -0.572, 0.022);
+    const vec4 c1 = vec4(1.0, 0.0425, 1.04, -0.04);
+    vec4 r = roughness * c0 + c1;
+    float a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    return vec2(-1.04, 1.04) * a004 + r.zw;
+}
+
+// 3x3 percentage closer filtering of a single shadow map layer.
+float sample_shadow(int layer, vec3 world_position, float bias) {
+    vec4 light_space = shadow_transforms[layer].shadow_matrix * vec4(world_position, 1.0);
+    vec3 coords = (light_space.xyz / light_space.w) * 0.5 + 0.5;
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+
+    vec2 texel_size = 1.0 / vec2(textureSize(shadow_map, 0).xy);
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            vec2 uv = coords.xy + vec2(x, y) * texel_size;
+            lit += texture(shadow_map, vec4(uv, float(layer), coords.z - bias));
+        }
+    }
+    return lit / 9.0;
+}
+
+float shadow_factor(uint light_index, vec3 world_position, float n_dot_l) {
+    int layer = lights[light_index].shadow_index;
+    if (layer < 0) {
+        return 1.0;
+    }
+
+    float bias = max(0.002 * (1.0 - n_dot_l), 0.0005);
+
+    if (lights[light_index].kind == LIGHT_DIRECTIONAL) {
+        float view_depth = abs((view_matrix * vec4(world_position, 1.0)).z);
+        int cascade = CASCADE_COUNT;
+        for (int i = CASCADE_COUNT - 1; i >= 0; i--) {
+            if (view_depth <= cascade_splits[i]) {
+                cascade = i;
+            }
+        }
+        if (cascade == CASCADE_COUNT) {
+            return 1.0;
+        }
+        layer += cascade;
+    } else {
+        // Must be kept in the same order as `point_light_transforms`.
+        vec3 to_fragment = world_position - lights[light_index].position;
+        vec3 extent = abs(to_fragment);
+        int face;
+        if (extent.x >= extent.y && extent.x >= extent.z) {
+            face = to_fragment.x > 0.0 ? 0 : 1;
+        } else if (extent.y >= extent.z) {
+            face = to_fragment.y > 0.0 ? 2 : 3;
+        } else {
+            face = to_fragment.z > 0.0 ? 4 : 5;
+        }
+        layer += face;
+    }
+
+    return sample_shadow(layer, world_position, bias);
 }
 
 void main() {
-    vec4 total_lighting = vec4(0.0, 0.0, 0.0, 0.0);
-    vec4 cam_position = extract_camera_position(model_view_matrix);
+    vec4 base_color_sample = texture(color_texture, v_tex_coord);
+    vec3 base_color = srgb_to_linear(base_color_sample.rgb) * base_color_factor.rgb;
+    float alpha = base_color_sample.a * base_color_factor.a;
+
+    vec4 metallic_roughness = texture(metallic_roughness_texture, v_tex_coord);
+    float roughness = clamp(metallic_roughness.g * roughness_factor, 0.04, 1.0);
+    float metallic = clamp(metallic_roughness.b * metallic_factor, 0.0, 1.0);
+
+    float occlusion = mix(1.0, texture(occlusion_texture, v_tex_coord).r, occlusion_strength);
+    vec3 emissive = srgb_to_linear(texture(emissive_texture, v_tex_coord).rgb) * emissive_factor;
+
+    vec3 n = normalize(normal_camera);
+    vec3 v = normalize(camera_position.xyz - frag_position_world);
+    float n_dot_v = max(dot(n, v), 0.0001);
+
+    vec3 f0 = mix(DIELECTRIC_F0, base_color, metallic);
+    vec3 diffuse_color = base_color * (1.0 - metallic);
+
+    vec3 total_lighting = vec3(0.0);
 
     for (uint i = uint(0); i < min(num_lights, MAX_LIGHTS); i++) {
-        vec3 light_position = lights[i].position;
-        vec4 light_color = lights[i].color;
-        float light_power = lights[i].power;
-
-        // ambient
-        vec4 ambient = light_color * light_power * 0.0001;
-
-        // diffuse
-        vec3 norm = normalize(normal_camera);
-        vec3 light_direction = normalize(light_position - frag_position_world);
-        float diff = max(dot(norm, light_direction), 0.0);
-        vec4 diffuse = diff * light_color;
-
-        // specular
-        vec4 specular_strength = vec4(vec3(light_power * 0.1), 1.0);
-        vec3 view_direction = normalize(cam_position.xyz - frag_position_world);
-        vec3 reflect_direction = reflect(-light_direction, norm);
-        float spec = pow(max(dot(view_direction, reflect_direction), 0.0), 32.0);
-        vec4 specular = specular_strength * spec * light_color;
-
-        total_lighting += (ambient + diffuse + specular);
+        vec3 l;
+        vec3 radiance;
+        if (lights[i].kind == LIGHT_DIRECTIONAL) {
+            l = -normalize(lights[i].direction);
+            radiance = lights[i].color.rgb * lights[i].power;
+        } else {
+            vec3 to_light = lights[i].position - frag_position_world;
+            float distance_squared = max(dot(to_light, to_light), 0.0001);
+            l = normalize(to_light);
+            radiance = lights[i].color.rgb * lights[i].power / (4.0 * PI * distance_squared);
+        }
+
+        vec3 h = normalize(v + l);
+        float n_dot_l = max(dot(n, l), 0.0);
+        float n_dot_h = max(dot(n, h), 0.0);
+        float h_dot_v = max(dot(h, v), 0.0);
+
+        vec3 f = fresnel_schlick(h_dot_v, f0);
+        float d = distribution_ggx(n_dot_h, roughness);
+        float g = geometry_smith(n_dot_v, n_dot_l, roughness);
+
+        vec3 specular = (d * g * f) / max(4.0 * n_dot_v * n_dot_l, 0.0001);
+        vec3 diffuse = (vec3(1.0) - f) * diffuse_color / PI;
+
+        float shadow = shadow_factor(i, frag_position_world, n_dot_l);
+
+        total_lighting += (diffuse + specular) * radiance * n_dot_l * shadow;
     }
 
-	Target0 = texture(color_texture, v_tex_coord) * total_lighting;
+    vec3 ambient_fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    vec3 ambient_diffuse = environment_irradiance(n) * diffuse_color * (vec3(1.0) - ambient_fresnel);
+
+    vec3 reflected = reflect(-v, n);
+    vec3 prefiltered = textureLod(specular_map, equirect_uv(reflected), roughness * specular_max_lod).rgb;
+    vec2 brdf = environment_brdf(roughness, n_dot_v);
+    vec3 ambient_specular = prefiltered * (ambient_fresnel * brdf.x + brdf.y);
+
+    vec3 ambient = (ambient_diffuse + ambient_specular) * occlusion * environment_intensity;
+    vec3 color = ambient + total_lighting + emissive;
+
+    // Reinhard tone mapping, since the render target is not floating point.
+    color = color / (color + vec3(1.0));
+
+    Target0 = vec4(linear_to_srgb(color), alpha);
 }
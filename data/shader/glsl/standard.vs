@@ -7,21 +7,27 @@ in vec3 normal;
 out vec2 v_tex_coord;
 out vec3 frag_position_world;
 out vec3 normal_camera;
-out mat4 model_view_matrix;
 
-layout (std140) uniform vert_locals {
-    mat4 projection_matrix;
+layout (std140) uniform model_locals {
     mat4 model_matrix;
+    mat4 normal_matrix;
+};
+
+layout (std140) uniform frame_locals {
+    mat4 projection_matrix;
     mat4 view_matrix;
+    vec4 camera_position;
+    vec4 cascade_splits;
+    float time;
+    uint num_lights;
 };
 
 void main() {
-    model_view_matrix = view_matrix * model_matrix;
-    mat4 mvp = projection_matrix * model_view_matrix;
+    mat4 mvp = projection_matrix * view_matrix * model_matrix;
     v_tex_coord = tex_coord;
     gl_Position = mvp * vec4(position, 1.0);
     frag_position_world = (model_matrix * vec4(position, 1.0)).xyz;
-    normal_camera = mat3(transpose(inverse(model_matrix))) * normal;
+    normal_camera = mat3(normal_matrix) * normal;
 
     gl_ClipDistance[0] = 1.0;
 }
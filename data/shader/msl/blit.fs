@@ -0,0 +1,23 @@
+#include <metal_stdlib>
+
+using namespace metal;
+
+struct VertexOutput {
+    float4 vertex_position [[position]];
+    float2 uv;
+};
+
+struct FragmentOut {
+	float4 main [[color(0)]];
+};
+
+// The source was already written with its channels swizzled for the drawable,
+// so it is copied across unchanged.
+fragment FragmentOut frag(VertexOutput vertices     [[stage_in]],
+                          texture2d<float> source   [[texture(0)]],
+                          sampler source_           [[sampler(0)]]) {
+    FragmentOut out;
+	out.main = source.sample(source_, vertices.uv);
+
+	return out;
+}
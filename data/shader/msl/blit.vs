@@ -0,0 +1,20 @@
+#include <metal_stdlib>
+
+using namespace metal;
+
+struct VertexInput {
+    float2 position  [[attribute(0)]];
+};
+
+struct VertexOutput {
+    float4 vertex_position [[position]];
+    float2 uv;
+};
+
+vertex VertexOutput vert(VertexInput vertices [[stage_in]]) {
+    VertexOutput out;
+    // Metal textures start at the top left, unlike clip space.
+    out.uv = float2(vertices.position.x * 0.5 + 0.5, 0.5 - vertices.position.y * 0.5);
+    out.vertex_position = float4(vertices.position, 0.0, 1.0);
+    return out;
+}
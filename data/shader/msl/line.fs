@@ -0,0 +1,21 @@
+#include <metal_stdlib>
+
+using namespace metal;
+
+struct VertexOutput {
+    float4 vertex_position [[position]];
+    float4 color;
+};
+
+struct FragmentOut {
+	float4 main [[color(0)]];
+};
+
+// The colours are already as they should appear on screen, so they skip the
+// tone mapping the lit geometry goes through.
+fragment FragmentOut frag(VertexOutput vertices [[stage_in]]) {
+    FragmentOut out;
+	out.main = vertices.color.zyxw;
+
+	return out;
+}
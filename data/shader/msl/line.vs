@@ -0,0 +1,25 @@
+#include <metal_stdlib>
+
+using namespace metal;
+
+struct VertexInput {
+    float3 position  [[attribute(0)]];
+    float4 color     [[attribute(1)]];
+};
+
+struct VertexOutput {
+    float4 vertex_position [[position]];
+    float4 color;
+};
+
+struct LineUniforms {
+    float4x4 view_projection;
+};
+
+vertex VertexOutput vert(VertexInput vertices               [[stage_in]],
+                         constant LineUniforms& line_locals [[buffer(0)]]) {
+    VertexOutput out;
+    out.color = vertices.color;
+    out.vertex_position = line_locals.view_projection * float4(vertices.position, 1.0);
+    return out;
+}
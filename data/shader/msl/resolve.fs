@@ -0,0 +1,28 @@
+#include <metal_stdlib>
+
+using namespace metal;
+
+struct VertexOutput {
+    float4 vertex_position [[position]];
+    float2 uv;
+};
+
+struct FragmentOut {
+	float4 main [[color(0)]];
+};
+
+fragment FragmentOut frag(VertexOutput vertices        [[stage_in]],
+                          texture2d_ms<float> source   [[texture(0)]],
+                          sampler source_              [[sampler(0)]]) {
+    uint2 coord = uint2(vertices.vertex_position.xy);
+    uint samples = source.get_num_samples();
+    float4 sum = float4(0.0);
+    for (uint i = 0; i < samples; ++i) {
+        sum += source.read(coord, i);
+    }
+
+    FragmentOut out;
+	out.main = sum / float(samples);
+
+	return out;
+}
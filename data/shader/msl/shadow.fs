@@ -0,0 +1,10 @@
+#include <metal_stdlib>
+
+using namespace metal;
+
+struct VertexOutput {
+    float4 vertex_position [[position]];
+};
+
+fragment void frag(VertexOutput vertices [[stage_in]]) {
+}
@@ -0,0 +1,29 @@
+#include <metal_stdlib>
+
+using namespace metal;
+
+struct VertexInput {
+    float3 position  [[attribute(0)]];
+};
+
+struct VertexOutput {
+    float4 vertex_position [[position]];
+};
+
+struct ModelUniforms {
+    float4x4 model_matrix;
+    float4x4 normal_matrix;
+};
+
+struct ShadowUniforms {
+    float4x4 shadow_transform;
+};
+
+vertex VertexOutput vert(VertexInput vertices                     [[stage_in]],
+                         constant ModelUniforms& model_locals     [[buffer(0)]],
+                         constant ShadowUniforms& shadow_locals   [[buffer(1)]]) {
+    VertexOutput out;
+    out.vertex_position = shadow_locals.shadow_transform * model_locals.model_matrix *
+                          float4(vertices.position, 1.0);
+    return out;
+}
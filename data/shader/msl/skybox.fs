@@ -0,0 +1,71 @@
+#include <metal_stdlib>
+
+using namespace metal;
+
+constant float PI = 3.14159265359;
+
+constant uint SKY_GRADIENT = 0;
+constant uint SKY_EQUIRECTANGULAR = 1;
+
+struct VertexOutput {
+    float4 vertex_position [[position]];
+    float4 direction;
+};
+
+struct FragmentOut {
+	float4 main [[color(0)]];
+};
+
+struct SkyUniforms {
+    float4x4 inverse_view_projection;
+    float4 zenith_color;
+    float4 horizon_color;
+    float4 ground_color;
+    uint sky_mode;
+    float sky_intensity;
+};
+
+float3 srgb_to_linear(float3 color) {
+    return pow(color, float3(2.2));
+}
+
+float3 linear_to_srgb(float3 color) {
+    return pow(color, float3(1.0 / 2.2));
+}
+
+float2 equirect_uv(float3 direction) {
+    float3 d = normalize(direction);
+    return float2(0.5 + atan2(d.x, -d.z) / (2.0 * PI), acos(clamp(d.y, -1.0, 1.0)) / PI);
+}
+
+fragment FragmentOut frag(VertexOutput vertices                 [[stage_in]],
+                          constant SkyUniforms& sky_locals      [[buffer(0)]],
+                          texture2d<float> equirect_map         [[texture(0)]],
+                          sampler equirect_map_                 [[sampler(0)]],
+                          texturecube<float> cube_map           [[texture(1)]],
+                          sampler cube_map_                     [[sampler(1)]]) {
+    float3 direction = normalize(vertices.direction.xyz / vertices.direction.w);
+
+    float3 color;
+    if (sky_locals.sky_mode == SKY_GRADIENT) {
+        float height = direction.y;
+        if (height >= 0.0) {
+            color = mix(sky_locals.horizon_color.rgb, sky_locals.zenith_color.rgb, sqrt(height));
+        } else {
+            color = mix(sky_locals.horizon_color.rgb, sky_locals.ground_color.rgb, sqrt(-height));
+        }
+    } else if (sky_locals.sky_mode == SKY_EQUIRECTANGULAR) {
+        color = equirect_map.sample(equirect_map_, equirect_uv(direction)).rgb;
+    } else {
+        color = srgb_to_linear(cube_map.sample(cube_map_, direction).rgb);
+    }
+    color *= sky_locals.sky_intensity;
+
+    // The same tone mapping as the lit geometry, so the two match.
+    color = color / (color + float3(1.0));
+
+    FragmentOut out;
+	out.main = float4(linear_to_srgb(color), 1.0).zyxw;
+
+	return out;
+}
@@ -0,0 +1,32 @@
+#include <metal_stdlib>
+
+using namespace metal;
+
+struct VertexInput {
+    float2 position  [[attribute(0)]];
+};
+
+struct VertexOutput {
+    float4 vertex_position [[position]];
+    float4 direction;
+};
+
+struct SkyUniforms {
+    float4x4 inverse_view_projection;
+    float4 zenith_color;
+    float4 horizon_color;
+    float4 ground_color;
+    uint sky_mode;
+    float sky_intensity;
+};
+
+vertex VertexOutput vert(VertexInput vertices               [[stage_in]],
+                         constant SkyUniforms& sky_locals   [[buffer(0)]]) {
+    VertexOutput out;
+    // The direction is left unnormalized, since only the division by w after
+    // interpolation gives the right direction for each fragment.
+    out.direction = sky_locals.inverse_view_projection * float4(vertices.position, 1.0, 1.0);
+    // z equal to w puts the triangle on the far plane, behind everything else.
+    out.vertex_position = float4(vertices.position, 1.0, 1.0);
+    return out;
+}
@@ -2,6 +2,15 @@
 
 using namespace metal;
 
+constant uint MAX_LIGHTS = 10;
+constant int CASCADE_COUNT = 3;
+constant float PI = 3.14159265359;
+
+constant uint LIGHT_DIRECTIONAL = 1;
+
+// Dielectrics reflect roughly 4% of light head-on.
+constant float3 DIELECTRIC_F0 = float3(0.04);
+
 struct VertexOutput {
     float4 vertex_position [[position]];
     float2 uv;
@@ -13,14 +22,269 @@ struct FragmentOut {
 	float4 main [[color(0)]];
 };
 
-fragment FragmentOut frag(VertexOutput vertices          [[stage_in]],
-                          texture2d<float> color_texture [[texture(0)]],
-                          sampler color_texture_         [[sampler(0)]]) {
+struct Light {
+    float4 color;
+    packed_float3 position;
+    float power;
+    packed_float3 direction;
+    uint kind;
+    int shadow_index;
+};
+
+struct ShadowTransform {
+    float4x4 shadow_matrix;
+};
+
+struct FrameUniforms {
+    float4x4 projection_matrix;
+    float4x4 view_matrix;
+    float4 camera_position;
+    float4 cascade_splits;
+    float time;
+    uint num_lights;
+};
+
+struct EnvironmentUniforms {
+    float4 irradiance_sh[9];
+    float specular_max_lod;
+    float environment_intensity;
+};
+
+struct MaterialUniforms {
+    float4 base_color_factor;
+    packed_float3 emissive_factor;
+    float metallic_factor;
+    float roughness_factor;
+    float occlusion_strength;
+};
+
+float3 srgb_to_linear(float3 color) {
+    return pow(color, float3(2.2));
+}
+
+float3 linear_to_srgb(float3 color) {
+    return pow(color, float3(1.0 / 2.2));
+}
+
+// Trowbridge-Reitz GGX normal distribution function.
+float distribution_ggx(float n_dot_h, float roughness) {
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float denom = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    return a2 / (PI * denom * denom);
+}
+
+float geometry_schlick_ggx(float n_dot_x, float roughness) {
+    float r = roughness + 1.0;
+    float k = (r * r) / 8.0;
+    return n_dot_x / (n_dot_x * (1.0 - k) + k);
+}
+
+// Smith's method, combining the masking and shadowing terms.
+float geometry_smith(float n_dot_v, float n_dot_l, float roughness) {
+    return geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness);
+}
+
+float3 fresnel_schlick(float cos_theta, float3 f0) {
+    return f0 + (1.0 - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+float3 fresnel_schlick_roughness(float cos_theta, float3 f0, float roughness) {
+    return f0 + (max(float3(1.0 - roughness), f0) - f0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Must be kept in sync with `direction_to_equirect` in `environment.rs`.
+float2 equirect_uv(float3 direction) {
+    float3 d = normalize(direction);
+    return float2(0.5 + atan2(d.x, -d.z) / (2.0 * PI), acos(clamp(d.y, -1.0, 1.0)) / PI);
+}
+
+// The irradiance arriving at a surface with the normal `n`, divided by pi.
+float3 environment_irradiance(constant EnvironmentUniforms& env, float3 n) {
+    float3 irradiance = env.irradiance_sh[0].rgb * 0.282095
+        + env.irradiance_sh[1].rgb * 0.488603 * n.y
+        + env.irradiance_sh[2].rgb * 0.488603 * n.z
+        + env.irradiance_sh[3].rgb * 0.488603 * n.x
+        + env.irradiance_sh[4].rgb * 1.092548 * n.x * n.y
+        + env.irradiance_sh[5].rgb * 1.092548 * n.y * n.z
+        + env.irradiance_sh[6].rgb * 0.315392 * (3.0 * n.z * n.z - 1.0)
+        + env.irradiance_sh[7].rgb * 1.092548 * n.x * n.z
+        + env.irradiance_sh[8].rgb * 0.546274 * (n.x * n.x - n.y * n.y);
+    return max(irradiance, float3(0.0));
+}
+
+// Analytical fit of the split-sum environment BRDF, from Karis' "Physically
+// Based Shading on Mobile".
+float2 environment_brdf(float roughness, float n_dot_v) {
+    const float4 c0 = float4(-1.0, -0.0275, -0.572, 0.022);
+    const float4 c1 = float4(1.0, 0.0425, 1.04, -0.04);
+    float4 r = roughness * c0 + c1;
+    float a004 = min(r.x * r.x, exp2(-9.28 * n_dot_v)) * r.x + r.y;
+    return float2(-1.04, 1.04) * a004 + r.zw;
+}
+
+// 3x3 percentage closer filtering of a single shadow map layer.
+float sample_shadow(depth2d_array<float> shadow_map,
+                    sampler shadow_map_,
+                    constant ShadowTransform* shadow_transforms,
+                    int layer,
+                    float3 world_position,
+                    float bias) {
+    float4 light_space = shadow_transforms[layer].shadow_matrix * float4(world_position, 1.0);
+    float3 coords = (light_space.xyz / light_space.w) * 0.5 + 0.5;
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+    // Metal's texture coordinates start at the top left.
+    coords.y = 1.0 - coords.y;
+
+    float2 texel_size = 1.0 / float2(shadow_map.get_width(), shadow_map.get_height());
+    float lit = 0.0;
+    for (int x = -1; x <= 1; x++) {
+        for (int y = -1; y <= 1; y++) {
+            float2 uv = coords.xy + float2(x, y) * texel_size;
+            lit += shadow_map.sample_compare(shadow_map_, uv, layer, coords.z - bias);
+        }
+    }
+    return lit / 9.0;
+}
+
+float shadow_factor(constant Light& light,
+                    constant FrameUniforms& frame_locals,
+                    constant ShadowTransform* shadow_transforms,
+                    depth2d_array<float> shadow_map,
+                    sampler shadow_map_,
+                    float3 world_position,
+                    float n_dot_l) {
+    int layer = light.shadow_index;
+    if (layer < 0) {
+        return 1.0;
+    }
+
+    float bias = max(0.002 * (1.0 - n_dot_l), 0.0005);
+
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        float view_depth = abs((frame_locals.view_matrix * float4(world_position, 1.0)).z);
+        int cascade = CASCADE_COUNT;
+        for (int i = CASCADE_COUNT - 1; i >= 0; i--) {
+            if (view_depth <= frame_locals.cascade_splits[i]) {
+                cascade = i;
+            }
+        }
+        if (cascade == CASCADE_COUNT) {
+            return 1.0;
+        }
+        layer += cascade;
+    } else {
+        // Must be kept in the same order as `point_light_transforms`.
+        float3 to_fragment = world_position - float3(light.position);
+        float3 extent = abs(to_fragment);
+        int face;
+        if (extent.x >= extent.y && extent.x >= extent.z) {
+            face = to_fragment.x > 0.0 ? 0 : 1;
+        } else if (extent.y >= extent.z) {
+            face = to_fragment.y > 0.0 ? 2 : 3;
+        } else {
+            face = to_fragment.z > 0.0 ? 4 : 5;
+        }
+        layer += face;
+    }
+
+    return sample_shadow(shadow_map, shadow_map_, shadow_transforms, layer, world_position, bias);
+}
+
+fragment FragmentOut frag(VertexOutput vertices                         [[stage_in]],
+                          constant FrameUniforms& frame_locals          [[buffer(1)]],
+                          constant MaterialUniforms& material_locals    [[buffer(2)]],
+                          constant Light* lights                        [[buffer(3)]],
+                          constant ShadowTransform* shadow_transforms   [[buffer(4)]],
+                          constant EnvironmentUniforms& environment     [[buffer(5)]],
+                          texture2d<float> color_texture                [[texture(0)]],
+                          sampler color_texture_                        [[sampler(0)]],
+                          texture2d<float> metallic_roughness_texture   [[texture(1)]],
+                          sampler metallic_roughness_texture_           [[sampler(1)]],
+                          texture2d<float> occlusion_texture            [[texture(2)]],
+                          sampler occlusion_texture_                    [[sampler(2)]],
+                          texture2d<float> emissive_texture             [[texture(3)]],
+                          sampler emissive_texture_                     [[sampler(3)]],
+                          depth2d_array<float> shadow_map               [[texture(4)]],
+                          sampler shadow_map_                           [[sampler(4)]],
+                          texture2d<float> specular_map                 [[texture(5)]],
+                          sampler specular_map_                         [[sampler(5)]]) {
 	FragmentOut out;
 
-	float4 frag_col = color_texture.sample(color_texture_, vertices.uv);
+    float4 base_color_sample = color_texture.sample(color_texture_, vertices.uv);
+    float3 base_color = srgb_to_linear(base_color_sample.rgb) * material_locals.base_color_factor.rgb;
+    float alpha = base_color_sample.a * material_locals.base_color_factor.a;
+
+    float4 metallic_roughness = metallic_roughness_texture.sample(metallic_roughness_texture_, vertices.uv);
+    float roughness = clamp(metallic_roughness.g * material_locals.roughness_factor, 0.04, 1.0);
+    float metallic = clamp(metallic_roughness.b * material_locals.metallic_factor, 0.0, 1.0);
+
+    float occlusion = mix(1.0,
+                          occlusion_texture.sample(occlusion_texture_, vertices.uv).r,
+                          material_locals.occlusion_strength);
+    float3 emissive = srgb_to_linear(emissive_texture.sample(emissive_texture_, vertices.uv).rgb) *
+                      float3(material_locals.emissive_factor);
+
+    float3 n = normalize(vertices.normal_camera);
+    float3 v = normalize(frame_locals.camera_position.xyz - vertices.frag_position_world);
+    float n_dot_v = max(dot(n, v), 0.0001);
+
+    float3 f0 = mix(DIELECTRIC_F0, base_color, metallic);
+    float3 diffuse_color = base_color * (1.0 - metallic);
+
+    float3 total_lighting = float3(0.0);
+
+    for (uint i = 0; i < min(frame_locals.num_lights, MAX_LIGHTS); i++) {
+        float3 l;
+        float3 radiance;
+        if (lights[i].kind == LIGHT_DIRECTIONAL) {
+            l = -normalize(float3(lights[i].direction));
+            radiance = lights[i].color.rgb * lights[i].power;
+        } else {
+            float3 to_light = float3(lights[i].position) - vertices.frag_position_world;
+            float distance_squared = max(dot(to_light, to_light), 0.0001);
+            l = normalize(to_light);
+            radiance = lights[i].color.rgb * lights[i].power / (4.0 * PI * distance_squared);
+        }
+
+        float3 h = normalize(v + l);
+        float n_dot_l = max(dot(n, l), 0.0);
+        float n_dot_h = max(dot(n, h), 0.0);
+        float h_dot_v = max(dot(h, v), 0.0);
+
+        float3 f = fresnel_schlick(h_dot_v, f0);
+        float d = distribution_ggx(n_dot_h, roughness);
+        float g = geometry_smith(n_dot_v, n_dot_l, roughness);
+
+        float3 specular = (d * g * f) / max(4.0 * n_dot_v * n_dot_l, 0.0001);
+        float3 diffuse = (float3(1.0) - f) * diffuse_color / PI;
+
+        float shadow = shadow_factor(lights[i], frame_locals, shadow_transforms, shadow_map,
+                                     shadow_map_, vertices.frag_position_world, n_dot_l);
+
+        total_lighting += (diffuse + specular) * radiance * n_dot_l * shadow;
+    }
+
+    float3 ambient_fresnel = fresnel_schlick_roughness(n_dot_v, f0, roughness);
+    float3 ambient_diffuse = environment_irradiance(environment, n) * diffuse_color *
+                             (float3(1.0) - ambient_fresnel);
+
+    float3 reflected = reflect(-v, n);
+    float3 prefiltered = specular_map.sample(specular_map_, equirect_uv(reflected),
+                                             level(roughness * environment.specular_max_lod)).rgb;
+    float2 brdf = environment_brdf(roughness, n_dot_v);
+    float3 ambient_specular = prefiltered * (ambient_fresnel * brdf.x + brdf.y);
+
+    float3 ambient = (ambient_diffuse + ambient_specular) * occlusion * environment.environment_intensity;
+    float3 color = ambient + total_lighting + emissive;
+
+    // Reinhard tone mapping, since the render target is not floating point.
+    color = color / (color + float3(1.0));
+
     // @FIXME: It appears that colors in Metal are bgra
-	out.main = frag_col.zyxw;
+	out.main = float4(linear_to_srgb(color), alpha).zyxw;
 
 	return out;
 }
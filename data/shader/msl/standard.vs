@@ -15,25 +15,32 @@ struct VertexOutput {
     float3 normal_camera;
 };
 
-struct VertexUniforms {
-    float4x4 mvp_transform;
-    float4x4 model_transform;
-    float4x4 view_transform;
+struct ModelUniforms {
+    float4x4 model_matrix;
+    float4x4 normal_matrix;
 };
 
-struct SharedUniforms {
+struct FrameUniforms {
+    float4x4 projection_matrix;
+    float4x4 view_matrix;
+    float4 camera_position;
+    float4 cascade_splits;
+    float time;
     uint num_lights;
 };
 
-vertex VertexOutput vert(VertexInput vertices      		      [[stage_in]],
-                         constant VertexUniforms& vert_locals [[buffer(0)]]
-                         //constant SharedUniforms& shared_locals [[buffer(1)]],
-                         ) {
+vertex VertexOutput vert(VertexInput vertices                     [[stage_in]],
+                         constant ModelUniforms& model_locals     [[buffer(0)]],
+                         constant FrameUniforms& frame_locals     [[buffer(1)]]) {
     VertexOutput out;
 
-    out.vertex_position = vert_locals.mvp_transform * float4(vertices.position, 1.0);
+    float4x4 mvp = frame_locals.projection_matrix * frame_locals.view_matrix * model_locals.model_matrix;
+    out.vertex_position = mvp * float4(vertices.position, 1.0);
     out.uv = vertices.tex_coord;
-    // out.normal_camera = float3x3(transpose(inverse(vertex_locals.model_transform))) * vertices.normal;
+    out.frag_position_world = (model_locals.model_matrix * float4(vertices.position, 1.0)).xyz;
+
+    float4x4 n = model_locals.normal_matrix;
+    out.normal_camera = float3x3(n[0].xyz, n[1].xyz, n[2].xyz) * vertices.normal;
 
     return out;
 }
@@ -0,0 +1,617 @@
+//! Settings which are kept between runs, in a TOML file.
+//!
+//! The file is looked for next to the assets folder first, so that a copy of
+//! the viewer can carry its own settings, and then in the platform's config
+//! directory. Missing settings take their default values, and a missing file
+//! is the same as an empty one. Only the settings which differ from their
+//! defaults are written back, so that changing a default in a later version
+//! reaches everyone who left that setting alone.
+
+use app_dirs::{self, AppDataType, AppDirsError, AppInfo};
+use input::action::Action;
+use input::bindings::Binding;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use toml;
+use util::get_assets_folder;
+
+const APP_INFO: AppInfo = AppInfo {
+    name: "gfx_tutorial",
+    author: "georgeburton",
+};
+
+const FILE_NAME: &str = "config.toml";
+
+/// The numbers of samples per pixel which multisampling can use.
+const MSAA_SAMPLES: &[u8] = &[1, 2, 4, 8, 16];
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub camera: CameraConfig,
+    pub graphics: GraphicsConfig,
+    pub gamepad: GamepadConfig,
+    pub gizmo: GizmoConfig,
+    pub key_bindings: KeyBindings,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    /// In units per second.
+    pub move_speed: f32,
+    /// How quickly the camera gets up to speed and how quickly it stops, as
+    /// rates per second. Larger values are quicker, and zero is immediate.
+    pub acceleration: f32,
+    pub damping: f32,
+    /// What the speed is multiplied by while sprinting or moving slowly.
+    pub sprint_multiplier: f32,
+    pub slow_multiplier: f32,
+    /// In degrees for each unit the mouse moves, which is usually a pixel.
+    /// This was once `mouse_speed`, a rate per second, and is named
+    /// differently so that old configs are not read with the new meaning.
+    pub mouse_degrees_per_pixel: f32,
+    /// How long mouse look takes to mostly catch up with the mouse, in
+    /// seconds. Zero turns as soon as the mouse moves.
+    pub mouse_smoothing: f32,
+    /// Looks down when the mouse or stick is pushed up.
+    pub invert_y: bool,
+    /// In degrees per second, with a stick pushed all the way.
+    pub turn_speed: f32,
+    /// How fast dragging turns the orbit camera around its target, in
+    /// degrees for each pixel the cursor moves.
+    pub orbit_speed: f32,
+    /// The vertical field of view, in degrees.
+    pub fov: f32,
+    /// The distances to the near and far clip planes.
+    pub near_plane: f32,
+    pub far_plane: f32,
+    /// How fast the walking camera walks and jumps, in units per second, and
+    /// how fast it falls, in units per second squared.
+    pub walk_speed: f32,
+    pub jump_speed: f32,
+    pub gravity: f32,
+    /// The size of the capsule which the walking camera collides with the
+    /// scene as, and the height of its eyes above its feet.
+    pub player_radius: f32,
+    pub player_height: f32,
+    pub eye_height: f32,
+}
+
+impl Default for CameraConfig {
+    #[inline]
+    fn default() -> Self {
+        CameraConfig {
+            move_speed: 7.0,
+            acceleration: 10.0,
+            damping: 8.0,
+            sprint_multiplier: 3.0,
+            slow_multiplier: 0.25,
+            mouse_degrees_per_pixel: 0.05,
+            mouse_smoothing: 0.0,
+            invert_y: false,
+            turn_speed: 120.0,
+            orbit_speed: 0.3,
+            fov: 45.0,
+            near_plane: 0.1,
+            far_plane: 100.0,
+            walk_speed: 4.0,
+            jump_speed: 5.0,
+            gravity: 9.81,
+            player_radius: 0.3,
+            player_height: 1.8,
+            eye_height: 1.6,
+        }
+    }
+}
+
+/// Images which surround the scene, looked for in the assets folder unless
+/// they are existing paths. Written as a single name, such as
+/// `"img/sky.hdr"`, for an equirectangular image, or a list of six names for
+/// the faces of a cube map, in the order +X, -X, +Y, -Y, +Z, -Z.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BackgroundImage {
+    Equirectangular(String),
+    CubeMap([String; 6]),
+}
+
+/// How the sticks and triggers of gamepads respond. Their buttons are bound to
+/// actions with the keys.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GamepadConfig {
+    pub enabled: bool,
+    /// How far a stick has to be pushed, from 0 to 1, before it does
+    /// anything, since sticks rarely rest exactly in the centre.
+    pub dead_zone: f32,
+    pub trigger_dead_zone: f32,
+    /// How far past the dead zone a stick or trigger is pushed is raised to
+    /// this power, so that small movements are finer. 1 is linear.
+    pub response_exponent: f32,
+}
+
+impl Default for GamepadConfig {
+    #[inline]
+    fn default() -> Self {
+        GamepadConfig {
+            enabled: true,
+            dead_zone: 0.15,
+            trigger_dead_zone: 0.05,
+            response_exponent: 2.0,
+        }
+    }
+}
+
+/// The handles for moving, turning and scaling the selected model.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GizmoConfig {
+    /// How long the handles are, as a fraction of the height of the screen.
+    pub size: f32,
+    /// What dragging a handle snaps to while snapping is held, in units for
+    /// moving, degrees for turning and times the mesh's size for scaling.
+    pub translate_step: f32,
+    pub rotate_step: f32,
+    pub scale_step: f32,
+}
+
+impl Default for GizmoConfig {
+    #[inline]
+    fn default() -> Self {
+        GizmoConfig {
+            size: 0.15,
+            translate_step: 0.5,
+            rotate_step: 15.0,
+            scale_step: 0.25,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GraphicsConfig {
+    /// One of the names accepted by `--backend`, or none for the default.
+    pub backend: Option<String>,
+    /// The last size of the window, or none to cover the primary monitor.
+    pub window_size: Option<(u32, u32)>,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    pub msaa: u8,
+    /// The most frames drawn a second, so that the viewer does not use a
+    /// whole core when vsync is off. Zero draws frames as fast as possible.
+    pub frame_rate_limit: f32,
+    /// How many times a second the camera and the animation are stepped,
+    /// however often frames are drawn.
+    pub update_rate: f64,
+    /// What to draw behind the scene and light it with, instead of the built
+    /// in sky.
+    pub background: Option<BackgroundImage>,
+    /// Replaces the sky with a flat colour when set. The scene is still lit
+    /// by the sky.
+    pub background_color: Option<[f32; 4]>,
+    pub show_fps: bool,
+    /// Keeps the bounding volume hierarchies built over meshes between runs,
+    /// which makes large meshes quicker to load.
+    pub cache_bvh: bool,
+}
+
+impl Default for GraphicsConfig {
+    #[inline]
+    fn default() -> Self {
+        GraphicsConfig {
+            backend: None,
+            window_size: None,
+            fullscreen: false,
+            vsync: true,
+            msaa: 1,
+            frame_rate_limit: 120.0,
+            update_rate: 120.0,
+            background: None,
+            background_color: None,
+            show_fps: true,
+            cache_bvh: true,
+        }
+    }
+}
+
+/// The inputs bound to each action, written as described in
+/// `input::bindings`. Each action can have several bindings.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub move_forwards: Vec<String>,
+    pub move_backwards: Vec<String>,
+    pub move_left: Vec<String>,
+    pub move_right: Vec<String>,
+    pub move_up: Vec<String>,
+    pub move_down: Vec<String>,
+    pub sprint: Vec<String>,
+    pub move_slowly: Vec<String>,
+    pub jump: Vec<String>,
+    pub orbit: Vec<String>,
+    pub pan: Vec<String>,
+    pub toggle_camera_mode: Vec<String>,
+    pub toggle_walking: Vec<String>,
+    pub select: Vec<String>,
+    pub frame_selected: Vec<String>,
+    pub gizmo_translate: Vec<String>,
+    pub gizmo_rotate: Vec<String>,
+    pub gizmo_scale: Vec<String>,
+    pub snap: Vec<String>,
+    pub toggle_projection: Vec<String>,
+    pub view_front: Vec<String>,
+    pub view_back: Vec<String>,
+    pub view_left: Vec<String>,
+    pub view_right: Vec<String>,
+    pub view_top: Vec<String>,
+    pub view_bottom: Vec<String>,
+    pub widen_fov: Vec<String>,
+    pub narrow_fov: Vec<String>,
+    pub push_near_plane: Vec<String>,
+    pub pull_near_plane: Vec<String>,
+    pub push_far_plane: Vec<String>,
+    pub pull_far_plane: Vec<String>,
+    pub add_keyframe: Vec<String>,
+    pub play_camera_path: Vec<String>,
+    pub rewind_camera_path: Vec<String>,
+    pub toggle_fps: Vec<String>,
+    pub screenshot: Vec<String>,
+    pub toggle_recording: Vec<String>,
+    pub quit: Vec<String>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        fn keys(names: &[&str]) -> Vec<String> {
+            names.iter().map(|&name| name.to_owned()).collect()
+        }
+
+        KeyBindings {
+            move_forwards: keys(&["W", "Up"]),
+            move_backwards: keys(&["S", "Down"]),
+            move_left: keys(&["A", "Left"]),
+            move_right: keys(&["D", "Right"]),
+            move_up: keys(&["E"]),
+            move_down: keys(&["Q"]),
+            sprint: keys(&["LShift", "GamepadLeftThumb"]),
+            move_slowly: keys(&["LAlt"]),
+            jump: keys(&["Space", "GamepadSouth"]),
+            orbit: keys(&["MouseLeft"]),
+            pan: keys(&["MouseMiddle", "Shift+MouseLeft"]),
+            toggle_camera_mode: keys(&["C"]),
+            toggle_walking: keys(&["V"]),
+            select: keys(&["MouseRight"]),
+            frame_selected: keys(&["F"]),
+            gizmo_translate: keys(&["Key1"]),
+            gizmo_rotate: keys(&["Key2"]),
+            gizmo_scale: keys(&["Key3"]),
+            snap: keys(&["Ctrl"]),
+            toggle_projection: keys(&["Numpad5", "O"]),
+            view_front: keys(&["Numpad1"]),
+            view_back: keys(&["Ctrl+Numpad1"]),
+            view_left: keys(&["Ctrl+Numpad3"]),
+            view_right: keys(&["Numpad3"]),
+            view_top: keys(&["Numpad7"]),
+            view_bottom: keys(&["Ctrl+Numpad7"]),
+            widen_fov: keys(&["RBracket"]),
+            narrow_fov: keys(&["LBracket"]),
+            push_near_plane: keys(&["Ctrl+RBracket"]),
+            pull_near_plane: keys(&["Ctrl+LBracket"]),
+            push_far_plane: keys(&["Shift+RBracket"]),
+            pull_far_plane: keys(&["Shift+LBracket"]),
+            add_keyframe: keys(&["K"]),
+            play_camera_path: keys(&["P"]),
+            rewind_camera_path: keys(&["Ctrl+P"]),
+            toggle_fps: keys(&["F3", "GamepadSelect"]),
+            screenshot: keys(&["F12", "GamepadNorth"]),
+            toggle_recording: keys(&["F11"]),
+            quit: keys(&["Escape"]),
+        }
+    }
+}
+
+impl Config {
+    /// Puts back the defaults of any settings which cannot be used, saying
+    /// which ones, rather than failing once the viewer tries to use them.
+    fn replace_invalid_settings(&mut self) {
+        if !MSAA_SAMPLES.contains(&self.graphics.msaa) {
+            let msaa = GraphicsConfig::default().msaa;
+            eprintln!(
+                "Using msaa = {} instead of {}, which is not one of {:?}",
+                msaa,
+                self.graphics.msaa,
+                MSAA_SAMPLES
+            );
+            self.graphics.msaa = msaa;
+        }
+        let update_rate = self.graphics.update_rate;
+        if !update_rate.is_finite() || update_rate <= 0.0 {
+            let default = GraphicsConfig::default().update_rate;
+            eprintln!(
+                "Using update_rate = {} instead of {}, which is not a positive number",
+                default,
+                update_rate
+            );
+            self.graphics.update_rate = default;
+        }
+        self.key_bindings.move_toggle_fps_off_jump();
+    }
+}
+
+impl KeyBindings {
+    /// Space used to toggle the frame counter, until it was given to jumping.
+    /// Config files written before then still bind it to both, so it is
+    /// moved back to the new default.
+    fn move_toggle_fps_off_jump(&mut self) {
+        let is_space = |text: &String| text.trim().eq_ignore_ascii_case("Space");
+        if !self.jump.iter().any(&is_space) || !self.toggle_fps.iter().any(&is_space) {
+            return;
+        }
+        eprintln!("Space is bound to jump, so toggle_fps is bound to F3 instead");
+        for text in &mut self.toggle_fps {
+            if is_space(text) {
+                *text = "F3".to_owned();
+            }
+        }
+    }
+
+    pub fn bindings(&self, action: Action) -> &[String] {
+        match action {
+            Action::MoveForwards => &self.move_forwards,
+            Action::MoveBackwards => &self.move_backwards,
+            Action::MoveLeft => &self.move_left,
+            Action::MoveRight => &self.move_right,
+            Action::MoveUp => &self.move_up,
+            Action::MoveDown => &self.move_down,
+            Action::Sprint => &self.sprint,
+            Action::MoveSlowly => &self.move_slowly,
+            Action::Jump => &self.jump,
+            Action::Orbit => &self.orbit,
+            Action::Pan => &self.pan,
+            Action::ToggleCameraMode => &self.toggle_camera_mode,
+            Action::ToggleWalking => &self.toggle_walking,
+            Action::Select => &self.select,
+            Action::FrameSelected => &self.frame_selected,
+            Action::GizmoTranslate => &self.gizmo_translate,
+            Action::GizmoRotate => &self.gizmo_rotate,
+            Action::GizmoScale => &self.gizmo_scale,
+            Action::Snap => &self.snap,
+            Action::ToggleProjection => &self.toggle_projection,
+            Action::ViewFront => &self.view_front,
+            Action::ViewBack => &self.view_back,
+            Action::ViewLeft => &self.view_left,
+            Action::ViewRight => &self.view_right,
+            Action::ViewTop => &self.view_top,
+            Action::ViewBottom => &self.view_bottom,
+            Action::WidenFov => &self.widen_fov,
+            Action::NarrowFov => &self.narrow_fov,
+            Action::PushNearPlane => &self.push_near_plane,
+            Action::PullNearPlane => &self.pull_near_plane,
+            Action::PushFarPlane => &self.push_far_plane,
+            Action::PullFarPlane => &self.pull_far_plane,
+            Action::AddKeyframe => &self.add_keyframe,
+            Action::PlayCameraPath => &self.play_camera_path,
+            Action::RewindCameraPath => &self.rewind_camera_path,
+            Action::ToggleFps => &self.toggle_fps,
+            Action::Screenshot => &self.screenshot,
+            Action::ToggleRecording => &self.toggle_recording,
+            Action::Quit => &self.quit,
+        }
+    }
+
+    /// Every binding which does not name inputs, so that typos can be
+    /// reported instead of silently leaving an action unbound.
+    pub fn invalid_bindings(&self) -> Vec<&str> {
+        Action::ALL
+            .iter()
+            .flat_map(|&action| self.bindings(action).iter())
+            .map(String::as_str)
+            .filter(|text| Binding::parse(text).is_none())
+            .collect()
+    }
+}
+
+/// A `Config` together with the file it was read from and is written back to.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConfigFile {
+    pub config: Config,
+    path: Option<PathBuf>,
+    saved: Config,
+}
+
+impl ConfigFile {
+    /// Reads the config file, or uses the defaults if there is none yet. A
+    /// file which cannot be read is reported and left alone, rather than being
+    /// overwritten with the defaults when the viewer exits.
+    pub fn load() -> Self {
+        let path = match find_path() {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("Not saving settings, could not find a config directory: {}", e);
+                return ConfigFile::unsaved(Default::default());
+            }
+        };
+
+        if !path.exists() {
+            return ConfigFile {
+                config: Default::default(),
+                saved: Default::default(),
+                path: Some(path),
+            };
+        }
+
+        match read(&path) {
+            Ok(mut config) => {
+                config.replace_invalid_settings();
+                ConfigFile {
+                    config: config.clone(),
+                    saved: config,
+                    path: Some(path),
+                }
+            }
+            Err(e) => {
+                eprintln!("Using the default settings, could not read {}: {}", path.display(), e);
+                ConfigFile::unsaved(Default::default())
+            }
+        }
+    }
+
+    /// A config which is never written out.
+    #[inline]
+    pub fn unsaved(config: Config) -> Self {
+        ConfigFile {
+            saved: config.clone(),
+            config,
+            path: None,
+        }
+    }
+
+    #[inline]
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref().map(PathBuf::as_path)
+    }
+
+    /// Writes the config back if it was changed since it was loaded or last
+    /// saved, leaving out the settings which have their default values.
+    /// Returns whether anything was written.
+    pub fn save_if_changed(&mut self) -> Result<bool, ConfigError> {
+        let path = match self.path {
+            Some(ref path) if self.config != self.saved => path,
+            _ => return Ok(false),
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = toml::to_string_pretty(&changed_settings(&self.config)?)?;
+        File::create(path)?.write_all(text.as_bytes())?;
+        self.saved = self.config.clone();
+        Ok(true)
+    }
+}
+
+/// The file next to the assets folder if there is one, and otherwise the one
+/// in the user's config directory, whether or not it exists yet.
+fn find_path() -> Result<PathBuf, AppDirsError> {
+    if let Ok(assets) = get_assets_folder() {
+        if let Some(parent) = assets.parent() {
+            let portable = parent.join(FILE_NAME);
+            if portable.is_file() {
+                return Ok(portable);
+            }
+        }
+    }
+    Ok(app_dirs::get_app_root(AppDataType::UserConfig, &APP_INFO)?.join(FILE_NAME))
+}
+
+/// The directory for files which are kept between runs to save time, and
+/// which can be deleted at any time.
+pub fn cache_dir() -> Result<PathBuf, AppDirsError> {
+    app_dirs::get_app_root(AppDataType::UserCache, &APP_INFO)
+}
+
+/// The settings which differ from their defaults.
+fn changed_settings(config: &Config) -> Result<toml::Value, toml::ser::Error> {
+    let settings = toml::Value::try_from(config)?;
+    let defaults = toml::Value::try_from(Config::default())?;
+    Ok(without_defaults(settings, &defaults)
+        .unwrap_or_else(|| toml::Value::Table(Default::default())))
+}
+
+/// `value` without anything which is the same in `defaults`, or `None` if it
+/// is all the same. Tables are compared key by key, and anything else as a
+/// whole, so a list of key bindings is written out in full if any of it
+/// changed.
+fn without_defaults(value: toml::Value, defaults: &toml::Value) -> Option<toml::Value> {
+    match (value, defaults) {
+        (toml::Value::Table(table), &toml::Value::Table(ref defaults)) => {
+            let changed = table
+                .into_iter()
+                .filter_map(|(key, value)| match defaults.get(&key) {
+                    Some(default) => without_defaults(value, default).map(|value| (key, value)),
+                    None => Some((key, value)),
+                })
+                .collect::<toml::value::Table>();
+            if changed.is_empty() {
+                None
+            } else {
+                Some(toml::Value::Table(changed))
+            }
+        }
+        (value, default) => if value == *default {
+            None
+        } else {
+            Some(value)
+        },
+    }
+}
+
+fn read(path: &Path) -> Result<Config, ConfigError> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    Ok(toml::from_str(&text)?)
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Parse(toml::de::Error),
+    Serialize(toml::ser::Error),
+}
+
+impl From<io::Error> for ConfigError {
+    #[inline]
+    fn from(e: io::Error) -> Self {
+        ConfigError::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for ConfigError {
+    #[inline]
+    fn from(e: toml::de::Error) -> Self {
+        ConfigError::Parse(e)
+    }
+}
+
+impl From<toml::ser::Error> for ConfigError {
+    #[inline]
+    fn from(e: toml::ser::Error) -> Self {
+        ConfigError::Serialize(e)
+    }
+}
+
+impl fmt::Display for ConfigError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            ConfigError::Io(ref e) => write!(fmtr, "{}: {}", desc, e),
+            ConfigError::Parse(ref e) => write!(fmtr, "{}: {}", desc, e),
+            ConfigError::Serialize(ref e) => write!(fmtr, "{}: {}", desc, e),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            ConfigError::Io(_) => "An io error occurred while accessing the config file",
+            ConfigError::Parse(_) => "The config file is not valid",
+            ConfigError::Serialize(_) => "The config could not be written as TOML",
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ConfigError::Io(ref e) => Some(e),
+            ConfigError::Parse(ref e) => Some(e),
+            ConfigError::Serialize(ref e) => Some(e),
+        }
+    }
+}
@@ -1,23 +1,48 @@
 use ::WindowExt;
 use ang::{Angle, Degrees};
-use graphics::camera::{Camera, CameraMatrices};
-use na::{self, Isometry3, Point3, Perspective3, Vector3};
+use graphics::camera::{Camera, CameraMatrices, Projection};
+use input::action::{Action, AnalogueAction};
+use na::{self, Isometry3, Point3, Vector2, Vector3};
 use num::Zero;
 use std::ops::Neg;
 
+/// How far away things are shown at the same size as in perspective, when
+/// the projection is orthographic.
+pub const ORTHOGRAPHIC_FOCUS_DISTANCE: f32 = 10.0;
+/// How much faster each line of scrolling makes the camera move, and how far
+/// scrolling can change its speed either way.
+const SPEED_PER_LINE: f32 = 1.1;
+const MAX_SPEED_SCALE: f32 = 16.0;
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct CameraController {
     pub input: PlayerInput,
     position: Point3<f32>,
     horizontal_angle: Angle<f32>,
     vertical_angle: Angle<f32>,
-    fov: Angle<f32>,
-    perspective: Perspective3<f32>,
+    pub projection: Projection,
     mouse_speed: f32,
     move_speed: f32,
+    /// Scales `move_speed`, and is changed by scrolling.
+    speed_scale: f32,
+    velocity: Vector3<f32>,
+    /// How quickly the camera reaches the speed it is pushed towards, and how
+    /// quickly it stops when let go, as the fraction of the remaining
+    /// difference which is made up each second on a logarithmic scale.
+    acceleration: f32,
+    damping: f32,
+    sprint_multiplier: f32,
+    slow_multiplier: f32,
+    turn_speed: f32,
+    /// How long mouse movement takes to mostly catch up, in seconds, or zero
+    /// to turn as soon as the mouse moves.
+    mouse_smoothing: f32,
+    /// How far the mouse has moved which has not yet turned the camera.
+    pending_look: Vector2<f32>,
+    invert_y: bool,
 }
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct PlayerInput {
     pub moving_forwards: bool,
     pub moving_backwards: bool,
@@ -25,6 +50,21 @@ pub struct PlayerInput {
     pub moving_right: bool,
     pub moving_up: bool,
     pub moving_down: bool,
+    pub sprinting: bool,
+    pub moving_slowly: bool,
+    pub jumping: bool,
+    pub analogue: AnalogueInput,
+}
+
+/// How far each analogue action is pushed, from -1 to 1. These add to the
+/// digital inputs, so a key and a stick can be used together.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AnalogueInput {
+    pub move_right: f32,
+    pub move_forwards: f32,
+    pub move_up: f32,
+    pub turn_right: f32,
+    pub turn_down: f32,
 }
 
 impl CameraController {
@@ -33,22 +73,95 @@ impl CameraController {
         mouse_speed: f32,
         move_speed: f32,
     ) -> Self {
-        let fov = Angle::eighth();
         CameraController {
             input: Default::default(),
             position: Point3::new(0.0, 0.0, 10.0),
             horizontal_angle: Angle::zero(),
             vertical_angle: Angle::zero(),
-            fov,
-            perspective: Perspective3::new(window.aspect(), fov.in_radians(), 0.1, 100.0),
+            projection: Projection::new(window.aspect(), Angle::eighth(), 0.1, 100.0),
             mouse_speed,
             move_speed,
+            speed_scale: 1.0,
+            velocity: Vector3::zeros(),
+            acceleration: 0.0,
+            damping: 0.0,
+            sprint_multiplier: 1.0,
+            slow_multiplier: 1.0,
+            turn_speed: 0.0,
+            mouse_smoothing: 0.0,
+            pending_look: Vector2::zeros(),
+            invert_y: false,
+        }
+    }
+
+    /// How fast analogue input turns the camera, in degrees per second.
+    #[inline]
+    pub fn with_turn_speed(self, turn_speed: f32) -> Self {
+        CameraController {
+            turn_speed,
+            ..self
+        }
+    }
+
+    /// How quickly the camera speeds up and slows down, where larger values
+    /// are quicker and zero moves at full speed as soon as it is pushed.
+    #[inline]
+    pub fn with_acceleration(self, acceleration: f32, damping: f32) -> Self {
+        CameraController {
+            acceleration,
+            damping,
+            ..self
+        }
+    }
+
+    /// What the speed is multiplied by while sprinting or moving slowly.
+    #[inline]
+    pub fn with_speed_multipliers(self, sprint_multiplier: f32, slow_multiplier: f32) -> Self {
+        CameraController {
+            sprint_multiplier,
+            slow_multiplier,
+            ..self
+        }
+    }
+
+    #[inline]
+    pub fn with_mouse_smoothing(self, mouse_smoothing: f32) -> Self {
+        CameraController {
+            mouse_smoothing,
+            ..self
+        }
+    }
+
+    /// Makes moving the mouse or stick up look down, like a plane's controls.
+    #[inline]
+    pub fn with_invert_y(self, invert_y: bool) -> Self {
+        CameraController {
+            invert_y,
+            ..self
+        }
+    }
+
+    #[inline]
+    pub fn with_projection(self, projection: Projection) -> Self {
+        CameraController {
+            projection,
+            ..self
         }
     }
 
     #[inline]
     pub fn on_resize<W: WindowExt>(&mut self, window: &W) {
-        self.perspective.set_aspect(window.aspect());
+        self.projection.set_aspect(window.aspect());
+    }
+
+    #[inline]
+    pub fn position(&self) -> Point3<f32> {
+        self.position
+    }
+
+    #[inline]
+    pub fn set_position(&mut self, position: Point3<f32>) {
+        self.position = position;
     }
 
     #[inline]
@@ -74,12 +187,51 @@ impl CameraController {
         self.right().cross(&self.direction())
     }
 
-    pub fn on_cursor_moved(&mut self, dt_s: f32, (x, y): (f64, f64), (win_w, win_h): (i32, i32), hidpi: f32) {
-        let delta_h = Degrees(self.mouse_speed * dt_s * ((win_w / 2) as f32 - (x as f32 / hidpi)));
-        let delta_v = Degrees(self.mouse_speed * dt_s * ((win_h / 2) as f32 - (y as f32 / hidpi)));
+    /// Turns the camera by how far the mouse moved to the right and
+    /// downwards, however long the frame took. With smoothing, the turn is
+    /// spread over the next few frames by `smooth_look`.
+    pub fn look(&mut self, x: f32, y: f32) {
+        if self.mouse_smoothing > 0.0 {
+            self.pending_look += Vector2::new(x, y);
+        } else {
+            let speed = self.mouse_speed;
+            self.turn(Degrees(speed * x), Degrees(speed * y));
+        }
+    }
+
+    /// Turns by part of the mouse movement which is still to be applied,
+    /// which is called once per frame. The part depends on how long the frame
+    /// took, so that the camera catches up at the same rate however fast the
+    /// frames are.
+    pub fn smooth_look(&mut self, dt_s: f32) {
+        if self.pending_look == Vector2::zeros() {
+            return;
+        }
+        let fraction = if self.mouse_smoothing > 0.0 {
+            1.0 - (-dt_s / self.mouse_smoothing).exp()
+        } else {
+            1.0
+        };
+        let look = self.pending_look * fraction;
+        self.pending_look -= look;
+        let speed = self.mouse_speed;
+        self.turn(Degrees(speed * look.x), Degrees(speed * look.y));
+    }
 
-        self.horizontal_angle += delta_h;
-        self.vertical_angle -= delta_v;
+    /// Looks along `-direction`, where `horizontal` turns it around the y axis
+    /// from the z axis and `vertical` raises it towards the y axis.
+    pub fn set_orientation(&mut self, horizontal: Angle<f32>, vertical: Angle<f32>) {
+        self.horizontal_angle = horizontal.normalized();
+        self.vertical_angle = vertical;
+    }
+
+    fn turn(&mut self, right: Angle<f32>, down: Angle<f32>) {
+        self.horizontal_angle -= right;
+        if self.invert_y {
+            self.vertical_angle -= down;
+        } else {
+            self.vertical_angle += down;
+        }
 
         self.horizontal_angle = self.horizontal_angle.normalized();
 
@@ -94,44 +246,137 @@ impl CameraController {
         }
     }
 
+    /// Starts or stops moving. Returns whether the action is one which moves
+    /// the camera.
+    pub fn on_action(&mut self, action: Action, is_pressed: bool) -> bool {
+        let input = &mut self.input;
+        match action {
+            Action::MoveForwards => input.moving_forwards = is_pressed,
+            Action::MoveBackwards => input.moving_backwards = is_pressed,
+            Action::MoveLeft => input.moving_left = is_pressed,
+            Action::MoveRight => input.moving_right = is_pressed,
+            Action::MoveUp => input.moving_up = is_pressed,
+            Action::MoveDown => input.moving_down = is_pressed,
+            Action::Sprint => input.sprinting = is_pressed,
+            Action::MoveSlowly => input.moving_slowly = is_pressed,
+            Action::Jump => input.jumping = is_pressed,
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn on_analogue(&mut self, action: AnalogueAction, value: f32) {
+        let analogue = &mut self.input.analogue;
+        match action {
+            AnalogueAction::MoveRight => analogue.move_right = value,
+            AnalogueAction::MoveForwards => analogue.move_forwards = value,
+            AnalogueAction::MoveUp => analogue.move_up = value,
+            AnalogueAction::TurnRight => analogue.turn_right = value,
+            AnalogueAction::TurnDown => analogue.turn_down = value,
+        }
+    }
+
     pub fn apply_input(&mut self, dt_s: f32) {
-        let direction = self.direction();
-        let right = self.right();
-        let up =Vector3::new(0.0, 1.0, 0.0);
-        if self.input.moving_forwards {
-            self.position -= direction * self.move_speed * dt_s;
+        fn axis(positive: bool, negative: bool, analogue: f32) -> f32 {
+            let digital = positive as i32 - negative as i32;
+            (digital as f32 + analogue).max(-1.0).min(1.0)
         }
-        if self.input.moving_backwards {
-            self.position += direction * self.move_speed * dt_s;
+
+        /// The fraction of the way to a target which is covered in `dt_s`
+        /// seconds at `rate`, or all of it if the rate is zero.
+        fn approach(rate: f32, dt_s: f32) -> f32 {
+            if rate > 0.0 {
+                1.0 - (-rate * dt_s).exp()
+            } else {
+                1.0
+            }
         }
-        if self.input.moving_left {
-            self.position += right * self.move_speed * dt_s;
+
+        let input = self.input.clone();
+        let analogue = input.analogue;
+        let mut push = Vector3::new(
+            axis(input.moving_right, input.moving_left, analogue.move_right),
+            axis(input.moving_up, input.moving_down, analogue.move_up),
+            axis(input.moving_forwards, input.moving_backwards, analogue.move_forwards),
+        );
+        // Moving diagonally is no faster than moving straight, but a stick
+        // pushed part of the way still moves slowly.
+        let length = push.norm();
+        if length > 1.0 {
+            push /= length;
         }
-        if self.input.moving_right {
-            self.position -= right * self.move_speed * dt_s;
+
+        let mut speed = self.move_speed * self.speed_scale;
+        if input.sprinting {
+            speed *= self.sprint_multiplier;
         }
-        if self.input.moving_up {
-            self.position += up * self.move_speed * dt_s;
+        if input.moving_slowly {
+            speed *= self.slow_multiplier;
         }
-        if self.input.moving_down {
-            self.position -= up * self.move_speed * dt_s;
+        let target_velocity = (self.right() * -push.x + Vector3::y() * push.y
+            + self.direction() * -push.z) * speed;
+        let rate = if push == Vector3::zeros() {
+            self.damping
+        } else {
+            self.acceleration
+        };
+        self.velocity += (target_velocity - self.velocity) * approach(rate, dt_s);
+        self.position += self.velocity * dt_s;
+
+        self.apply_turn(dt_s);
+    }
+
+    /// Turns by how far the analogue turning actions are pushed, for `dt_s`
+    /// seconds.
+    pub fn apply_turn(&mut self, dt_s: f32) {
+        let turn = self.turn_speed * dt_s;
+        let analogue = self.input.analogue;
+        let (turn_right, turn_down) = (analogue.turn_right, analogue.turn_down);
+        if turn_right != 0.0 || turn_down != 0.0 {
+            self.turn(Degrees(turn * turn_right), Degrees(turn * turn_down));
+        }
+    }
+
+    /// Turns the world around so that the camera is at the origin, looking
+    /// down the -z axis.
+    pub fn view(&self) -> Isometry3<f32> {
+        let direction = self.direction();
+        let up = self.up();
+
+        Isometry3::look_at_lh(
+            &self.position,
+            &Point3::from_coordinates(self.position.coords + direction),
+            &up,
+        )
+    }
+
+    /// Changes how fast the camera moves, faster for positive `lines` and
+    /// slower for negative ones.
+    pub fn scroll(&mut self, lines: f32) {
+        self.speed_scale = (self.speed_scale * SPEED_PER_LINE.powf(lines))
+            .max(1.0 / MAX_SPEED_SCALE)
+            .min(MAX_SPEED_SCALE);
+    }
+
+    /// Stops moving at once, rather than slowing down.
+    #[inline]
+    pub fn stop(&mut self) {
+        self.velocity = Vector3::zeros();
+    }
+
+    /// The camera part of the way from `previous` to this one. Only the
+    /// position is blended, since looking around happens as the cursor moves
+    /// rather than in steps.
+    pub fn interpolated(&self, previous: &CameraController, alpha: f32) -> CameraController {
+        CameraController {
+            position: previous.position + (self.position - previous.position) * alpha,
+            ..self.clone()
         }
     }
 }
 
 impl Camera for CameraController {
     fn matrices(&self) -> CameraMatrices {
-        let view = {
-            let direction = self.direction();
-            let up = self.up();
-
-            Isometry3::look_at_lh(
-                &self.position,
-                &Point3::from_coordinates(self.position.coords + direction),
-                &up,
-            )
-        };
-
-        CameraMatrices::new(view.to_homogeneous(), self.perspective.to_homogeneous())
+        self.projection.matrices(self.view().to_homogeneous(), ORTHOGRAPHIC_FOCUS_DISTANCE)
     }
 }
@@ -0,0 +1,470 @@
+//! Keyframed camera paths, for fly-throughs which look the same every time
+//! they are played.
+//!
+//! Paths are saved as plain text, one keyframe per line, so that they can be
+//! retimed by hand:
+//!
+//! ```text
+//! gfx_tutorial camera path 1
+//! key 0 0 0 10 0 0 0 1 45
+//! key 2 5 1 8 0 0.2588 0 0.9659 45
+//! ```
+//!
+//! Each `key` line gives the time in seconds, the position, the orientation
+//! as a quaternion `i j k w`, and the vertical field of view in degrees.
+
+use ::WindowExt;
+use ang::Degrees;
+use controllers::camera_controller::ORTHOGRAPHIC_FOCUS_DISTANCE;
+use graphics::camera::{Camera, CameraMatrices, Projection};
+use na::{Isometry3, Point3, Quaternion, Translation3, UnitQuaternion, Vector3};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::{FromStr, SplitWhitespace};
+
+const HEADER: &str = "gfx_tutorial camera path 1";
+
+/// How long after the last keyframe a new one is added.
+pub const KEYFRAME_SPACING: f32 = 2.0;
+
+/// How much faster each line of scrolling plays a path, and how far either way
+/// the speed can be changed.
+const SPEED_PER_LINE: f32 = 1.1;
+const MAX_SPEED: f32 = 16.0;
+
+/// Where a camera is, which way it looks, and how wide, at one time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Keyframe {
+    /// Seconds from the start of the path.
+    pub time: f32,
+    pub position: Point3<f32>,
+    /// Turns the camera, which looks down its -z axis with y up, to face the
+    /// way it looks in the world.
+    pub orientation: UnitQuaternion<f32>,
+    /// The vertical field of view, in degrees.
+    pub fov: f32,
+}
+
+impl Keyframe {
+    /// The keyframe of a camera with the view transform `view`.
+    pub fn from_view(time: f32, view: &Isometry3<f32>, fov: f32) -> Self {
+        let camera_to_world = view.inverse();
+        Keyframe {
+            time,
+            position: Point3::from_coordinates(camera_to_world.translation.vector),
+            orientation: camera_to_world.rotation,
+            fov,
+        }
+    }
+
+    pub fn view(&self) -> Isometry3<f32> {
+        let translation = Translation3::from_vector(self.position.coords);
+        Isometry3::from_parts(translation, self.orientation).inverse()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CameraPath {
+    /// In order of time.
+    keyframes: Vec<Keyframe>,
+}
+
+impl CameraPath {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CameraPathError> {
+        let file = BufReader::new(File::open(path)?);
+        let mut lines = file.lines();
+        match lines.next() {
+            Some(line) => if line?.trim() != HEADER {
+                return Err(CameraPathError::NotACameraPath);
+            },
+            None => return Err(CameraPathError::NotACameraPath),
+        }
+
+        let mut camera_path = CameraPath::default();
+        for (i, line) in lines.enumerate() {
+            let line = line?;
+            let mut words = line.split_whitespace();
+            match words.next() {
+                None => continue,
+                Some("key") => match parse_keyframe(&mut words) {
+                    Some(keyframe) => camera_path.insert(keyframe),
+                    // The header is the first line.
+                    None => return Err(CameraPathError::Parse(i + 2, line.clone())),
+                },
+                Some(_) => return Err(CameraPathError::Parse(i + 2, line.clone())),
+            }
+        }
+        Ok(camera_path)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CameraPathError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "{}", HEADER)?;
+        for keyframe in &self.keyframes {
+            let position = keyframe.position;
+            let q = keyframe.orientation.as_ref().coords;
+            writeln!(
+                file,
+                "key {} {} {} {} {} {} {} {} {}",
+                keyframe.time,
+                position.x,
+                position.y,
+                position.z,
+                q.x,
+                q.y,
+                q.z,
+                q.w,
+                keyframe.fov
+            )?;
+        }
+        file.flush()?;
+        Ok(())
+    }
+
+    #[inline]
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.keyframes.is_empty()
+    }
+
+    /// The time of the last keyframe.
+    #[inline]
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |keyframe| keyframe.time)
+    }
+
+    /// Adds a keyframe, keeping them in order of time.
+    pub fn insert(&mut self, keyframe: Keyframe) {
+        let index = self.keyframes
+            .iter()
+            .position(|other| other.time > keyframe.time)
+            .unwrap_or(self.keyframes.len());
+        self.keyframes.insert(index, keyframe);
+    }
+
+    /// Where the camera is at `time`, or `None` if there are no keyframes.
+    /// The position follows a Catmull-Rom spline through the keyframes, so
+    /// the camera does not turn sharply at them. The orientation and field of
+    /// view are blended between each pair of keyframes.
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let keyframes = &self.keyframes;
+        let first = *keyframes.first()?;
+        let last = *keyframes.last()?;
+        if time <= first.time {
+            return Some(Keyframe { time, ..first });
+        }
+        if time >= last.time {
+            return Some(Keyframe { time, ..last });
+        }
+
+        let end = keyframes.iter().position(|keyframe| keyframe.time > time)?;
+        let start = end - 1;
+        let (k1, k2) = (keyframes[start], keyframes[end]);
+        let duration = k2.time - k1.time;
+        let t = (time - k1.time) / duration;
+
+        // The tangents are scaled by the length of the segment, since the
+        // keyframes need not be evenly spaced in time.
+        let tangent = |i: usize| -> Vector3<f32> {
+            let before = keyframes[i.saturating_sub(1)];
+            let after = keyframes[(i + 1).min(keyframes.len() - 1)];
+            let dt = after.time - before.time;
+            if dt > 0.0 {
+                (after.position - before.position) / dt * duration
+            } else {
+                Vector3::zeros()
+            }
+        };
+        let (m1, m2) = (tangent(start), tangent(end));
+
+        let (t2, t3) = (t * t, t * t * t);
+        let h00 = 2.0 * t3 - 3.0 * t2 + 1.0;
+        let h10 = t3 - 2.0 * t2 + t;
+        let h01 = -2.0 * t3 + 3.0 * t2;
+        let h11 = t3 - t2;
+        let position = Point3::from_coordinates(
+            k1.position.coords * h00 + m1 * h10 + k2.position.coords * h01 + m2 * h11,
+        );
+
+        // Keyframes which look in opposite directions have no shortest
+        // rotation between them, so the camera turns at the next keyframe.
+        let orientation = k1.orientation
+            .try_slerp(&k2.orientation, t, 1.0e-6)
+            .unwrap_or(k1.orientation);
+
+        Some(Keyframe {
+            time,
+            position,
+            orientation,
+            fov: k1.fov + (k2.fov - k1.fov) * t,
+        })
+    }
+}
+
+fn parse<T: FromStr>(words: &mut SplitWhitespace) -> Option<T> {
+    words.next().and_then(|word| word.parse().ok())
+}
+
+fn parse_keyframe(words: &mut SplitWhitespace) -> Option<Keyframe> {
+    let time = parse(words)?;
+    let position = Point3::new(parse(words)?, parse(words)?, parse(words)?);
+    let (i, j, k, w) = (parse(words)?, parse(words)?, parse(words)?, parse(words)?);
+    let fov = parse(words)?;
+    if words.next().is_some() {
+        return None;
+    }
+    Some(Keyframe {
+        time,
+        position,
+        orientation: UnitQuaternion::from_quaternion(Quaternion::new(w, i, j, k)),
+        fov,
+    })
+}
+
+/// Plays a camera path, and can be paused, sped up and moved to any time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraPathPlayer {
+    pub path: CameraPath,
+    pub projection: Projection,
+    time: f32,
+    speed: f32,
+    is_playing: bool,
+}
+
+impl CameraPathPlayer {
+    pub fn new(path: CameraPath, projection: Projection) -> Self {
+        CameraPathPlayer {
+            path,
+            projection,
+            time: 0.0,
+            speed: 1.0,
+            is_playing: false,
+        }
+    }
+
+    #[inline]
+    pub fn on_resize<W: WindowExt>(&mut self, window: &W) {
+        self.projection.set_aspect(window.aspect());
+    }
+
+    #[inline]
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    #[inline]
+    pub fn is_playing(&self) -> bool {
+        self.is_playing
+    }
+
+    #[inline]
+    pub fn is_finished(&self) -> bool {
+        self.time >= self.path.duration()
+    }
+
+    /// Plays from where the path was paused, or from the start if it had
+    /// finished.
+    pub fn play(&mut self) {
+        if self.is_finished() {
+            self.time = 0.0;
+        }
+        self.is_playing = true;
+    }
+
+    #[inline]
+    pub fn pause(&mut self) {
+        self.is_playing = false;
+    }
+
+    /// Moves to `time` seconds from the start, without starting or stopping.
+    #[inline]
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.max(0.0).min(self.path.duration());
+    }
+
+    /// Plays faster for positive `lines`, and slower for negative ones.
+    pub fn scroll(&mut self, lines: f32) {
+        self.speed = (self.speed * SPEED_PER_LINE.powf(lines))
+            .max(1.0 / MAX_SPEED)
+            .min(MAX_SPEED);
+    }
+
+    /// Moves along the path by `dt_s` seconds, at the playback speed, and
+    /// stops at the end.
+    pub fn update(&mut self, dt_s: f32) {
+        if !self.is_playing {
+            return;
+        }
+        let time = self.time + dt_s * self.speed;
+        self.seek(time);
+        if self.is_finished() {
+            self.is_playing = false;
+        }
+    }
+
+    pub fn interpolated(&self, previous: &CameraPathPlayer, alpha: f32) -> CameraPathPlayer {
+        CameraPathPlayer {
+            time: previous.time + (self.time - previous.time) * alpha,
+            ..self.clone()
+        }
+    }
+}
+
+impl Camera for CameraPathPlayer {
+    fn matrices(&self) -> CameraMatrices {
+        let mut projection = self.projection;
+        let view = match self.path.sample(self.time) {
+            Some(keyframe) => {
+                projection.set_fov(Degrees(keyframe.fov));
+                keyframe.view()
+            }
+            None => Isometry3::identity(),
+        };
+        projection.matrices(view.to_homogeneous(), ORTHOGRAPHIC_FOCUS_DISTANCE)
+    }
+}
+
+#[derive(Debug)]
+pub enum CameraPathError {
+    Io(io::Error),
+    NotACameraPath,
+    /// A line which is not valid, with its line number.
+    Parse(usize, String),
+}
+
+impl From<io::Error> for CameraPathError {
+    #[inline]
+    fn from(e: io::Error) -> Self {
+        CameraPathError::Io(e)
+    }
+}
+
+impl fmt::Display for CameraPathError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            CameraPathError::Io(ref e) => write!(fmtr, "{}: {}", desc, e),
+            CameraPathError::Parse(number, ref line) => {
+                write!(fmtr, "{} on line {}: {}", desc, number, line)
+            }
+            CameraPathError::NotACameraPath => write!(fmtr, "{}", desc),
+        }
+    }
+}
+
+impl Error for CameraPathError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            CameraPathError::Io(_) => "An io error occurred while accessing the camera path",
+            CameraPathError::NotACameraPath => "The file is not a camera path",
+            CameraPathError::Parse(..) => "The camera path contains a line which is not valid",
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            CameraPathError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn keyframe(time: f32, x: f32) -> Keyframe {
+        Keyframe {
+            time,
+            position: Point3::new(x, 0.0, 0.0),
+            orientation: UnitQuaternion::from_euler_angles(0.0, x, 0.0),
+            fov: 45.0 + x,
+        }
+    }
+
+    fn path(keyframes: &[Keyframe]) -> CameraPath {
+        let mut path = CameraPath::default();
+        for &keyframe in keyframes {
+            path.insert(keyframe);
+        }
+        path
+    }
+
+    #[test]
+    fn sample_passes_through_keyframes() {
+        let keyframes = [keyframe(0.0, 0.0), keyframe(1.0, 1.0), keyframe(3.0, -2.0)];
+        let path = path(&keyframes);
+        for keyframe in &keyframes {
+            let sample = path.sample(keyframe.time).unwrap();
+            assert!((sample.position - keyframe.position).norm() < 1.0e-5);
+            assert!((sample.fov - keyframe.fov).abs() < 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn sample_holds_before_and_after_the_path() {
+        let path = path(&[keyframe(1.0, 1.0), keyframe(2.0, 2.0)]);
+        assert_eq!(path.sample(0.0).unwrap().position, Point3::new(1.0, 0.0, 0.0));
+        assert_eq!(path.sample(5.0).unwrap().position, Point3::new(2.0, 0.0, 0.0));
+        assert!(CameraPath::default().sample(0.0).is_none());
+    }
+
+    #[test]
+    fn evenly_spaced_keyframes_in_a_line_move_evenly() {
+        let path = path(&[keyframe(0.0, 0.0), keyframe(1.0, 1.0), keyframe(2.0, 2.0)]);
+        let sample = path.sample(1.5).unwrap();
+        assert!((sample.position.x - 1.5).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn insert_keeps_keyframes_in_order() {
+        let path = path(&[keyframe(2.0, 0.0), keyframe(0.0, 1.0), keyframe(1.0, 2.0)]);
+        let times = path.keyframes().iter().map(|k| k.time).collect::<Vec<_>>();
+        assert_eq!(times, vec![0.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn keyframe_view_round_trips() {
+        let view = Isometry3::look_at_rh(
+            &Point3::new(1.0, 2.0, 3.0),
+            &Point3::new(0.0, 0.0, 0.0),
+            &Vector3::y(),
+        );
+        let keyframe = Keyframe::from_view(0.0, &view, 45.0);
+        assert_eq!(keyframe.position, Point3::new(1.0, 2.0, 3.0));
+        let difference = keyframe.view().to_homogeneous() - view.to_homogeneous();
+        assert!(difference.iter().all(|x| x.abs() < 1.0e-5));
+    }
+
+    #[test]
+    fn save_and_load_round_trip() {
+        let path = path(&[keyframe(0.0, 0.0), keyframe(2.0, 1.5)]);
+        // Another run of the tests may be using the temporary directory at the
+        // same time.
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let name = format!("save_and_load_round_trip_{}.txt", time.subsec_nanos());
+        let file = env::temp_dir().join(name);
+        path.save(&file).unwrap();
+        let loaded = CameraPath::load(&file).unwrap();
+        assert_eq!(loaded.keyframes().len(), 2);
+        for (a, b) in loaded.keyframes().iter().zip(path.keyframes()) {
+            assert!((a.position - b.position).norm() < 1.0e-5);
+            let (qa, qb) = (a.orientation.as_ref().coords, b.orientation.as_ref().coords);
+            assert!((qa - qb).norm() < 1.0e-4);
+        }
+        fs::remove_file(&file).unwrap();
+    }
+}
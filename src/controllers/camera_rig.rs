@@ -0,0 +1,311 @@
+use ::WindowExt;
+use ang::{Angle, Degrees};
+use config::CameraConfig;
+use controllers::camera_controller::CameraController;
+use controllers::camera_path::{CameraPath, CameraPathPlayer, Keyframe, KEYFRAME_SPACING};
+use controllers::orbit_controller::OrbitController;
+use controllers::player_controller::PlayerController;
+use graphics::bounds::Aabb;
+use graphics::camera::{Camera, CameraMatrices, Projection, ProjectionMode};
+use input::action::{Action, AnalogueAction};
+use physics::collision::CollisionMesh;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum CameraMode {
+    /// Flies around like in a first person game.
+    Free,
+    /// Turns around a target, for inspecting models.
+    Orbit,
+    /// Follows a camera path.
+    Path,
+    /// Walks around the scene, colliding with the models.
+    Walk,
+}
+
+/// The standard views of a model, each named for the side it is seen from.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ViewPreset {
+    Front,
+    Back,
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl ViewPreset {
+    /// The yaw and pitch of the orbit camera which sees this side.
+    fn orientation(&self) -> (Angle<f32>, Angle<f32>) {
+        let (yaw, pitch) = match *self {
+            ViewPreset::Front => (0.0, 0.0),
+            ViewPreset::Back => (180.0, 0.0),
+            ViewPreset::Left => (-90.0, 0.0),
+            ViewPreset::Right => (90.0, 0.0),
+            ViewPreset::Top => (0.0, 90.0),
+            ViewPreset::Bottom => (0.0, -90.0),
+        };
+        (Degrees(yaw), Degrees(pitch))
+    }
+}
+
+/// Every kind of camera, with one of them in use. Each keeps its place while
+/// another is used, so switching back returns to where it was.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CameraRig {
+    pub free: CameraController,
+    pub orbit: OrbitController,
+    pub path: CameraPathPlayer,
+    pub player: PlayerController,
+    mode: CameraMode,
+}
+
+impl CameraRig {
+    pub fn new<W: WindowExt>(window: &W, config: &CameraConfig) -> Self {
+        let projection = Projection::new(
+            window.aspect(),
+            Degrees(config.fov),
+            config.near_plane,
+            config.far_plane,
+        );
+        let free = CameraController::new(window, config.mouse_degrees_per_pixel, config.move_speed)
+            .with_turn_speed(config.turn_speed)
+            .with_acceleration(config.acceleration, config.damping)
+            .with_speed_multipliers(config.sprint_multiplier, config.slow_multiplier)
+            .with_mouse_smoothing(config.mouse_smoothing)
+            .with_invert_y(config.invert_y)
+            .with_projection(projection);
+        CameraRig {
+            player: PlayerController::new(free.clone(), config),
+            free,
+            orbit: OrbitController::new(window, config.orbit_speed).with_projection(projection),
+            path: CameraPathPlayer::new(CameraPath::default(), projection),
+            mode: CameraMode::Free,
+        }
+    }
+
+    #[inline]
+    pub fn with_camera_path(mut self, path: CameraPath) -> Self {
+        self.path.path = path;
+        self
+    }
+
+    /// Switches between the free and orbit cameras, or back to the free
+    /// camera from a camera path or walking.
+    #[inline]
+    pub fn toggle_mode(&mut self) {
+        let mode = match self.mode {
+            CameraMode::Free => CameraMode::Orbit,
+            CameraMode::Orbit | CameraMode::Path | CameraMode::Walk => CameraMode::Free,
+        };
+        self.set_mode(mode);
+    }
+
+    /// Starts walking from where the free camera is, or goes back to flying
+    /// from where the walk ended.
+    pub fn toggle_walking(&mut self) {
+        if self.mode == CameraMode::Walk {
+            self.set_mode(CameraMode::Free);
+        } else {
+            self.player.spawn_at(&self.free);
+            self.set_mode(CameraMode::Walk);
+        }
+    }
+
+    /// The free camera stops when it is left, rather than drifting on when it
+    /// is used again, and a camera path stops playing. After walking, the
+    /// free camera carries on from the player's eyes.
+    fn set_mode(&mut self, mode: CameraMode) {
+        if self.mode == CameraMode::Walk && mode != CameraMode::Walk {
+            let input = self.free.input.clone();
+            self.free = self.player.camera.clone();
+            self.free.input = input;
+        }
+        if mode != CameraMode::Free {
+            self.free.stop();
+        }
+        if mode != CameraMode::Path {
+            self.path.pause();
+        }
+        self.mode = mode;
+    }
+
+    /// Whether the cursor should be hidden and kept in the window, which the
+    /// free and walking cameras need to turn freely but which would stop the
+    /// orbit camera's cursor from being seen.
+    #[inline]
+    pub fn grabs_cursor(&self) -> bool {
+        self.mode == CameraMode::Free || self.mode == CameraMode::Walk
+    }
+
+    pub fn on_resize<W: WindowExt>(&mut self, window: &W) {
+        self.free.on_resize(window);
+        self.orbit.on_resize(window);
+        self.path.on_resize(window);
+        self.player.camera.on_resize(window);
+    }
+
+    /// Every camera is told about every action, so that none thinks a key
+    /// is still held after switching. Returns whether the action is one
+    /// which moves a camera.
+    pub fn on_action(&mut self, action: Action, is_pressed: bool) -> bool {
+        let is_free_action = self.free.on_action(action, is_pressed);
+        let is_orbit_action = self.orbit.on_action(action, is_pressed);
+        let is_player_action = self.player.camera.on_action(action, is_pressed);
+        is_free_action || is_orbit_action || is_player_action
+    }
+
+    #[inline]
+    pub fn on_analogue(&mut self, action: AnalogueAction, value: f32) {
+        self.free.on_analogue(action, value);
+        self.player.camera.on_analogue(action, value);
+    }
+
+    /// Passes cursor movement on to the camera in use.
+    pub fn look(&mut self, x: f32, y: f32) {
+        match self.mode {
+            CameraMode::Free => self.free.look(x, y),
+            CameraMode::Orbit => self.orbit.look(x, y),
+            CameraMode::Path => (),
+            CameraMode::Walk => self.player.camera.look(x, y),
+        }
+    }
+
+    /// Catches up with smoothed mouse movement, once per frame of `dt_s`
+    /// seconds.
+    #[inline]
+    pub fn smooth_look(&mut self, dt_s: f32) {
+        self.free.smooth_look(dt_s);
+        self.player.camera.smooth_look(dt_s);
+    }
+
+    /// Zooms the orbit camera, or changes how fast the free camera moves or
+    /// the camera path plays.
+    pub fn scroll(&mut self, lines: f32) {
+        match self.mode {
+            CameraMode::Free => self.free.scroll(lines),
+            CameraMode::Orbit => self.orbit.scroll(lines),
+            CameraMode::Path => self.path.scroll(lines),
+            CameraMode::Walk => (),
+        }
+    }
+
+    /// Steps the free camera, the camera path or the player, which collides
+    /// with `world`. The orbit camera only moves as input arrives.
+    pub fn apply_input(&mut self, dt_s: f32, world: &CollisionMesh) {
+        match self.mode {
+            CameraMode::Free => self.free.apply_input(dt_s),
+            CameraMode::Orbit => (),
+            CameraMode::Path => self.path.update(dt_s),
+            CameraMode::Walk => self.player.apply_input(dt_s, world),
+        }
+    }
+
+    pub fn interpolated(&self, previous: &CameraRig, alpha: f32) -> CameraRig {
+        CameraRig {
+            free: self.free.interpolated(&previous.free, alpha),
+            path: self.path.interpolated(&previous.path, alpha),
+            player: self.player.interpolated(&previous.player, alpha),
+            ..self.clone()
+        }
+    }
+
+    /// Adds where the camera in use is to the end of the camera path.
+    pub fn add_keyframe(&mut self) {
+        let time = if self.path.path.is_empty() {
+            0.0
+        } else {
+            self.path.path.duration() + KEYFRAME_SPACING
+        };
+        let (view, fov) = match self.mode {
+            CameraMode::Free => (self.free.view(), self.free.projection.fov()),
+            CameraMode::Orbit => (self.orbit.view(), self.orbit.projection.fov()),
+            CameraMode::Path => match self.path.path.sample(self.path.time()) {
+                Some(keyframe) => (keyframe.view(), Degrees(keyframe.fov)),
+                None => return,
+            },
+            CameraMode::Walk => (self.player.camera.view(), self.player.camera.projection.fov()),
+        };
+        let keyframe = Keyframe::from_view(time, &view, fov.in_degrees());
+        self.path.path.insert(keyframe);
+    }
+
+    /// Starts playing the camera path, or pauses or resumes it once it has
+    /// started. Does nothing if the path has no keyframes.
+    pub fn toggle_path_playback(&mut self) {
+        if self.path.path.is_empty() {
+            return;
+        }
+        if self.mode != CameraMode::Path {
+            self.set_mode(CameraMode::Path);
+            self.path.play();
+        } else if self.path.is_playing() {
+            self.path.pause();
+        } else {
+            self.path.play();
+        }
+    }
+
+    /// Moves back to the start of the camera path.
+    #[inline]
+    pub fn rewind_path(&mut self) {
+        self.path.seek(0.0);
+    }
+
+    /// Switches every camera between perspective and orthographic projection.
+    pub fn toggle_projection(&mut self) {
+        let mode = match self.free.projection.mode() {
+            ProjectionMode::Perspective => ProjectionMode::Orthographic,
+            ProjectionMode::Orthographic => ProjectionMode::Perspective,
+        };
+        self.free.projection.set_mode(mode);
+        self.orbit.projection.set_mode(mode);
+        self.path.projection.set_mode(mode);
+        self.player.camera.projection.set_mode(mode);
+    }
+
+    /// Widens the field of view of the free, orbit and walking cameras by
+    /// `angle`, or narrows it if the angle is negative. Camera paths keep
+    /// their own.
+    pub fn widen_fov(&mut self, angle: Angle<f32>) {
+        let fov = Degrees(self.free.projection.fov().in_degrees() + angle.in_degrees());
+        self.free.projection.set_fov(fov);
+        self.orbit.projection.set_fov(fov);
+        self.player.camera.projection.set_fov(fov);
+    }
+
+    /// Moves the near and far clip planes of every camera, multiplying their
+    /// distances by the factors given, which push a plane away when they are
+    /// more than one and pull it in when they are less.
+    pub fn scale_clip_planes(&mut self, near_factor: f32, far_factor: f32) {
+        let (near, far) = self.free.projection.clip_planes();
+        let (near, far) = (near * near_factor, far * far_factor);
+        self.free.projection.set_clip_planes(near, far);
+        self.orbit.projection.set_clip_planes(near, far);
+        self.path.projection.set_clip_planes(near, far);
+        self.player.camera.projection.set_clip_planes(near, far);
+    }
+
+    /// Switches to the orbit camera, looking at the target from one side.
+    pub fn view(&mut self, preset: ViewPreset) {
+        let (yaw, pitch) = preset.orientation();
+        self.orbit.set_orientation(yaw, pitch);
+        self.set_mode(CameraMode::Orbit);
+    }
+
+    /// Switches to the orbit camera, looking at all of the box.
+    pub fn frame(&mut self, bounds: &Aabb) {
+        self.orbit.frame(bounds);
+        self.set_mode(CameraMode::Orbit);
+    }
+}
+
+impl Camera for CameraRig {
+    fn matrices(&self) -> CameraMatrices {
+        match self.mode {
+            CameraMode::Free => self.free.matrices(),
+            CameraMode::Orbit => self.orbit.matrices(),
+            CameraMode::Path => self.path.matrices(),
+            CameraMode::Walk => self.player.matrices(),
+        }
+    }
+}
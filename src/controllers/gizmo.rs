@@ -0,0 +1,476 @@
+use config::GizmoConfig;
+use graphics::camera::CameraMatrices;
+use graphics::lines::LineVertex;
+use graphics::transform::Transform;
+use input::action::Action;
+use na::{Point3, Unit, UnitQuaternion, Vector3, Vector4};
+use physics::ray::Ray;
+use std::f32::consts::PI;
+
+/// How near the cursor has to pass to a handle to grab it, as a fraction of
+/// the size of the gizmo.
+const GRAB_TOLERANCE: f32 = 0.08;
+/// Where the squares for moving across a plane start and end along each of
+/// its axes, as fractions of the size of the gizmo.
+const PLANE_HANDLE_START: f32 = 0.25;
+const PLANE_HANDLE_END: f32 = 0.45;
+/// Half the width of the square for scaling evenly, and of the squares at
+/// the ends of the scaling axes.
+const CENTRE_HANDLE_SIZE: f32 = 0.1;
+const END_HANDLE_SIZE: f32 = 0.05;
+/// The length and width of the arrow heads on the moving axes.
+const ARROW_LENGTH: f32 = 0.15;
+const ARROW_WIDTH: f32 = 0.05;
+const RING_SEGMENTS: usize = 48;
+/// The smallest a model can be scaled down to along each axis, either way.
+const MIN_SCALE: f32 = 0.01;
+
+const AXIS_COLORS: [[f32; 4]; 3] = [
+    [0.9, 0.2, 0.2, 1.0],
+    [0.2, 0.8, 0.2, 1.0],
+    [0.25, 0.4, 1.0, 1.0],
+];
+const CENTRE_COLOR: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const ACTIVE_COLOR: [f32; 4] = [1.0, 0.85, 0.1, 1.0];
+
+/// What dragging the gizmo's handles does to the selected model.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GizmoMode {
+    Translate,
+    Rotate,
+    Scale,
+}
+
+/// A part of the gizmo which can be dragged. Axes are numbered from 0 for x
+/// to 2 for z. They are the world's when moving and turning, and the model's
+/// own when scaling, since that is what the model is stretched along.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Handle {
+    /// Moves along, turns around or scales along an axis.
+    Axis(usize),
+    /// Moves across the plane facing an axis.
+    Plane(usize),
+    /// Scales evenly.
+    Centre,
+}
+
+/// A handle being dragged.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Drag {
+    handle: Handle,
+    /// The model's transform when the handle was grabbed, which every step of
+    /// the drag starts from so that snapping does not build up errors.
+    start: Transform,
+    /// Where the handle was grabbed.
+    grab: Point3<f32>,
+    /// How far from the centre the handle was grabbed, along its axis for the
+    /// axes and in any direction for the centre.
+    grab_distance: f32,
+    /// The plane the cursor is followed across, for the handles which are not
+    /// dragged along a line.
+    normal: Vector3<f32>,
+}
+
+/// Handles drawn over the selected model, which move, turn and scale it as
+/// they are dragged. They keep the same size on screen however far away the
+/// model is.
+///
+/// The handles on the axes stretch the model along just that axis, and
+/// dragging one past the centre mirrors the model. The centre handle scales
+/// it evenly.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gizmo {
+    mode: GizmoMode,
+    config: GizmoConfig,
+    is_snapping: bool,
+    drag: Option<Drag>,
+}
+
+impl Gizmo {
+    pub fn new(config: &GizmoConfig) -> Self {
+        Gizmo {
+            mode: GizmoMode::Translate,
+            config: config.clone(),
+            is_snapping: false,
+            drag: None,
+        }
+    }
+
+    /// Changes mode or starts or stops snapping. Releasing the select button
+    /// lets go of the handle being dragged, but is left for others to see
+    /// too. Returns whether the action is one of the gizmo's.
+    pub fn on_action(&mut self, action: Action, is_pressed: bool) -> bool {
+        let mode = match action {
+            Action::Snap => {
+                self.is_snapping = is_pressed;
+                return true;
+            }
+            Action::Select if !is_pressed => {
+                self.drag = None;
+                return false;
+            }
+            Action::GizmoTranslate => GizmoMode::Translate,
+            Action::GizmoRotate => GizmoMode::Rotate,
+            Action::GizmoScale => GizmoMode::Scale,
+            _ => return false,
+        };
+        if is_pressed && mode != self.mode {
+            self.mode = mode;
+            self.drag = None;
+        }
+        true
+    }
+
+    /// Grabs the handle the ray passes nearest, if it passes near enough to
+    /// one, for the gizmo over a model placed at `transform`. Returns
+    /// whether a handle was grabbed.
+    pub fn grab(&mut self, ray: &Ray, camera: &CameraMatrices, transform: &Transform) -> bool {
+        let centre = transform.position();
+        let size = self.size(&centre, camera);
+        let tolerance = size * GRAB_TOLERANCE;
+
+        let mut nearest: Option<(f32, Drag)> = None;
+        {
+            let mut consider = |miss: f32, handle, grab, grab_distance, normal| {
+                if miss >= tolerance || nearest.map_or(false, |(nearest, _)| nearest <= miss) {
+                    return;
+                }
+                let drag = Drag {
+                    handle,
+                    start: *transform,
+                    grab,
+                    grab_distance,
+                    normal,
+                };
+                nearest = Some((miss, drag));
+            };
+
+            for i in 0..3 {
+                let axis = self.axis(i, transform);
+                match self.mode {
+                    GizmoMode::Translate | GizmoMode::Scale => {
+                        if let Some(along) = ray.closest_on_line(&centre, &axis) {
+                            let closest = centre + axis * along.max(0.0).min(size);
+                            let miss = ray.distance_to_point(&closest);
+                            let grab = centre + axis * along;
+                            // Scaling divides by it, so it is kept away from
+                            // zero.
+                            let grab_distance = if self.mode == GizmoMode::Scale {
+                                along.max(size * CENTRE_HANDLE_SIZE)
+                            } else {
+                                along
+                            };
+                            consider(miss, Handle::Axis(i), grab, grab_distance, axis);
+                        }
+                    }
+                    GizmoMode::Rotate => {
+                        if let Some(distance) = ray.intersect_plane(&centre, &axis) {
+                            let point = ray.at(distance);
+                            let miss = ((point - centre).norm() - size).abs();
+                            consider(miss, Handle::Axis(i), point, size, axis);
+                        }
+                    }
+                }
+
+                if self.mode == GizmoMode::Translate {
+                    if let Some(distance) = ray.intersect_plane(&centre, &axis) {
+                        let point = ray.at(distance);
+                        let offset = (point - centre) / size;
+                        let (j, k) = ((i + 1) % 3, (i + 2) % 3);
+                        let inside = |x: f32| x >= PLANE_HANDLE_START && x <= PLANE_HANDLE_END;
+                        if inside(offset[j]) && inside(offset[k]) {
+                            consider(0.0, Handle::Plane(i), point, 0.0, axis);
+                        }
+                    }
+                }
+            }
+
+            if self.mode == GizmoMode::Scale {
+                // Scaling evenly follows the cursor across the plane facing
+                // the camera.
+                let normal = -ray.direction;
+                if let Some(distance) = ray.intersect_plane(&centre, &normal) {
+                    let point = ray.at(distance);
+                    let offset = (point - centre).norm();
+                    let miss = (offset - size * CENTRE_HANDLE_SIZE).max(0.0);
+                    let grab_distance = offset.max(size * CENTRE_HANDLE_SIZE * 0.5);
+                    consider(miss, Handle::Centre, point, grab_distance, normal);
+                }
+            }
+        }
+
+        self.drag = nearest.map(|(_, drag)| drag);
+        self.drag.is_some()
+    }
+
+    #[inline]
+    pub fn is_dragging(&self) -> bool {
+        self.drag.is_some()
+    }
+
+    /// Moves, turns or scales `transform` as the handle being dragged
+    /// follows the ray, if a handle is being dragged.
+    pub fn drag_to(&self, ray: &Ray, transform: &mut Transform) {
+        let drag = match self.drag {
+            Some(drag) => drag,
+            None => return,
+        };
+        let start = drag.start;
+        let centre = start.position();
+        let mut translation = start.translation;
+        let mut rotation = start.rotation;
+        let mut scale = start.scale;
+
+        match (self.mode, drag.handle) {
+            (GizmoMode::Translate, Handle::Axis(i)) => {
+                let along = match ray.closest_on_line(&centre, &axis(i)) {
+                    Some(along) => along,
+                    None => return,
+                };
+                let offset = along - drag.grab_distance;
+                translation += axis(i) * self.snap(offset, self.config.translate_step);
+            }
+            (GizmoMode::Translate, Handle::Plane(_)) => {
+                let point = match ray.intersect_plane(&drag.grab, &drag.normal) {
+                    Some(distance) => ray.at(distance),
+                    None => return,
+                };
+                let offset = point - drag.grab;
+                for i in 0..3 {
+                    translation[i] += self.snap(offset[i], self.config.translate_step);
+                }
+            }
+            (GizmoMode::Rotate, Handle::Axis(i)) => {
+                let point = match ray.intersect_plane(&centre, &drag.normal) {
+                    Some(distance) => ray.at(distance),
+                    None => return,
+                };
+                let from = drag.grab - centre;
+                let to = point - centre;
+                let angle = drag.normal.dot(&from.cross(&to)).atan2(from.dot(&to));
+                let degrees = self.snap(angle * 180.0 / PI, self.config.rotate_step);
+                let turn = UnitQuaternion::from_axis_angle(
+                    &Unit::new_normalize(axis(i)),
+                    degrees * PI / 180.0,
+                );
+                rotation = turn * rotation;
+            }
+            (GizmoMode::Scale, Handle::Axis(i)) => {
+                let along = match ray.closest_on_line(&centre, &drag.normal) {
+                    Some(along) => along,
+                    None => return,
+                };
+                scale[i] = self.snap_scale(scale[i] * along / drag.grab_distance);
+            }
+            (GizmoMode::Scale, Handle::Centre) => {
+                let point = match ray.intersect_plane(&drag.grab, &drag.normal) {
+                    Some(distance) => ray.at(distance),
+                    None => return,
+                };
+                let factor = (point - centre).norm() / drag.grab_distance;
+                for i in 0..3 {
+                    scale[i] = self.snap_scale(scale[i] * factor);
+                }
+            }
+            _ => return,
+        }
+
+        // The pivot is left where it is, so the model turns and scales about
+        // the centre of the gizmo.
+        transform.translation = translation;
+        transform.rotation = rotation;
+        transform.scale = scale;
+    }
+
+    /// The lines to draw the gizmo with, over a model placed at `transform`.
+    pub fn lines(&self, transform: &Transform, camera: &CameraMatrices) -> Vec<LineVertex> {
+        let centre = transform.position();
+        let size = self.size(&centre, camera);
+        let color = |handle: Handle, color: [f32; 4]| match self.drag {
+            Some(drag) if drag.handle == handle => ACTIVE_COLOR,
+            _ => color,
+        };
+
+        let mut lines = Vec::new();
+        for i in 0..3 {
+            let axis = self.axis(i, transform);
+            let side = self.axis((i + 1) % 3, transform);
+            let up = self.axis((i + 2) % 3, transform);
+            let axis_color = color(Handle::Axis(i), AXIS_COLORS[i]);
+            let end = centre + axis * size;
+            match self.mode {
+                GizmoMode::Translate => {
+                    line(&mut lines, &centre, &end, axis_color);
+                    let back = end - axis * (size * ARROW_LENGTH);
+                    line(&mut lines, &end, &(back + side * (size * ARROW_WIDTH)), axis_color);
+                    line(&mut lines, &end, &(back - side * (size * ARROW_WIDTH)), axis_color);
+
+                    let middle = (PLANE_HANDLE_START + PLANE_HANDLE_END) / 2.0;
+                    let half = (PLANE_HANDLE_END - PLANE_HANDLE_START) / 2.0;
+                    let plane_centre = centre + (side + up) * (size * middle);
+                    let plane_color = color(Handle::Plane(i), AXIS_COLORS[i]);
+                    let (u, v) = (side * (size * half), up * (size * half));
+                    square(&mut lines, &plane_centre, u, v, plane_color);
+                }
+                GizmoMode::Rotate => {
+                    let point = |n: usize| {
+                        let angle = 2.0 * PI * n as f32 / RING_SEGMENTS as f32;
+                        centre + (side * angle.cos() + up * angle.sin()) * size
+                    };
+                    for n in 0..RING_SEGMENTS {
+                        line(&mut lines, &point(n), &point(n + 1), axis_color);
+                    }
+                }
+                GizmoMode::Scale => {
+                    line(&mut lines, &centre, &end, axis_color);
+                    let half = size * END_HANDLE_SIZE;
+                    square(&mut lines, &end, side * half, up * half, axis_color);
+                }
+            }
+        }
+
+        if self.mode == GizmoMode::Scale {
+            // The rows of the view matrix are the camera's axes, so the square
+            // faces the camera.
+            let view = camera.view;
+            let right = Vector3::new(view[(0, 0)], view[(0, 1)], view[(0, 2)]);
+            let up = Vector3::new(view[(1, 0)], view[(1, 1)], view[(1, 2)]);
+            let half = size * CENTRE_HANDLE_SIZE;
+            let centre_color = color(Handle::Centre, CENTRE_COLOR);
+            square(&mut lines, &centre, right * half, up * half, centre_color);
+        }
+        lines
+    }
+
+    /// How long the handles are in world units at `centre`, for them to be
+    /// the configured fraction of the height of the screen.
+    fn size(&self, centre: &Point3<f32>, camera: &CameraMatrices) -> f32 {
+        let position = Vector4::new(centre.x, centre.y, centre.z, 1.0);
+        let clip = camera.projection * camera.view * position;
+        // w is the distance in front of the camera for a perspective
+        // projection, and 1 for an orthographic one.
+        let w = clip.w.abs().max(camera.near);
+        self.config.size * 2.0 * w / camera.projection[(1, 1)].abs().max(1.0e-6)
+    }
+
+    /// The direction of the `i`th axis of the handles, for a model placed at
+    /// `transform`.
+    #[inline]
+    fn axis(&self, i: usize, transform: &Transform) -> Vector3<f32> {
+        match self.mode {
+            GizmoMode::Scale => transform.rotation * axis(i),
+            GizmoMode::Translate | GizmoMode::Rotate => axis(i),
+        }
+    }
+
+    #[inline]
+    fn snap(&self, value: f32, step: f32) -> f32 {
+        if self.is_snapping && step > 0.0 {
+            (value / step).round() * step
+        } else {
+            value
+        }
+    }
+
+    /// Snaps the scale along an axis, and keeps it from reaching zero, which
+    /// would flatten the model. Negative scales mirror the model.
+    fn snap_scale(&self, scale: f32) -> f32 {
+        let scale = self.snap(scale, self.config.scale_step);
+        if scale < 0.0 {
+            scale.min(-MIN_SCALE)
+        } else {
+            scale.max(MIN_SCALE)
+        }
+    }
+}
+
+#[inline]
+fn axis(i: usize) -> Vector3<f32> {
+    let mut axis = Vector3::zeros();
+    axis[i] = 1.0;
+    axis
+}
+
+#[inline]
+fn line(lines: &mut Vec<LineVertex>, from: &Point3<f32>, to: &Point3<f32>, color: [f32; 4]) {
+    lines.push(LineVertex::new([from.x, from.y, from.z], color));
+    lines.push(LineVertex::new([to.x, to.y, to.z], color));
+}
+
+/// The outline of the square around `centre` with its corners `u` and `v`
+/// away along each side.
+fn square(
+    lines: &mut Vec<LineVertex>,
+    centre: &Point3<f32>,
+    u: Vector3<f32>,
+    v: Vector3<f32>,
+    color: [f32; 4],
+) {
+    let c = *centre;
+    let corners = [c - u - v, c + u - v, c + u + v, c - u + v];
+    for i in 0..4 {
+        line(lines, &corners[i], &corners[(i + 1) % 4], color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Matrix4;
+
+    /// A camera looking along z, where the screen is the same as the world
+    /// around the origin, and the gizmo is 0.3 long.
+    fn camera() -> CameraMatrices {
+        CameraMatrices::new(Matrix4::identity(), Matrix4::identity(), -1.0, 1.0)
+    }
+
+    fn ray(x: f32, y: f32) -> Ray {
+        Ray::from_screen(&camera(), x, y).unwrap()
+    }
+
+    #[test]
+    fn dragging_an_axis_moves_only_along_it() {
+        let mut gizmo = Gizmo::new(&GizmoConfig::default());
+        let mut transform = Transform::default();
+        assert!(gizmo.grab(&ray(0.2, 0.0), &camera(), &transform));
+        gizmo.drag_to(&ray(0.25, 0.1), &mut transform);
+        assert!((transform.translation - Vector3::new(0.05, 0.0, 0.0)).norm() < 1.0e-5);
+    }
+
+    #[test]
+    fn missing_every_handle_grabs_nothing() {
+        let mut gizmo = Gizmo::new(&GizmoConfig::default());
+        let transform = Transform::default();
+        assert!(!gizmo.grab(&ray(0.8, 0.8), &camera(), &transform));
+    }
+
+    #[test]
+    fn turning_snaps_to_the_nearest_step() {
+        let mut gizmo = Gizmo::new(&GizmoConfig::default());
+        gizmo.on_action(Action::GizmoRotate, true);
+        gizmo.on_action(Action::Snap, true);
+        let mut transform = Transform::default();
+        assert!(gizmo.grab(&ray(0.3, 0.0), &camera(), &transform));
+
+        let angle = 40.0 * PI / 180.0;
+        gizmo.drag_to(&ray(0.3 * angle.cos(), 0.3 * angle.sin()), &mut transform);
+        let rotation = transform.rotation;
+        assert!((rotation.angle() - PI / 4.0).abs() < 1.0e-4);
+        assert!((rotation * Vector3::z() - Vector3::z()).norm() < 1.0e-5);
+    }
+
+    #[test]
+    fn scaling_an_axis_stretches_only_along_it() {
+        let mut gizmo = Gizmo::new(&GizmoConfig::default());
+        gizmo.on_action(Action::GizmoScale, true);
+        let mut transform = Transform::default();
+        assert!(gizmo.grab(&ray(0.3, 0.0), &camera(), &transform));
+        gizmo.drag_to(&ray(0.6, 0.0), &mut transform);
+        assert!((transform.scale - Vector3::new(2.0, 1.0, 1.0)).norm() < 1.0e-4);
+
+        // Dragging past the centre mirrors the model.
+        gizmo.drag_to(&ray(-0.3, 0.0), &mut transform);
+        assert!((transform.scale.x + 1.0).abs() < 1.0e-4);
+        assert!(transform.is_mirrored());
+    }
+}
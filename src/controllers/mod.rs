@@ -1,2 +1,6 @@
 pub mod camera_controller;
+pub mod camera_path;
+pub mod camera_rig;
+pub mod gizmo;
+pub mod orbit_controller;
 pub mod player_controller;
@@ -0,0 +1,159 @@
+use ::WindowExt;
+use ang::{Angle, Degrees};
+use graphics::bounds::Aabb;
+use graphics::camera::{Camera, CameraMatrices, Projection};
+use input::action::Action;
+use na::{self, Isometry3, Point3, Vector3};
+use num::Zero;
+use std::ops::Neg;
+
+/// How much closer one line of scrolling moves the camera, as a fraction of
+/// its distance from the target.
+const ZOOM_PER_LINE: f32 = 0.1;
+const MIN_DISTANCE: f32 = 0.01;
+/// How much room to leave around a framed model, as a fraction of its size.
+const FRAME_MARGIN: f32 = 1.1;
+
+/// Looks at a target from around it, for inspecting models. Dragging turns
+/// the camera around the target, scrolling moves it closer or further away,
+/// and dragging with the pan button moves the target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrbitController {
+    target: Point3<f32>,
+    distance: f32,
+    yaw: Angle<f32>,
+    pitch: Angle<f32>,
+    pub projection: Projection,
+    /// The height of the window in logical pixels, so that panning keeps the
+    /// target under the cursor.
+    viewport_height: f32,
+    rotate_speed: f32,
+    is_rotating: bool,
+    is_panning: bool,
+}
+
+impl OrbitController {
+    /// Looks at the origin from along the z axis, from where the free camera
+    /// starts. `rotate_speed` is in degrees for each pixel the cursor moves.
+    pub fn new<W: WindowExt>(window: &W, rotate_speed: f32) -> Self {
+        let (_, height) = window.windowext_get_inner_size::<f32>();
+        OrbitController {
+            target: na::origin(),
+            distance: 10.0,
+            yaw: Angle::zero(),
+            pitch: Angle::zero(),
+            projection: Projection::new(window.aspect(), Angle::eighth(), 0.1, 100.0),
+            viewport_height: height,
+            rotate_speed,
+            is_rotating: false,
+            is_panning: false,
+        }
+    }
+
+    #[inline]
+    pub fn with_projection(self, projection: Projection) -> Self {
+        OrbitController {
+            projection,
+            ..self
+        }
+    }
+
+    #[inline]
+    pub fn on_resize<W: WindowExt>(&mut self, window: &W) {
+        self.projection.set_aspect(window.aspect());
+        self.viewport_height = window.windowext_get_inner_size::<f32>().1;
+    }
+
+    pub fn eye(&self) -> Point3<f32> {
+        let offset = Vector3::new(
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+            self.pitch.cos() * self.yaw.cos(),
+        );
+        self.target + offset * self.distance
+    }
+
+    /// The top of the screen, which is worked out from the angles rather than
+    /// being the y axis so that the camera can look straight down.
+    fn up(&self) -> Vector3<f32> {
+        Vector3::new(
+            -self.pitch.sin() * self.yaw.sin(),
+            self.pitch.cos(),
+            -self.pitch.sin() * self.yaw.cos(),
+        )
+    }
+
+    /// Moves the camera around the target, to where `yaw` turns it around the
+    /// y axis from the z axis and `pitch` raises it towards the y axis.
+    pub fn set_orientation(&mut self, yaw: Angle<f32>, pitch: Angle<f32>) {
+        self.yaw = yaw.normalized();
+        self.pitch = pitch;
+    }
+
+    /// Turns the world around so that the camera is at the origin, looking
+    /// down the -z axis.
+    #[inline]
+    pub fn view(&self) -> Isometry3<f32> {
+        Isometry3::look_at_rh(&self.eye(), &self.target, &self.up())
+    }
+
+    /// Starts or stops dragging. Returns whether the action is one which
+    /// moves this camera.
+    pub fn on_action(&mut self, action: Action, is_pressed: bool) -> bool {
+        match action {
+            Action::Orbit => self.is_rotating = is_pressed,
+            Action::Pan => self.is_panning = is_pressed,
+            _ => return false,
+        }
+        true
+    }
+
+    /// Turns or pans while the cursor is dragged, by how far it moved in
+    /// logical pixels to the right and downwards.
+    pub fn look(&mut self, x: f32, y: f32) {
+        if self.is_rotating {
+            self.yaw -= Degrees(self.rotate_speed * x);
+            self.pitch += Degrees(self.rotate_speed * y);
+            self.yaw = self.yaw.normalized();
+
+            let threshold = Angle::quarter() - Degrees(1.0f32);
+            if self.pitch > threshold {
+                self.pitch = threshold;
+            }
+            if self.pitch < threshold.neg() {
+                self.pitch = threshold.neg();
+            }
+        } else if self.is_panning {
+            // Moves the target as far as the cursor moved at its distance, so
+            // that the point under the cursor stays under it.
+            let half_fov = self.projection.fov().in_radians() / 2.0;
+            let units_per_pixel =
+                2.0 * self.distance * half_fov.tan() / self.viewport_height.max(1.0);
+            let forward = (self.target - self.eye()).normalize();
+            let up = self.up();
+            let right = forward.cross(&up);
+            self.target += (up * y - right * x) * units_per_pixel;
+        }
+    }
+
+    /// Moves towards the target for positive `lines`, and away for negative
+    /// ones.
+    pub fn scroll(&mut self, lines: f32) {
+        self.distance = (self.distance * (1.0 - ZOOM_PER_LINE).powf(lines)).max(MIN_DISTANCE);
+    }
+
+    /// Looks at the center of the box, from far enough away to see all of
+    /// it.
+    pub fn frame(&mut self, bounds: &Aabb) {
+        self.target = bounds.center();
+        let radius = bounds.radius().max(MIN_DISTANCE);
+        let half_fov = self.projection.fov().in_radians() / 2.0;
+        self.distance = radius * FRAME_MARGIN / half_fov.sin();
+    }
+}
+
+impl Camera for OrbitController {
+    fn matrices(&self) -> CameraMatrices {
+        self.projection.matrices(self.view().to_homogeneous(), self.distance)
+    }
+}
@@ -1,2 +1,197 @@
-#[derive(Clone, Debug, Eq, PartialEq)]
-pub struct PlayerController;
+use config::CameraConfig;
+use controllers::camera_controller::CameraController;
+use graphics::camera::{Camera, CameraMatrices};
+use na::{Point3, Vector3};
+use physics::collision::{Capsule, CollisionMesh, Contact};
+
+/// The smallest y component of the normal of a surface which can be stood
+/// on, which is the cosine of the steepest slope that can be walked up.
+const MIN_GROUND_NORMAL_Y: f32 = 0.7;
+/// How many times overlaps are pushed apart each time the player moves, since
+/// pushing out of one triangle can push into another.
+const MAX_RESOLVE_ITERATIONS: usize = 4;
+/// The most the player moves before collisions are checked again, as a
+/// fraction of its radius, so that it cannot pass through thin walls.
+const MAX_STEP_FRACTION: f32 = 0.5;
+const MAX_STEPS: usize = 32;
+/// How much of the way to the walking speed the player can change its speed
+/// each second while in the air.
+const AIR_CONTROL: f32 = 2.0;
+/// How far the player can fall before it is put back where it started.
+const FALL_LIMIT: f32 = 100.0;
+
+/// Walks around the scene like in a first person game, falling under gravity
+/// and colliding with the models as a capsule. The camera is at its eyes,
+/// and turns as the free camera does.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlayerController {
+    pub camera: CameraController,
+    /// Where its feet are.
+    position: Point3<f32>,
+    /// Where it started, to go back to if it falls out of the scene.
+    spawn: Point3<f32>,
+    velocity: Vector3<f32>,
+    is_grounded: bool,
+    capsule: Capsule,
+    eye_height: f32,
+    walk_speed: f32,
+    jump_speed: f32,
+    gravity: f32,
+}
+
+impl PlayerController {
+    pub fn new(camera: CameraController, config: &CameraConfig) -> Self {
+        let position = camera.position() - Vector3::y() * config.eye_height;
+        PlayerController {
+            camera,
+            position,
+            spawn: position,
+            velocity: Vector3::zeros(),
+            is_grounded: false,
+            capsule: Capsule {
+                radius: config.player_radius,
+                height: config.player_height,
+            },
+            eye_height: config.eye_height,
+            walk_speed: config.walk_speed,
+            jump_speed: config.jump_speed,
+            gravity: config.gravity,
+        }
+    }
+
+    /// Stands the player where `camera` is, looking the same way, and makes
+    /// that where it goes back to if it falls out of the scene.
+    pub fn spawn_at(&mut self, camera: &CameraController) {
+        let input = self.camera.input.clone();
+        self.camera = camera.clone();
+        self.camera.input = input;
+        self.camera.stop();
+        self.position = camera.position() - Vector3::y() * self.eye_height;
+        self.spawn = self.position;
+        self.velocity = Vector3::zeros();
+        self.is_grounded = false;
+        self.update_camera();
+    }
+
+    /// Walks, jumps and falls for `dt_s` seconds, sliding along whatever in
+    /// `world` it walks into.
+    pub fn apply_input(&mut self, dt_s: f32, world: &CollisionMesh) {
+        fn axis(positive: bool, negative: bool, analogue: f32) -> f32 {
+            let digital = positive as i32 - negative as i32;
+            (digital as f32 + analogue).max(-1.0).min(1.0)
+        }
+
+        fn horizontal(v: Vector3<f32>) -> Vector3<f32> {
+            Vector3::new(v.x, 0.0, v.z).try_normalize(1.0e-6).unwrap_or_else(Vector3::zeros)
+        }
+
+        self.camera.apply_turn(dt_s);
+
+        let input = self.camera.input.clone();
+        let analogue = input.analogue;
+        let mut push = Vector3::new(
+            axis(input.moving_right, input.moving_left, analogue.move_right),
+            0.0,
+            axis(input.moving_forwards, input.moving_backwards, analogue.move_forwards),
+        );
+        let length = push.norm();
+        if length > 1.0 {
+            push /= length;
+        }
+
+        // Walks the way the camera faces, however far up or down it looks.
+        let forward = horizontal(-self.camera.direction());
+        let right = horizontal(-self.camera.right());
+        let wish = (right * push.x + forward * push.z) * self.walk_speed;
+        if self.is_grounded {
+            self.velocity.x = wish.x;
+            self.velocity.z = wish.z;
+            if input.jumping {
+                self.velocity.y = self.jump_speed;
+                self.is_grounded = false;
+            }
+        } else {
+            let fraction = (AIR_CONTROL * dt_s).min(1.0);
+            self.velocity.x += (wish.x - self.velocity.x) * fraction;
+            self.velocity.z += (wish.z - self.velocity.z) * fraction;
+        }
+        self.velocity.y -= self.gravity * dt_s;
+
+        let movement = self.velocity * dt_s;
+        self.move_and_slide(movement, world);
+
+        if self.position.y < self.spawn.y - FALL_LIMIT {
+            self.position = self.spawn;
+            self.velocity = Vector3::zeros();
+        }
+        self.update_camera();
+    }
+
+    /// Moves in steps short enough not to pass through anything, pushing the
+    /// capsule out of whatever it overlaps after each one.
+    fn move_and_slide(&mut self, movement: Vector3<f32>, world: &CollisionMesh) {
+        let max_step = (self.capsule.radius * MAX_STEP_FRACTION).max(1.0e-3);
+        let steps = ((movement.norm() / max_step).ceil() as usize).max(1).min(MAX_STEPS);
+        let step = movement / steps as f32;
+
+        self.is_grounded = false;
+        for _ in 0..steps {
+            self.position += step;
+            self.resolve_collisions(world);
+        }
+    }
+
+    fn resolve_collisions(&mut self, world: &CollisionMesh) {
+        for _ in 0..MAX_RESOLVE_ITERATIONS {
+            let bounds = self.capsule.bounds(&self.position);
+            let deepest = world
+                .triangles_near(&bounds)
+                .iter()
+                .filter_map(|triangle| self.capsule.collide(&self.position, triangle))
+                .fold(None, |deepest: Option<Contact>, contact| match deepest {
+                    Some(deepest) if deepest.depth >= contact.depth => Some(deepest),
+                    _ => Some(contact),
+                });
+            let contact = match deepest {
+                Some(contact) => contact,
+                None => break,
+            };
+
+            if contact.normal.y >= MIN_GROUND_NORMAL_Y {
+                // Pushes straight up out of the ground rather than along its
+                // normal, so that standing on a slope does not slide down it.
+                self.position.y += contact.depth / contact.normal.y;
+                self.is_grounded = true;
+            } else {
+                self.position += contact.normal * contact.depth;
+            }
+
+            // Slides along the surface by losing only the speed into it.
+            let into = self.velocity.dot(&contact.normal);
+            if into < 0.0 {
+                self.velocity -= contact.normal * into;
+            }
+        }
+    }
+
+    #[inline]
+    fn update_camera(&mut self) {
+        let eye = self.position + Vector3::y() * self.eye_height;
+        self.camera.set_position(eye);
+    }
+
+    /// The player part of the way from `previous` to this one.
+    pub fn interpolated(&self, previous: &PlayerController, alpha: f32) -> PlayerController {
+        PlayerController {
+            camera: self.camera.interpolated(&previous.camera, alpha),
+            ..self.clone()
+        }
+    }
+}
+
+impl Camera for PlayerController {
+    #[inline]
+    fn matrices(&self) -> CameraMatrices {
+        self.camera.matrices()
+    }
+}
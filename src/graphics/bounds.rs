@@ -0,0 +1,115 @@
+use ::Vertex;
+use graphics::transform::Transform;
+use na::{self, Point3, Vector3};
+
+/// An axis aligned bounding box.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Point3<f32>,
+    pub max: Point3<f32>,
+}
+
+impl Aabb {
+    #[inline]
+    pub fn new(min: Point3<f32>, max: Point3<f32>) -> Self {
+        Aabb { min, max }
+    }
+
+    /// The smallest box around the points, or `None` if there are none.
+    pub fn from_points<I: IntoIterator<Item = Point3<f32>>>(points: I) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        Some(points.fold(Aabb::new(first, first), |aabb, point| {
+            aabb.union(&Aabb::new(point, point))
+        }))
+    }
+
+    #[inline]
+    pub fn from_vertices(vertices: &[Vertex]) -> Option<Self> {
+        Aabb::from_points(vertices.iter().map(|v| Point3::new(v.pos[0], v.pos[1], v.pos[2])))
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Point3::new(
+                self.min.x.min(other.min.x),
+                self.min.y.min(other.min.y),
+                self.min.z.min(other.min.z),
+            ),
+            max: Point3::new(
+                self.max.x.max(other.max.x),
+                self.max.y.max(other.max.y),
+                self.max.z.max(other.max.z),
+            ),
+        }
+    }
+
+    #[inline]
+    pub fn center(&self) -> Point3<f32> {
+        na::center(&self.min, &self.max)
+    }
+
+    #[inline]
+    pub fn size(&self) -> Vector3<f32> {
+        self.max - self.min
+    }
+
+    /// The radius of the smallest sphere around the center which contains
+    /// the box.
+    #[inline]
+    pub fn radius(&self) -> f32 {
+        self.size().norm() / 2.0
+    }
+
+    /// The area of the outside of the box.
+    #[inline]
+    pub fn surface_area(&self) -> f32 {
+        let size = self.size();
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    /// The point in or on the box nearest to `point`.
+    pub fn closest_point(&self, point: &Point3<f32>) -> Point3<f32> {
+        Point3::new(
+            point.x.max(self.min.x).min(self.max.x),
+            point.y.max(self.min.y).min(self.max.y),
+            point.z.max(self.min.z).min(self.max.z),
+        )
+    }
+
+    /// Whether the boxes overlap or touch.
+    #[inline]
+    pub fn intersects(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x && other.min.x <= self.max.x
+            && self.min.y <= other.max.y && other.min.y <= self.max.y
+            && self.min.z <= other.max.z && other.min.z <= self.max.z
+    }
+
+    pub fn corners(&self) -> [Point3<f32>; 8] {
+        let (min, max) = (self.min, self.max);
+        [
+            Point3::new(min.x, min.y, min.z),
+            Point3::new(max.x, min.y, min.z),
+            Point3::new(min.x, max.y, min.z),
+            Point3::new(max.x, max.y, min.z),
+            Point3::new(min.x, min.y, max.z),
+            Point3::new(max.x, min.y, max.z),
+            Point3::new(min.x, max.y, max.z),
+            Point3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    /// The box around this one once it has been transformed.
+    pub fn transformed(&self, transform: &Transform) -> Aabb {
+        let corners = self.corners();
+        Aabb::from_points(corners.iter().map(|corner| transform.transform_point(corner)))
+            .expect("A box has corners")
+    }
+
+    /// The box around this one in the space `transform` maps from.
+    pub fn inverse_transformed(&self, transform: &Transform) -> Aabb {
+        let corners = self.corners();
+        Aabb::from_points(corners.iter().map(|corner| transform.inverse_transform_point(corner)))
+            .expect("A box has corners")
+    }
+}
@@ -1,17 +1,129 @@
-use na::{Matrix4};
+use ang::{Angle, Degrees};
+use na::{Matrix4, Orthographic3, Perspective3};
+
+/// The narrowest and widest fields of view a camera can be given, in degrees.
+const MIN_FOV: f32 = 5.0;
+const MAX_FOV: f32 = 150.0;
+/// The nearest a near clip plane can be, since depth precision falls apart
+/// as it approaches zero.
+const MIN_NEAR: f32 = 0.001;
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct CameraMatrices {
     pub view: Matrix4<f32>,
     pub projection: Matrix4<f32>,
+    /// The distances to the clip planes, which cannot be read back from every
+    /// kind of projection matrix.
+    pub near: f32,
+    pub far: f32,
 }
 
 impl CameraMatrices {
-	pub fn new(view: Matrix4<f32>, projection: Matrix4<f32>) -> Self {
-		CameraMatrices { view, projection, }
+	pub fn new(view: Matrix4<f32>, projection: Matrix4<f32>, near: f32, far: f32) -> Self {
+		CameraMatrices { view, projection, near, far, }
 	}
 }
 
 pub trait Camera {
     fn matrices(&self) -> CameraMatrices;
 }
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ProjectionMode {
+    Perspective,
+    /// Keeps things the same size however far away they are, so that lines
+    /// which are parallel stay parallel.
+    Orthographic,
+}
+
+/// How a camera maps what it sees onto the screen, which can be changed while
+/// the viewer runs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Projection {
+    mode: ProjectionMode,
+    aspect: f32,
+    fov: Angle<f32>,
+    near: f32,
+    far: f32,
+}
+
+impl Projection {
+    pub fn new(aspect: f32, fov: Angle<f32>, near: f32, far: f32) -> Self {
+        let mut projection = Projection {
+            mode: ProjectionMode::Perspective,
+            aspect,
+            fov,
+            near,
+            far,
+        };
+        projection.set_fov(fov);
+        projection.set_clip_planes(near, far);
+        projection
+    }
+
+    #[inline]
+    pub fn mode(&self) -> ProjectionMode {
+        self.mode
+    }
+
+    #[inline]
+    pub fn set_mode(&mut self, mode: ProjectionMode) {
+        self.mode = mode;
+    }
+
+    #[inline]
+    pub fn set_aspect(&mut self, aspect: f32) {
+        self.aspect = aspect;
+    }
+
+    /// The vertical field of view.
+    #[inline]
+    pub fn fov(&self) -> Angle<f32> {
+        self.fov
+    }
+
+    /// Sets the vertical field of view, keeping it between 5 and 150 degrees.
+    pub fn set_fov(&mut self, fov: Angle<f32>) {
+        let degrees = fov.in_degrees().max(MIN_FOV).min(MAX_FOV);
+        self.fov = Degrees(degrees);
+    }
+
+    /// The distances to the near and far clip planes.
+    #[inline]
+    pub fn clip_planes(&self) -> (f32, f32) {
+        (self.near, self.far)
+    }
+
+    /// Sets the distances to the clip planes. The far plane is kept beyond
+    /// the near one.
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        self.near = near.max(MIN_NEAR);
+        self.far = far.max(self.near * 2.0);
+    }
+
+    /// The matrices for a camera with the view matrix `view`. An orthographic
+    /// projection shows as much as a perspective one would at
+    /// `focus_distance` from the camera, so that switching between them
+    /// keeps what is being looked at the same size.
+    pub fn matrices(&self, view: Matrix4<f32>, focus_distance: f32) -> CameraMatrices {
+        let projection = match self.mode {
+            ProjectionMode::Perspective => {
+                Perspective3::new(self.aspect, self.fov.in_radians(), self.near, self.far)
+                    .to_homogeneous()
+            }
+            ProjectionMode::Orthographic => {
+                let half_height = focus_distance * (self.fov.in_radians() / 2.0).tan();
+                let half_width = half_height * self.aspect;
+                Orthographic3::new(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    self.near,
+                    self.far,
+                ).to_homogeneous()
+            }
+        };
+        CameraMatrices::new(view, projection, self.near, self.far)
+    }
+}
@@ -0,0 +1,276 @@
+use ::ColorFormat;
+use gfx::{CommandBuffer, CopyError, Device, Encoder, Resources};
+use gfx::buffer::CreationError as BufferCreationError;
+use gfx::format::{ChannelTyped, Formatted};
+use gfx::handle::RawTexture;
+use gfx::mapping::Error as MappingError;
+use gfx::texture::Size;
+use gfx::traits::FactoryExt;
+use graphics::platform::Backend;
+use image::RgbaImage;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Copies a colour texture back from the gpu into an image.
+///
+/// This flushes the encoder and waits for the copy, so it stalls the frame it
+/// is called in. The texture must have been created with the `TRANSFER_SRC`
+/// bind flag, such as by `OffscreenTarget`.
+pub fn read_pixels<R, F, C, D>(
+    factory: &mut F,
+    encoder: &mut Encoder<R, C>,
+    device: &mut D,
+    backend: &Backend,
+    texture: &RawTexture<R>,
+) -> Result<RgbaImage, CaptureError>
+where
+    R: Resources,
+    F: FactoryExt<R>,
+    C: CommandBuffer<R>,
+    D: Device<Resources = R, CommandBuffer = C>,
+{
+    let channel = <<ColorFormat as Formatted>::Channel as ChannelTyped>::get_channel_type();
+    let info = texture.get_info();
+    let (width, height, _, _) = info.kind.get_dimensions();
+    let pixel_count = width as usize * height as usize;
+    let download = factory.create_download_buffer::<[u8; 4]>(pixel_count)?;
+
+    encoder.copy_texture_to_buffer_raw(
+        texture,
+        None,
+        info.to_raw_image_info(channel, 0),
+        download.raw(),
+        0,
+    )?;
+    encoder.flush(device);
+
+    let reader = factory.read_mapping(&download)?;
+    Ok(to_image(&reader, (width, height), backend))
+}
+
+/// OpenGL stores textures bottom row first and the Metal shaders write their
+/// output swizzled for the drawable, so both are undone here.
+fn to_image(pixels: &[[u8; 4]], (width, height): (Size, Size), backend: &Backend) -> RgbaImage {
+    let (width, height) = (width as usize, height as usize);
+    let mut bytes = Vec::with_capacity(width * height * 4);
+    for row in 0..height {
+        let src_row = if backend.is_gl() { height - 1 - row } else { row };
+        for pixel in &pixels[src_row * width..(src_row + 1) * width] {
+            if backend.is_metal() {
+                bytes.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+            } else {
+                bytes.extend_from_slice(pixel);
+            }
+        }
+    }
+    RgbaImage::from_raw(width as u32, height as u32, bytes)
+        .expect("The buffer is exactly the size of the image")
+}
+
+/// Decides which frames are saved to disk and what they are called.
+///
+/// Single screenshots are numbered `screenshot-0000.png`, `screenshot-0001.png`
+/// and so on, skipping any which already exist. Each recorded sequence gets
+/// the next free `sequence-000` prefix and numbers its frames from zero, as in
+/// `sequence-000-00000.png`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameCapture {
+    directory: PathBuf,
+    is_screenshot_requested: bool,
+    sequence: Option<Sequence>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Sequence {
+    prefix: String,
+    next_frame: u32,
+}
+
+impl FrameCapture {
+    #[inline]
+    pub fn new<P: Into<PathBuf>>(directory: P) -> Self {
+        FrameCapture {
+            directory: directory.into(),
+            is_screenshot_requested: false,
+            sequence: None,
+        }
+    }
+
+    #[inline]
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Saves the next frame which is captured.
+    #[inline]
+    pub fn request_screenshot(&mut self) {
+        self.is_screenshot_requested = true;
+    }
+
+    /// Starts saving every frame, in a new numbered sequence.
+    pub fn start_sequence(&mut self) {
+        if self.sequence.is_none() {
+            let index =
+                next_free_name(&self.directory, |i| format!("sequence-{:03}-00000.png", i));
+            self.sequence = Some(Sequence {
+                prefix: format!("sequence-{:03}", index),
+                next_frame: 0,
+            });
+        }
+    }
+
+    #[inline]
+    pub fn stop_sequence(&mut self) {
+        self.sequence = None;
+    }
+
+    #[inline]
+    pub fn toggle_sequence(&mut self) {
+        if self.is_recording() {
+            self.stop_sequence();
+        } else {
+            self.start_sequence();
+        }
+    }
+
+    #[inline]
+    pub fn is_recording(&self) -> bool {
+        self.sequence.is_some()
+    }
+
+    /// Whether the current frame needs to be read back at all.
+    #[inline]
+    pub fn is_pending(&self) -> bool {
+        self.is_screenshot_requested || self.is_recording()
+    }
+
+    /// Reads back the texture if this frame is wanted, and writes it out.
+    /// Returns the paths which were written.
+    pub fn capture<R, F, C, D>(
+        &mut self,
+        factory: &mut F,
+        encoder: &mut Encoder<R, C>,
+        device: &mut D,
+        backend: &Backend,
+        texture: &RawTexture<R>,
+    ) -> Result<Vec<PathBuf>, CaptureError>
+    where
+        R: Resources,
+        F: FactoryExt<R>,
+        C: CommandBuffer<R>,
+        D: Device<Resources = R, CommandBuffer = C>,
+    {
+        if !self.is_pending() {
+            return Ok(Vec::new());
+        }
+
+        let image = read_pixels(factory, encoder, device, backend, texture)?;
+        self.save(&image)
+    }
+
+    /// Writes out an image which is already in memory, if this frame is
+    /// wanted. Returns the paths which were written.
+    pub fn save(&mut self, image: &RgbaImage) -> Result<Vec<PathBuf>, CaptureError> {
+        if !self.is_pending() {
+            return Ok(Vec::new());
+        }
+
+        fs::create_dir_all(&self.directory)?;
+
+        let mut paths = Vec::new();
+        if self.is_screenshot_requested {
+            self.is_screenshot_requested = false;
+            let index = next_free_name(&self.directory, |i| format!("screenshot-{:04}.png", i));
+            paths.push(self.directory.join(format!("screenshot-{:04}.png", index)));
+        }
+        if let Some(ref mut sequence) = self.sequence {
+            let name = format!("{}-{:05}.png", sequence.prefix, sequence.next_frame);
+            sequence.next_frame += 1;
+            paths.push(self.directory.join(name));
+        }
+
+        for path in &paths {
+            image.save(path)?;
+        }
+        Ok(paths)
+    }
+}
+
+/// The first index whose name does not exist in the directory yet.
+fn next_free_name<N: Fn(u32) -> String>(directory: &Path, name: N) -> u32 {
+    (0..).find(|&i| !directory.join(name(i)).exists()).unwrap()
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Buffer(BufferCreationError),
+    Copy(CopyError<[Size; 3], usize>),
+    Mapping(MappingError),
+    Io(io::Error),
+}
+
+impl From<BufferCreationError> for CaptureError {
+    #[inline]
+    fn from(e: BufferCreationError) -> Self {
+        CaptureError::Buffer(e)
+    }
+}
+
+impl From<CopyError<[Size; 3], usize>> for CaptureError {
+    #[inline]
+    fn from(e: CopyError<[Size; 3], usize>) -> Self {
+        CaptureError::Copy(e)
+    }
+}
+
+impl From<MappingError> for CaptureError {
+    #[inline]
+    fn from(e: MappingError) -> Self {
+        CaptureError::Mapping(e)
+    }
+}
+
+impl From<io::Error> for CaptureError {
+    #[inline]
+    fn from(e: io::Error) -> Self {
+        CaptureError::Io(e)
+    }
+}
+
+impl fmt::Display for CaptureError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            CaptureError::Buffer(ref e) => write!(fmtr, "{}: {}", desc, e),
+            CaptureError::Copy(ref e) => write!(fmtr, "{}: {:?}", desc, e),
+            CaptureError::Mapping(ref e) => write!(fmtr, "{}: {}", desc, e),
+            CaptureError::Io(ref e) => write!(fmtr, "{}: {}", desc, e),
+        }
+    }
+}
+
+impl Error for CaptureError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            CaptureError::Buffer(_) => "Could not create the download buffer",
+            CaptureError::Copy(_) => "Could not copy the frame out of its texture",
+            CaptureError::Mapping(_) => "Could not read the downloaded frame",
+            CaptureError::Io(_) => "Could not write the captured frame",
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            CaptureError::Buffer(ref e) => Some(e),
+            CaptureError::Copy(_) => None,
+            CaptureError::Mapping(ref e) => Some(e),
+            CaptureError::Io(ref e) => Some(e),
+        }
+    }
+}
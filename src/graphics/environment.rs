@@ -0,0 +1,631 @@
+use gfx::{self, CombinedError, Resources};
+use gfx::buffer::{CreationError as BufferCreationError, Role};
+use gfx::format::Rgba32F;
+use gfx::handle::{Buffer, Sampler, ShaderResourceView};
+use gfx::memory::Bind;
+use gfx::texture::{AaMode, FilterMethod, Kind, Mipmap, SamplerInfo, WrapMode};
+use gfx::traits::FactoryExt;
+use image::{self, ImageError, RgbaImage};
+use image::hdr::HDRDecoder;
+use na::Vector3;
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use util::{GetAssetsFolderError, find_asset};
+
+gfx_defines! {
+    #[derive(Default)]
+    constant EnvironmentLocals {
+        sh0: [f32; 4] = "irradiance_sh0",
+        sh1: [f32; 4] = "irradiance_sh1",
+        sh2: [f32; 4] = "irradiance_sh2",
+        sh3: [f32; 4] = "irradiance_sh3",
+        sh4: [f32; 4] = "irradiance_sh4",
+        sh5: [f32; 4] = "irradiance_sh5",
+        sh6: [f32; 4] = "irradiance_sh6",
+        sh7: [f32; 4] = "irradiance_sh7",
+        sh8: [f32; 4] = "irradiance_sh8",
+        max_lod: f32 = "specular_max_lod",
+        intensity: f32 = "environment_intensity",
+    }
+}
+
+/// The width of the most detailed level of the specular mip chain. Each level
+/// after it halves the resolution and is filtered for a rougher surface.
+const SPECULAR_BASE_WIDTH: usize = 128;
+const SPECULAR_LEVELS: usize = 5;
+const SPECULAR_SAMPLES: u32 = 64;
+
+pub const SH_COEFFICIENTS: usize = 9;
+
+/// The widest equirectangular map which the faces of a cube map are resampled
+/// into. It is plenty for lighting, and keeps large faces quick to convert.
+const MAX_CONVERTED_WIDTH: usize = 1024;
+
+/// An equirectangular radiance map, in linear floating point colour.
+#[derive(Clone, Debug)]
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vector3<f32>>,
+}
+
+impl EnvironmentMap {
+    /// Loads an equirectangular image, which is looked for in the assets
+    /// folder unless it is an existing path.
+    ///
+    /// Radiance `.hdr` files are read as they are. Any other image is assumed
+    /// to be sRGB encoded and is converted to linear colour.
+    pub fn load(file_name: &str) -> Result<Self, LoadEnvironmentError> {
+        let path = find_asset(file_name)?;
+        let is_hdr = path.extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| ext.eq_ignore_ascii_case("hdr"));
+        if !is_hdr {
+            let img = image::open(path)?.to_rgb();
+            let (width, height) = img.dimensions();
+            let pixels = img.pixels().map(|p| srgb_to_linear(&p.data)).collect();
+            return Ok(EnvironmentMap {
+                width: width as usize,
+                height: height as usize,
+                pixels,
+            });
+        }
+
+        let decoder = HDRDecoder::new(BufReader::new(File::open(path)?))?;
+        let metadata = decoder.metadata();
+        let pixels = decoder
+            .read_image_hdr()?
+            .into_iter()
+            .map(|p| Vector3::new(p.data[0], p.data[1], p.data[2]))
+            .collect();
+        Ok(EnvironmentMap {
+            width: metadata.width as usize,
+            height: metadata.height as usize,
+            pixels,
+        })
+    }
+
+    /// Resamples the faces of a cube map, as `load_cube_faces` gives them,
+    /// into an equirectangular map.
+    pub fn from_cube_faces(faces: &[RgbaImage]) -> Self {
+        let size = faces[0].width();
+        let width = (size as usize * 4).min(MAX_CONVERTED_WIDTH);
+        let height = width / 2;
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let theta = PI * (y as f32 + 0.5) / height as f32;
+            for x in 0..width {
+                let u = (x as f32 + 0.5) / width as f32;
+                let (face, s, t) = cube_face_coordinates(&equirect_to_direction(u, theta));
+                let texel = |coordinate: f32| ((coordinate * size as f32) as u32).min(size - 1);
+                let pixel = faces[face].get_pixel(texel(s), texel(t));
+                pixels.push(srgb_to_linear(&pixel.data));
+            }
+        }
+        EnvironmentMap {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// An environment which emits the same light in every direction.
+    pub fn uniform(color: [f32; 3]) -> Self {
+        EnvironmentMap {
+            width: 1,
+            height: 1,
+            pixels: vec![Vector3::new(color[0], color[1], color[2])],
+        }
+    }
+
+    #[inline]
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    #[inline]
+    pub fn pixels(&self) -> &[Vector3<f32>] {
+        &self.pixels
+    }
+
+    /// Bilinearly samples the map in the given direction.
+    pub fn sample(&self, direction: &Vector3<f32>) -> Vector3<f32> {
+        let (u, v) = direction_to_equirect(direction);
+        let x = u * self.width as f32 - 0.5;
+        let y = (v * self.height as f32 - 0.5).max(0.0);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+
+        let texel = |x: isize, y: isize| {
+            let x = x.wrapping_rem(self.width as isize);
+            let x = if x < 0 { x + self.width as isize } else { x } as usize;
+            let y = (y.max(0) as usize).min(self.height - 1);
+            self.pixels[y * self.width + x]
+        };
+
+        let (x0, y0) = (x0 as isize, y0 as isize);
+        let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1, y0) * tx;
+        let bottom = texel(x0, y0 + 1) * (1.0 - tx) + texel(x0 + 1, y0 + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    /// Halves the resolution of the map with a box filter.
+    fn downsampled(&self) -> Self {
+        if self.width == 1 && self.height == 1 {
+            return self.clone();
+        }
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let mut sum = Vector3::new(0.0, 0.0, 0.0);
+                for &(dx, dy) in &[(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let sx = (x * 2 + dx).min(self.width - 1);
+                    let sy = (y * 2 + dy).min(self.height - 1);
+                    sum += self.pixels[sy * self.width + sx];
+                }
+                pixels.push(sum / 4.0);
+            }
+        }
+        EnvironmentMap {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    /// Projects the map onto the first three bands of the spherical harmonics
+    /// and convolves it with a cosine lobe, giving the irradiance for any
+    /// surface normal divided by pi.
+    pub fn irradiance_sh(&self) -> [Vector3<f32>; SH_COEFFICIENTS] {
+        let mut coefficients = [Vector3::new(0.0, 0.0, 0.0); SH_COEFFICIENTS];
+        let texel_area = (2.0 * PI / self.width as f32) * (PI / self.height as f32);
+
+        for y in 0..self.height {
+            let theta = PI * (y as f32 + 0.5) / self.height as f32;
+            let solid_angle = texel_area * theta.sin();
+            for x in 0..self.width {
+                let u = (x as f32 + 0.5) / self.width as f32;
+                let direction = equirect_to_direction(u, theta);
+                let radiance = self.pixels[y * self.width + x];
+                let basis = sh_basis(&direction);
+                for (coefficient, b) in coefficients.iter_mut().zip(basis.iter()) {
+                    *coefficient += radiance * (b * solid_angle);
+                }
+            }
+        }
+
+        // The cosine lobe's bands are pi, 2pi/3 and pi/4. They are divided
+        // by pi here so that the shader can multiply by the diffuse colour.
+        const BAND_SCALE: [f32; 3] = [1.0, 2.0 / 3.0, 1.0 / 4.0];
+        for (i, coefficient) in coefficients.iter_mut().enumerate() {
+            let band = match i {
+                0 => 0,
+                1 | 2 | 3 => 1,
+                _ => 2,
+            };
+            *coefficient *= BAND_SCALE[band];
+        }
+        coefficients
+    }
+
+    /// Builds the specular mip chain, where each level is the radiance
+    /// convolved with the GGX lobe for a roughness of `level / (levels - 1)`.
+    pub fn specular_levels(&self) -> Vec<EnvironmentMap> {
+        let mut source = self.clone();
+        while source.width > SPECULAR_BASE_WIDTH * 2 {
+            source = source.downsampled();
+        }
+
+        let mut levels = Vec::with_capacity(SPECULAR_LEVELS);
+        let (mut width, mut height) = (SPECULAR_BASE_WIDTH, SPECULAR_BASE_WIDTH / 2);
+        for level in 0..SPECULAR_LEVELS {
+            let roughness = level as f32 / (SPECULAR_LEVELS - 1) as f32;
+            let mut pixels = Vec::with_capacity(width * height);
+            for y in 0..height {
+                let theta = PI * (y as f32 + 0.5) / height as f32;
+                for x in 0..width {
+                    let u = (x as f32 + 0.5) / width as f32;
+                    let normal = equirect_to_direction(u, theta);
+                    pixels.push(if level == 0 {
+                        source.sample(&normal)
+                    } else {
+                        prefilter_ggx(&source, &normal, roughness)
+                    });
+                }
+            }
+            levels.push(EnvironmentMap {
+                width,
+                height,
+                pixels,
+            });
+
+            // Sample from a lower resolution copy for blurrier levels to keep
+            // the number of samples down without adding noise.
+            if source.width > width {
+                source = source.downsampled();
+            }
+            width = (width / 2).max(1);
+            height = (height / 2).max(1);
+        }
+        levels
+    }
+}
+
+/// The GPU side of an environment map: the prefiltered specular mip chain and
+/// the irradiance coefficients, which are shared by every model.
+#[derive(Clone, Debug)]
+pub struct EnvironmentResources<R: Resources> {
+    pub locals: Buffer<R, EnvironmentLocals>,
+    pub specular_map: (ShaderResourceView<R, [f32; 4]>, Sampler<R>),
+}
+
+impl<R: Resources> EnvironmentResources<R> {
+    pub fn new<F: FactoryExt<R>>(
+        factory: &mut F,
+        environment: &EnvironmentMap,
+        intensity: f32,
+    ) -> Result<Self, EnvironmentError> {
+        let sh = environment.irradiance_sh();
+        let levels = environment.specular_levels();
+
+        let to_vec4 = |v: &Vector3<f32>| [v.x, v.y, v.z, 0.0];
+        let locals = EnvironmentLocals {
+            sh0: to_vec4(&sh[0]),
+            sh1: to_vec4(&sh[1]),
+            sh2: to_vec4(&sh[2]),
+            sh3: to_vec4(&sh[3]),
+            sh4: to_vec4(&sh[4]),
+            sh5: to_vec4(&sh[5]),
+            sh6: to_vec4(&sh[6]),
+            sh7: to_vec4(&sh[7]),
+            sh8: to_vec4(&sh[8]),
+            max_lod: (levels.len() - 1) as f32,
+            intensity,
+        };
+        let locals = factory.create_buffer_immutable(&[locals], Role::Constant, Bind::empty())?;
+
+        let level_data = levels
+            .iter()
+            .map(|level| {
+                level
+                    .pixels
+                    .iter()
+                    .map(|p| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits(), 1.0f32.to_bits()])
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let level_slices = level_data.iter().map(|l| &l[..]).collect::<Vec<_>>();
+        let (base_width, base_height) = levels[0].dimensions();
+        let kind = Kind::D2(base_width as u16, base_height as u16, AaMode::Single);
+        let (_, srv) =
+            factory.create_texture_immutable::<Rgba32F>(kind, Mipmap::Provided, &level_slices)?;
+
+        let sampler = {
+            let mut info = SamplerInfo::new(FilterMethod::Trilinear, WrapMode::Clamp);
+            info.wrap_mode.0 = WrapMode::Tile;
+            factory.create_sampler(info)
+        };
+
+        Ok(EnvironmentResources {
+            locals,
+            specular_map: (srv, sampler),
+        })
+    }
+}
+
+/// Loads the six faces of a cube map, in the order +X, -X, +Y, -Y, +Z, -Z,
+/// looking for them in the assets folder unless they are existing paths. The
+/// faces must be square and all the same size.
+pub fn load_cube_faces(faces: &[String; 6]) -> Result<Vec<RgbaImage>, LoadEnvironmentError> {
+    let mut images = Vec::with_capacity(faces.len());
+    for face in faces.iter() {
+        images.push(image::open(find_asset(face)?)?.to_rgba());
+    }
+    let size = images[0].width();
+    let is_valid_size = size <= u16::max_value() as u32;
+    if !is_valid_size || images.iter().any(|face| face.dimensions() != (size, size)) {
+        return Err(LoadEnvironmentError::CubeFaceSize);
+    }
+    Ok(images)
+}
+
+#[inline]
+fn srgb_to_linear(color: &[u8]) -> Vector3<f32> {
+    let to_linear = |c: u8| (c as f32 / 255.0).powf(2.2);
+    Vector3::new(to_linear(color[0]), to_linear(color[1]), to_linear(color[2]))
+}
+
+/// Maps a direction to equirectangular texture coordinates. Must be kept in
+/// sync with `equirect_uv` in the shaders.
+#[inline]
+pub fn direction_to_equirect(direction: &Vector3<f32>) -> (f32, f32) {
+    let d = direction.normalize();
+    let u = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+    let v = d.y.max(-1.0).min(1.0).acos() / PI;
+    (u, v)
+}
+
+#[inline]
+fn equirect_to_direction(u: f32, theta: f32) -> Vector3<f32> {
+    let phi = 2.0 * PI * u - PI;
+    Vector3::new(theta.sin() * phi.sin(), theta.cos(), -theta.sin() * phi.cos())
+}
+
+/// The face of a cube map which a direction points at, and where on that
+/// face, from 0 to 1 across and down, in the same way as the gpu picks them.
+fn cube_face_coordinates(d: &Vector3<f32>) -> (usize, f32, f32) {
+    let (x, y, z) = (d.x.abs(), d.y.abs(), d.z.abs());
+    let (face, s, t, major) = if x >= y && x >= z {
+        if d.x > 0.0 {
+            (0, -d.z, -d.y, x)
+        } else {
+            (1, d.z, -d.y, x)
+        }
+    } else if y >= z {
+        if d.y > 0.0 {
+            (2, d.x, d.z, y)
+        } else {
+            (3, d.x, -d.z, y)
+        }
+    } else if d.z > 0.0 {
+        (4, d.x, -d.y, z)
+    } else {
+        (5, -d.x, -d.y, z)
+    };
+    (face, (s / major + 1.0) / 2.0, (t / major + 1.0) / 2.0)
+}
+
+fn sh_basis(d: &Vector3<f32>) -> [f32; SH_COEFFICIENTS] {
+    [
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+#[inline]
+fn hammersley(i: u32, count: u32) -> (f32, f32) {
+    // Van der Corput radical inverse, by reversing the bits of `i`.
+    let mut bits = i;
+    bits = (bits << 16) | (bits >> 16);
+    bits = ((bits & 0x55555555) << 1) | ((bits & 0xAAAAAAAA) >> 1);
+    bits = ((bits & 0x33333333) << 2) | ((bits & 0xCCCCCCCC) >> 2);
+    bits = ((bits & 0x0F0F0F0F) << 4) | ((bits & 0xF0F0F0F0) >> 4);
+    bits = ((bits & 0x00FF00FF) << 8) | ((bits & 0xFF00FF00) >> 8);
+    (i as f32 / count as f32, bits as f32 * 2.3283064e-10)
+}
+
+/// Integrates the environment over the GGX lobe around `normal`, assuming
+/// that the view direction is the same as the normal.
+fn prefilter_ggx(source: &EnvironmentMap, normal: &Vector3<f32>, roughness: f32) -> Vector3<f32> {
+    let a = roughness * roughness;
+    let up = if normal.z.abs() < 0.999 {
+        Vector3::z()
+    } else {
+        Vector3::x()
+    };
+    let tangent = up.cross(normal).normalize();
+    let bitangent = normal.cross(&tangent);
+
+    let mut total = Vector3::new(0.0, 0.0, 0.0);
+    let mut total_weight = 0.0;
+    for i in 0..SPECULAR_SAMPLES {
+        let (x1, x2) = hammersley(i, SPECULAR_SAMPLES);
+        let phi = 2.0 * PI * x1;
+        let cos_theta = ((1.0 - x2) / (1.0 + (a * a - 1.0) * x2)).sqrt();
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let half = tangent * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) +
+            *normal * cos_theta;
+        let light = half * (2.0 * normal.dot(&half)) - *normal;
+
+        let n_dot_l = normal.dot(&light);
+        if n_dot_l > 0.0 {
+            total += source.sample(&light) * n_dot_l;
+            total_weight += n_dot_l;
+        }
+    }
+
+    if total_weight > 0.0 {
+        total / total_weight
+    } else {
+        source.sample(normal)
+    }
+}
+
+#[derive(Debug)]
+pub enum LoadEnvironmentError {
+    Io(io::Error),
+    Image(ImageError),
+    AssetsFolder(GetAssetsFolderError),
+    CubeFaceSize,
+}
+
+impl From<io::Error> for LoadEnvironmentError {
+    #[inline]
+    fn from(e: io::Error) -> Self {
+        LoadEnvironmentError::Io(e)
+    }
+}
+
+impl From<ImageError> for LoadEnvironmentError {
+    #[inline]
+    fn from(e: ImageError) -> Self {
+        LoadEnvironmentError::Image(e)
+    }
+}
+
+impl From<GetAssetsFolderError> for LoadEnvironmentError {
+    #[inline]
+    fn from(e: GetAssetsFolderError) -> Self {
+        LoadEnvironmentError::AssetsFolder(e)
+    }
+}
+
+impl fmt::Display for LoadEnvironmentError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadEnvironmentError::Io(ref e) => write!(fmtr, "{}: {}", self.description(), e),
+            LoadEnvironmentError::Image(ref e) => write!(fmtr, "{}: {}", self.description(), e),
+            LoadEnvironmentError::AssetsFolder(ref e) => {
+                write!(fmtr, "{}: {}", self.description(), e)
+            }
+            LoadEnvironmentError::CubeFaceSize => write!(fmtr, "{}", self.description()),
+        }
+    }
+}
+
+impl Error for LoadEnvironmentError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            LoadEnvironmentError::Io(_) => "An I/O error occurred",
+            LoadEnvironmentError::Image(_) => "Could not decode the environment map",
+            LoadEnvironmentError::AssetsFolder(_) => "Could not get assets folder",
+            LoadEnvironmentError::CubeFaceSize => {
+                "The faces of a cube map must be square and all the same size"
+            }
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            LoadEnvironmentError::Io(ref e) => Some(e),
+            LoadEnvironmentError::Image(ref e) => Some(e),
+            LoadEnvironmentError::AssetsFolder(ref e) => Some(e),
+            LoadEnvironmentError::CubeFaceSize => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum EnvironmentError {
+    Buffer(BufferCreationError),
+    Texture(CombinedError),
+}
+
+impl From<BufferCreationError> for EnvironmentError {
+    #[inline]
+    fn from(e: BufferCreationError) -> Self {
+        EnvironmentError::Buffer(e)
+    }
+}
+
+impl From<CombinedError> for EnvironmentError {
+    #[inline]
+    fn from(e: CombinedError) -> Self {
+        EnvironmentError::Texture(e)
+    }
+}
+
+impl fmt::Display for EnvironmentError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            EnvironmentError::Buffer(ref e) => write!(fmtr, "{}: {}", desc, e),
+            EnvironmentError::Texture(ref e) => write!(fmtr, "{}: {}", desc, e),
+        }
+    }
+}
+
+impl Error for EnvironmentError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            EnvironmentError::Buffer(_) => "An error occurred while creating the environment buffer",
+            EnvironmentError::Texture(_) => {
+                "An error occurred while uploading the environment map to the gpu"
+            }
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            EnvironmentError::Buffer(ref e) => Some(e),
+            EnvironmentError::Texture(ref e) => Some(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bundled_sky() -> EnvironmentMap {
+        let path = format!("{}/data/img/environment.hdr", env!("CARGO_MANIFEST_DIR"));
+        EnvironmentMap::load(&path).unwrap()
+    }
+
+    #[inline]
+    fn luminance(color: &Vector3<f32>) -> f32 {
+        0.2126 * color.x + 0.7152 * color.y + 0.0722 * color.z
+    }
+
+    #[test]
+    fn bundled_sky_lights_from_above() {
+        let sky = bundled_sky();
+        assert_eq!(sky.dimensions(), (128, 64));
+        assert!(luminance(&sky.sample(&Vector3::y())) > luminance(&sky.sample(&-Vector3::y())));
+
+        // More light arrives from above than below, which the linear band
+        // along y records.
+        let sh = sky.irradiance_sh();
+        assert!(sh[0].iter().all(|&c| c > 0.0));
+        assert!(sh[1].iter().all(|&c| c > 0.0));
+    }
+
+    #[test]
+    fn cube_faces_are_resampled_where_they_point() {
+        let colors = [
+            [255, 0, 0],
+            [128, 0, 0],
+            [0, 255, 0],
+            [0, 128, 0],
+            [0, 0, 255],
+            [0, 0, 128],
+        ];
+        let faces = colors
+            .iter()
+            .map(|c| RgbaImage::from_pixel(4, 4, image::Rgba([c[0], c[1], c[2], 255])))
+            .collect::<Vec<_>>();
+        let map = EnvironmentMap::from_cube_faces(&faces);
+        assert_eq!(map.dimensions(), (16, 8));
+        let directions = [
+            Vector3::x(),
+            -Vector3::x(),
+            Vector3::y(),
+            -Vector3::y(),
+            Vector3::z(),
+            -Vector3::z(),
+        ];
+        for (direction, color) in directions.iter().zip(colors.iter()) {
+            let expected = srgb_to_linear(color);
+            assert!((map.sample(direction) - expected).norm() < 1.0e-5);
+        }
+    }
+
+    #[test]
+    fn rougher_levels_spread_out_the_sun() {
+        let levels = bundled_sky().specular_levels();
+        assert_eq!(levels.len(), SPECULAR_LEVELS);
+        let brightest = |level: &EnvironmentMap| {
+            level.pixels().iter().map(luminance).fold(0.0f32, f32::max)
+        };
+        assert!(brightest(&levels[SPECULAR_LEVELS - 1]) < brightest(&levels[0]));
+    }
+}
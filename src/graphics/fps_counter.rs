@@ -19,6 +19,16 @@ impl FpsCounter {
         }
     }
 
+    #[inline]
+    pub fn with_show_fps(self, show_fps: bool) -> Self {
+        FpsCounter { show_fps, ..self }
+    }
+
+    #[inline]
+    pub fn is_showing_fps(&self) -> bool {
+        self.show_fps
+    }
+
     #[inline]
     pub fn toggle_show_fps(&mut self) {
         self.show_fps = !self.show_fps;
@@ -0,0 +1,73 @@
+use ::{FrameLocals, MAX_LIGHTS, ShaderLight};
+use gfx::{CommandBuffer, Encoder, Resources, UpdateError};
+use gfx::handle::{Buffer, Sampler, ShaderResourceView};
+use gfx::traits::FactoryExt;
+use graphics::camera::CameraMatrices;
+use graphics::environment::{EnvironmentLocals, EnvironmentResources};
+use graphics::shadow::{MAX_SHADOW_LAYERS, ShadowMaps, ShadowTransform};
+
+/// The constant buffers which hold the data that is shared between every draw
+/// call in a frame. The handles are cloned into each pipeline which reads them,
+/// so they only need to be uploaded once per frame.
+#[derive(Clone, Debug)]
+pub struct FrameResources<R: Resources> {
+    pub locals: Buffer<R, FrameLocals>,
+    pub lights: Buffer<R, ShaderLight>,
+    pub shadow_transforms: Buffer<R, ShadowTransform>,
+    pub shadow_map: (ShaderResourceView<R, f32>, Sampler<R>),
+    pub environment: Buffer<R, EnvironmentLocals>,
+    pub specular_map: (ShaderResourceView<R, [f32; 4]>, Sampler<R>),
+}
+
+impl<R: Resources> FrameResources<R> {
+    pub fn new<F: FactoryExt<R>>(
+        factory: &mut F,
+        shadows: &ShadowMaps<R>,
+        environment: &EnvironmentResources<R>,
+    ) -> Self {
+        FrameResources {
+            locals: factory.create_constant_buffer(1),
+            lights: factory.create_constant_buffer(MAX_LIGHTS),
+            shadow_transforms: factory.create_constant_buffer(MAX_SHADOW_LAYERS),
+            shadow_map: shadows.resource(),
+            environment: environment.locals.clone(),
+            specular_map: environment.specular_map.clone(),
+        }
+    }
+
+    /// Writes the uniforms shared by everything drawn this frame. Only the
+    /// first `MAX_LIGHTS` lights fit in the buffer, and the rest are left out.
+    pub fn update<C: CommandBuffer<R>>(
+        &self,
+        encoder: &mut Encoder<R, C>,
+        matrices: &CameraMatrices,
+        cascade_splits: [f32; 4],
+        time: f32,
+        lights: &[ShaderLight],
+    ) -> Result<(), UpdateError<usize>> {
+        let lights = &lights[..lights.len().min(MAX_LIGHTS)];
+        let num_lights = lights.len() as u32;
+
+        let CameraMatrices { view, projection, .. } = *matrices;
+        let camera_position = match view.try_inverse() {
+            Some(inverse_view) => {
+                let column = inverse_view.column(3);
+                [column[0], column[1], column[2], 1.0]
+            }
+            None => [0.0, 0.0, 0.0, 1.0],
+        };
+
+        encoder.update_constant_buffer(
+            &self.locals,
+            &FrameLocals {
+                projection: *(projection).as_ref(),
+                view: *(view).as_ref(),
+                camera_position,
+                cascade_splits,
+                time,
+                num_lights,
+            },
+        );
+        encoder.update_buffer(&self.lights, lights, 0)
+    }
+}
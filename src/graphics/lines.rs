@@ -0,0 +1,180 @@
+//! Draws coloured lines over the scene, for things like the transform gizmo
+//! which should never be hidden by what is in front of them.
+
+use ::ColorFormat;
+use gfx::{self, Bundle, CommandBuffer, Encoder, PipelineStateError, Primitive, Resources,
+          UpdateError};
+use gfx::buffer::{CreationError, Role};
+use gfx::handle::RenderTargetView;
+use gfx::memory::{Bind, Usage};
+use gfx::state::Rasterizer;
+use graphics::camera::CameraMatrices;
+use graphics::platform::{Backend, FactoryExt};
+use std::error::Error;
+use std::fmt;
+
+gfx_defines! {
+    vertex LineVertex {
+        pos: [f32; 3] = "position",
+        color: [f32; 4] = "color",
+    }
+
+    #[derive(Default)]
+    constant LineLocals {
+        view_projection: [[f32; 4]; 4] = "view_projection",
+    }
+
+    pipeline line_pipe {
+        vbuf: gfx::VertexBuffer<LineVertex> = (),
+        line_locals: gfx::ConstantBuffer<LineLocals> = "line_locals",
+        out: gfx::RenderTarget<ColorFormat> = "Target0",
+    }
+}
+
+/// The most vertices which can be drawn each frame. Lines past this are left
+/// out.
+const MAX_VERTICES: usize = 4096;
+
+const GLSL_LINE_VERT_SRC: &'static [u8] = include_bytes!("../../data/shader/glsl/line.vs");
+const GLSL_LINE_FRAG_SRC: &'static [u8] = include_bytes!("../../data/shader/glsl/line.fs");
+
+const MSL_LINE_VERT_SRC: &'static [u8] = include_bytes!("../../data/shader/msl/line.vs");
+const MSL_LINE_FRAG_SRC: &'static [u8] = include_bytes!("../../data/shader/msl/line.fs");
+
+impl LineVertex {
+    #[inline]
+    pub fn new(pos: [f32; 3], color: [f32; 4]) -> Self {
+        LineVertex { pos, color }
+    }
+}
+
+pub struct Lines<R: Resources> {
+    bundle: Bundle<R, line_pipe::Data<R>>,
+}
+
+impl<R: Resources> Lines<R> {
+    pub fn new<F: FactoryExt<R>>(
+        factory: &mut F,
+        backend: &Backend,
+        rtv: RenderTargetView<R, ColorFormat>,
+    ) -> Result<Self, LinesError> {
+        let program = if backend.is_gl() {
+            factory.link_program(GLSL_LINE_VERT_SRC, GLSL_LINE_FRAG_SRC).unwrap()
+        } else {
+            factory.link_program(MSL_LINE_VERT_SRC, MSL_LINE_FRAG_SRC).unwrap()
+        };
+
+        let pso = factory.create_pipeline_from_program(
+            &program,
+            Primitive::LineList,
+            Rasterizer::new_fill(),
+            line_pipe::new(),
+        )?;
+
+        let vbuf = factory.create_buffer::<LineVertex>(
+            MAX_VERTICES,
+            Role::Vertex,
+            Usage::Dynamic,
+            Bind::empty(),
+        )?;
+        let mut slice = gfx::Slice::new_match_vertex_buffer(&vbuf);
+        slice.end = 0;
+
+        let data = line_pipe::Data {
+            vbuf,
+            line_locals: factory.create_constant_buffer(1),
+            out: rtv,
+        };
+
+        Ok(Lines {
+            bundle: Bundle::new(slice, pso, data),
+        })
+    }
+
+    /// Draws each pair of `vertices` as a line, on top of whatever has been
+    /// drawn already.
+    pub fn encode<C: CommandBuffer<R>>(
+        &mut self,
+        encoder: &mut Encoder<R, C>,
+        camera: &CameraMatrices,
+        vertices: &[LineVertex],
+    ) -> Result<(), UpdateError<usize>> {
+        let count = vertices.len().min(MAX_VERTICES) & !1;
+        if count == 0 {
+            return Ok(());
+        }
+
+        encoder.update_buffer(&self.bundle.data.vbuf, &vertices[..count], 0)?;
+        encoder.update_constant_buffer(
+            &self.bundle.data.line_locals,
+            &LineLocals {
+                view_projection: *(camera.projection * camera.view).as_ref(),
+            },
+        );
+        self.bundle.slice.end = count as u32;
+        self.bundle.encode(encoder);
+        Ok(())
+    }
+
+    #[inline]
+    pub fn set_target(&mut self, rtv: RenderTargetView<R, ColorFormat>) {
+        self.bundle.data.out = rtv;
+    }
+}
+
+impl<R: Resources> fmt::Debug for Lines<R> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.debug_struct("Lines")
+            .field("bundle", &"Bundle(...)")
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum LinesError {
+    Pso(PipelineStateError<String>),
+    Buffer(CreationError),
+}
+
+impl fmt::Display for LinesError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            LinesError::Pso(ref e) => write!(fmtr, "{}: {}", desc, e),
+            LinesError::Buffer(ref e) => write!(fmtr, "{}: {}", desc, e),
+        }
+    }
+}
+
+impl Error for LinesError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            LinesError::Pso(_) => "There was an error creating the line pso",
+            LinesError::Buffer(_) => "The line vertex buffer could not be created",
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            LinesError::Pso(ref e) => Some(e),
+            LinesError::Buffer(ref e) => Some(e),
+        }
+    }
+}
+
+impl<'a> From<PipelineStateError<&'a str>> for LinesError {
+    #[inline]
+    fn from(e: PipelineStateError<&'a str>) -> Self {
+        LinesError::Pso(e.into())
+    }
+}
+
+impl From<CreationError> for LinesError {
+    #[inline]
+    fn from(e: CreationError) -> Self {
+        LinesError::Buffer(e)
+    }
+}
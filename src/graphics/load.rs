@@ -1,11 +1,15 @@
 use std::collections::BTreeMap;
 use std::collections::btree_map::Entry;
+use std::collections::hash_map::DefaultHasher;
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
+use std::hash::{Hash, Hasher};
 use std::io::{self, Read};
 use std::cmp::{Eq, Ord, Ordering};
-use util::{GetAssetsFolderError, get_assets_folder};
+use std::path::PathBuf;
+use config::cache_dir;
+use util::{GetAssetsFolderError, find_asset, get_assets_folder};
 use wavefront_obj::{ParseError, obj};
 use Vertex;
 
@@ -25,9 +29,7 @@ pub fn load_obj(obj_name: &str) -> Result<(Vec<Vertex>, Vec<Index>), LoadObjErro
     use wavefront_obj::obj::Primitive;
     let obj = {
         let mut obj_string = String::new();
-        let mut obj_file_name = get_assets_folder()?.to_path_buf();
-        obj_file_name.push(&format!("mesh/{}.obj", obj_name));
-        File::open(obj_file_name).and_then(|mut f| {
+        File::open(obj_path(obj_name)?).and_then(|mut f| {
             f.read_to_string(&mut obj_string)
         })?;
         obj::parse(obj_string)?
@@ -54,22 +56,45 @@ pub fn load_obj(obj_name: &str) -> Result<(Vec<Vertex>, Vec<Index>), LoadObjErro
                 norms.push(object.normals[n0 as usize]);
                 norms.push(object.normals[n2 as usize]);
             }
-            _ => {
-                println!("{:?}", s);
-                unimplemented!()
-            }
+            _ => return Err(LoadObjError::UnsupportedPrimitive),
         }
     }
 
     Ok(build_unified_buffers(&verts[..], &uvs[..], &norms[..]))
 }
 
+/// The file a mesh is loaded from, which is either given or found in the
+/// assets folder by its name.
+fn obj_path(obj_name: &str) -> Result<PathBuf, GetAssetsFolderError> {
+    if obj_name.ends_with(".obj") {
+        find_asset(obj_name)
+    } else {
+        Ok(get_assets_folder()?.join(format!("mesh/{}.obj", obj_name)))
+    }
+}
+
+/// Where the bvh over a mesh is cached, or `None` if there is nowhere to cache
+/// it. The file is named after the mesh and a hash of where it was loaded
+/// from, so that meshes with the same name in different folders do not
+/// replace each other's bvh.
+pub fn bvh_cache_path(obj_name: &str) -> Option<PathBuf> {
+    let path = obj_path(obj_name).ok()?;
+    let name = path.file_stem()?.to_str()?.to_owned();
+    // A mesh given on the command line may be relative to the working
+    // directory.
+    let mut hasher = DefaultHasher::new();
+    path.canonicalize().unwrap_or(path).hash(&mut hasher);
+    let file_name = format!("{}-{:016x}.bvh", name, hasher.finish());
+    Some(cache_dir().ok()?.join("bvh").join(file_name))
+}
+
 #[derive(Debug)]
 pub enum LoadObjError {
     Io(io::Error),
     ObjParse(ParseError),
     AssetsFolder(GetAssetsFolderError),
     NoMeshFound,
+    UnsupportedPrimitive,
 }
 
 impl From<io::Error> for LoadObjError {
@@ -100,7 +125,9 @@ impl fmt::Display for LoadObjError {
             LoadObjError::Io(ref e) => write!(fmtr, "{}: {}", self.description(), e),
             LoadObjError::ObjParse(ref e) => write!(fmtr, "{}, {:?}", self.description(), e),
             LoadObjError::AssetsFolder(ref e) => write!(fmtr, "{}, {:?}", self.description(), e),
-            LoadObjError::NoMeshFound => fmtr.pad(self.description()),
+            LoadObjError::NoMeshFound | LoadObjError::UnsupportedPrimitive => {
+                fmtr.pad(self.description())
+            }
         }
     }
 }
@@ -113,6 +140,10 @@ impl Error for LoadObjError {
             LoadObjError::ObjParse(_) => "Could not parse Obj file",
             LoadObjError::AssetsFolder(_) => "Could not get assets folder",
             LoadObjError::NoMeshFound => "Could not find a mesh in the obj file",
+            LoadObjError::UnsupportedPrimitive => {
+                "The obj file has points, lines, or faces without texture coordinates and \
+                 normals, which cannot be drawn"
+            }
         }
     }
 
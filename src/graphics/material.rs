@@ -0,0 +1,59 @@
+use ::MaterialLocals;
+
+/// A metallic-roughness material, laid out the same way as the materials
+/// exported by glTF authoring tools.
+///
+/// Each factor is multiplied with its texture, so a material without a texture
+/// uses the factor on its own. The metallic-roughness texture stores roughness
+/// in the green channel and metalness in the blue channel, and the occlusion
+/// texture is read from the red channel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Material {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    pub occlusion_strength: f32,
+    pub emissive: [f32; 3],
+    pub base_color_texture: Option<String>,
+    pub metallic_roughness_texture: Option<String>,
+    pub occlusion_texture: Option<String>,
+    pub emissive_texture: Option<String>,
+}
+
+impl Material {
+    #[inline]
+    pub fn with_base_color_texture<S: Into<String>>(texture_name: S) -> Self {
+        Material {
+            base_color_texture: Some(texture_name.into()),
+            ..Default::default()
+        }
+    }
+
+    #[inline]
+    pub fn to_locals(&self) -> MaterialLocals {
+        MaterialLocals {
+            base_color: self.base_color,
+            emissive: self.emissive,
+            metallic: self.metallic,
+            roughness: self.roughness,
+            occlusion_strength: self.occlusion_strength,
+        }
+    }
+}
+
+impl Default for Material {
+    #[inline]
+    fn default() -> Self {
+        Material {
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 0.5,
+            occlusion_strength: 1.0,
+            emissive: [0.0; 3],
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+        }
+    }
+}
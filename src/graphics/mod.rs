@@ -1,5 +1,15 @@
+pub mod bounds;
 pub mod camera;
+pub mod capture;
+pub mod environment;
 pub mod fps_counter;
+pub mod frame;
+pub mod lines;
 pub mod load;
+pub mod material;
 pub mod model;
 pub mod platform;
+pub mod shadow;
+pub mod skybox;
+pub mod target;
+pub mod transform;
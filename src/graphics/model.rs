@@ -1,35 +1,61 @@
-use ::{pipe, ColorFormat, DepthFormat, GLSL_VERT_SRC, GLSL_FRAG_SRC, MAX_LIGHTS, MSL_VERT_SRC,
-     MSL_FRAG_SRC, ShaderLight, SharedLocals, VertLocals};
-use gfx::{Bundle, CombinedError, CommandBuffer, Encoder, PipelineStateError, Primitive, Resources,
-          UpdateError};
-use gfx::handle::{DepthStencilView, RenderTargetView};
-use gfx::state::Rasterizer;
+use ::{pipe, ColorFormat, DepthFormat, GLSL_VERT_SRC, GLSL_FRAG_SRC, ModelLocals, MSL_VERT_SRC,
+     MSL_FRAG_SRC};
+use gfx::{Bundle, CombinedError, CommandBuffer, Encoder, PipelineState, PipelineStateError,
+          Primitive, Resources};
+use gfx::handle::{Buffer, DepthStencilView, RenderTargetView, ShaderResourceView};
+use gfx::state::{FrontFace, Rasterizer};
 use gfx::texture::{AaMode, Kind, Mipmap};
-use graphics::load::{load_obj, LoadObjError};
-use graphics::platform::{Backend, FactoryExt, WindowExt};
+use graphics::bounds::Aabb;
+use graphics::frame::FrameResources;
+use graphics::load::{bvh_cache_path, load_obj, LoadObjError};
+use graphics::material::Material;
+use graphics::platform::{Backend, FactoryExt};
+use graphics::shadow::{shadow_pipe, ShadowFormat, ShadowLocals};
+use graphics::transform::Transform;
 use image::{self, ImageError};
 use lazy_load::Asset;
-use na::{Matrix4, Similarity3};
+use na::{self, Matrix4};
+use physics::bvh::Bvh;
+use physics::collision::Triangle;
+use physics::ray::{Ray, RayHit};
 use std::error::Error;
 use std::fmt;
-use util::get_assets_folder;
+use std::rc::Rc;
+use util::{find_asset, GetAssetsFolderError};
+
+/// How much light a selected model gives off, on top of its material, so that
+/// it stands out.
+const HIGHLIGHT: [f32; 3] = [0.3, 0.2, 0.0];
 
 pub struct Model<R: Resources> {
     bundle: Bundle<R, pipe::Data<R>>,
-    pub similarity: Similarity3<f32>,
+    /// Draws the model while it is mirrored, which turns its triangles
+    /// inside out, so that their fronts are still the ones drawn.
+    mirrored_pso: PipelineState<R, pipe::Meta>,
+    /// The bounds of the mesh, before it is transformed.
+    bounds: Aabb,
+    /// The triangles of the mesh before it is transformed, to collide with
+    /// and to pick. It is shared with the scene's collision mesh.
+    bvh: Rc<Bvh>,
+    pub transform: Transform,
+    pub material: Material,
 }
 
 impl<R: Resources> Model<R> {
     pub fn load<F: FactoryExt<R>>(
         factory: &mut F,
         backend: &Backend,
+        frame: &FrameResources<R>,
         rtv: RenderTargetView<R, ColorFormat>,
         dsv: DepthStencilView<R, DepthFormat>,
         model_name: &str,
-        texture_name: &str,
+        material: Material,
+        cache_bvh: bool,
     ) -> Result<Self, ModelLoadError> {
-        let similarity = Similarity3::from_scaling(1.0);
-        let bundle = {
+        let transform = Transform::default();
+        let mut bounds = Aabb::new(na::origin(), na::origin());
+        let mut bvh = Bvh::default();
+        let (bundle, mirrored_pso) = {
             let program = if backend.is_gl() {
                 factory.link_program(GLSL_VERT_SRC, GLSL_FRAG_SRC).unwrap()
             } else {
@@ -42,92 +68,194 @@ impl<R: Resources> Model<R> {
                 Rasterizer::new_fill().with_cull_back(),
                 pipe::new(),
             )?;
+            let mirrored_pso = factory.create_pipeline_from_program(
+                &program,
+                Primitive::TriangleList,
+                Rasterizer {
+                    front_face: FrontFace::Clockwise,
+                    ..Rasterizer::new_fill().with_cull_back()
+                },
+                pipe::new(),
+            )?;
 
-            let (_, srv) = {
-                let mut img_path = get_assets_folder().unwrap().to_path_buf();
-                img_path.push(texture_name);
-                let img = image::open(img_path)?.to_rgba();
-                let (iw, ih) = img.dimensions();
-                let kind = Kind::D2(iw as u16, ih as u16, AaMode::Single);
-                factory.create_texture_immutable_u8::<ColorFormat>(
-                    kind,
-                    Mipmap::Provided,
-                    &[&img],
-                )?
-            };
+            const WHITE: [u8; 4] = [255, 255, 255, 255];
+            let base_color_srv =
+                load_texture(factory, material.base_color_texture.as_ref(), WHITE)?;
+            let metallic_roughness_srv =
+                load_texture(factory, material.metallic_roughness_texture.as_ref(), WHITE)?;
+            let occlusion_srv = load_texture(factory, material.occlusion_texture.as_ref(), WHITE)?;
+            let emissive_srv = load_texture(factory, material.emissive_texture.as_ref(), WHITE)?;
 
             let sampler = factory.create_sampler_linear();
 
             let (verts, inds) = load_obj(model_name)?;
+            bounds = Aabb::from_vertices(&verts).unwrap_or(bounds);
+            let triangles = Triangle::from_mesh(&verts, &inds);
+            bvh = match bvh_cache_path(model_name) {
+                Some(ref path) if cache_bvh => Bvh::load_or_build(triangles, path),
+                _ => Bvh::new(triangles),
+            };
             let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&verts[..], &inds[..]);
             let data = pipe::Data {
                 vbuf,
-                vert_locals: factory.create_constant_buffer(1),
-                shared_locals: factory.create_constant_buffer(1),
-                lights: factory.create_constant_buffer(MAX_LIGHTS),
-                main_texture: (srv, sampler),
+                model_locals: factory.create_constant_buffer(1),
+                frame_locals: frame.locals.clone(),
+                material_locals: factory.create_constant_buffer(1),
+                lights: frame.lights.clone(),
+                shadow_transforms: frame.shadow_transforms.clone(),
+                shadow_map: frame.shadow_map.clone(),
+                environment_locals: frame.environment.clone(),
+                specular_map: frame.specular_map.clone(),
+                main_texture: (base_color_srv, sampler.clone()),
+                metallic_roughness_texture: (metallic_roughness_srv, sampler.clone()),
+                occlusion_texture: (occlusion_srv, sampler.clone()),
+                emissive_texture: (emissive_srv, sampler),
                 out: rtv,
                 main_depth: dsv,
             };
 
-            Bundle::new(slice, pso, data)
+            (Bundle::new(slice, pso, data), mirrored_pso)
         };
-        Ok(Model { bundle, similarity })
+        Ok(Model {
+            bundle,
+            mirrored_pso,
+            bounds,
+            bvh: Rc::new(bvh),
+            transform,
+            material,
+        })
+    }
+
+    /// The bounds of the model where it is placed.
+    #[inline]
+    pub fn bounds(&self) -> Aabb {
+        self.bounds.transformed(&self.transform)
     }
 
+    /// The hierarchy over the triangles of the model, before it is
+    /// transformed.
     #[inline]
-    pub fn encode<C: CommandBuffer<R>>(&self, encoder: &mut Encoder<R, C>) {
-        self.bundle.encode(encoder)
+    pub fn bvh(&self) -> Rc<Bvh> {
+        self.bvh.clone()
+    }
+
+    /// The nearest triangle of the model which the ray hits, if it hits any.
+    /// The distance is along the ray as it was given.
+    pub fn cast_ray(&self, ray: &Ray) -> Option<RayHit> {
+        // The bvh is in the model's own space, so the ray is moved there.
+        self.bvh.cast_ray(&ray.inverse_transformed(&self.transform))
     }
 
+    /// Draws the model placed at `transform`, which must be the one its
+    /// locals were last updated with, to wind its triangles the right way.
     #[inline]
-    pub fn update_matrices<C: CommandBuffer<R>>(
+    pub fn encode<C: CommandBuffer<R>>(&self, encoder: &mut Encoder<R, C>, transform: &Transform) {
+        if transform.is_mirrored() {
+            encoder.draw(&self.bundle.slice, &self.mirrored_pso, &self.bundle.data);
+        } else {
+            self.bundle.encode(encoder)
+        }
+    }
+
+    /// Draws the model's depth with the shadow pipeline, reusing the model's
+    /// vertex buffer and locals.
+    pub fn encode_shadow<C: CommandBuffer<R>>(
         &self,
         encoder: &mut Encoder<R, C>,
-        view_matrix: &Matrix4<f32>,
-        projection_matrix: &Matrix4<f32>,
+        pso: &PipelineState<R, shadow_pipe::Meta>,
+        shadow_locals: &Buffer<R, ShadowLocals>,
+        out_depth: &DepthStencilView<R, ShadowFormat>,
     ) {
-        let model_matrix = self.similarity.to_homogeneous();
-        encoder.update_constant_buffer(
-            &self.bundle.data.vert_locals,
-            &VertLocals {
-                model: *(model_matrix).as_ref(),
-                view: *(view_matrix).as_ref(),
-                projection: *(projection_matrix).as_ref(),
-            },
-        );
+        let data = shadow_pipe::Data {
+            vbuf: self.bundle.data.vbuf.clone(),
+            model_locals: self.bundle.data.model_locals.clone(),
+            shadow_locals: shadow_locals.clone(),
+            out_depth: out_depth.clone(),
+        };
+        encoder.draw(&self.bundle.slice, pso, &data);
     }
 
+    /// Writes the model's uniforms, placing it at `transform` rather than at
+    /// its own transform so that it can be drawn between two simulation steps.
     #[inline]
-    pub fn update_lights<C: CommandBuffer<R>>(
+    pub fn update_locals<C: CommandBuffer<R>>(
         &self,
         encoder: &mut Encoder<R, C>,
-        lights: &[ShaderLight],
-    ) -> Result<(), UpdateError<usize>> {
-        let num_lights = lights.len() as u32;
-        assert!(num_lights < MAX_LIGHTS as u32);
+        transform: &Transform,
+        is_highlighted: bool,
+    ) {
+        let model_matrix = transform.to_homogeneous();
+        let normal_matrix = model_matrix
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity)
+            .transpose();
         encoder.update_constant_buffer(
-            &self.bundle.data.shared_locals,
-            &SharedLocals { num_lights },
+            &self.bundle.data.model_locals,
+            &ModelLocals {
+                model: *(model_matrix).as_ref(),
+                normal: *(normal_matrix).as_ref(),
+            },
         );
-        encoder.update_buffer(&self.bundle.data.lights, &lights, 0)
+        let mut material = self.material.to_locals();
+        if is_highlighted {
+            for (emissive, highlight) in material.emissive.iter_mut().zip(&HIGHLIGHT) {
+                *emissive += highlight;
+            }
+        }
+        encoder.update_constant_buffer(&self.bundle.data.material_locals, &material);
     }
 
+    /// Draws into different targets, such as after they were recreated at a
+    /// new size.
     #[inline]
-    pub fn update_views<W: WindowExt<R>>(&mut self, window: &W) {
-        window.update_views(&mut self.bundle.data.out, &mut self.bundle.data.main_depth);
+    pub fn set_targets(
+        &mut self,
+        rtv: RenderTargetView<R, ColorFormat>,
+        dsv: DepthStencilView<R, DepthFormat>,
+    ) {
+        self.bundle.data.out = rtv;
+        self.bundle.data.main_depth = dsv;
     }
 }
 
+fn load_texture<R, F>(
+    factory: &mut F,
+    texture_name: Option<&String>,
+    fallback: [u8; 4],
+) -> Result<ShaderResourceView<R, [f32; 4]>, ModelLoadError>
+where
+    R: Resources,
+    F: FactoryExt<R>,
+{
+    let (_, srv) = match texture_name {
+        Some(texture_name) => {
+            let img = image::open(find_asset(texture_name)?)?.to_rgba();
+            let (iw, ih) = img.dimensions();
+            let kind = Kind::D2(iw as u16, ih as u16, AaMode::Single);
+            factory.create_texture_immutable_u8::<ColorFormat>(kind, Mipmap::Provided, &[&img])?
+        }
+        None => {
+            let kind = Kind::D2(1, 1, AaMode::Single);
+            factory.create_texture_immutable_u8::<ColorFormat>(
+                kind,
+                Mipmap::Provided,
+                &[&fallback[..]],
+            )?
+        }
+    };
+    Ok(srv)
+}
+
 #[allow(dead_code)]
 #[derive(Debug)]
 pub struct ModelLoadParams<'a, R: Resources + 'a, F: FactoryExt<R> + 'a> {
     factory: &'a mut F,
     backend: &'a Backend,
+    frame: &'a FrameResources<R>,
     rtv: RenderTargetView<R, ColorFormat>,
     dsv: DepthStencilView<R, DepthFormat>,
     model_name: &'a str,
-    texture_name: &'a str,
+    material: Material,
 }
 
 /*
@@ -145,7 +273,10 @@ impl<R: Resources> fmt::Debug for Model<R> {
     fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
         fmtr.debug_struct("Model")
             .field("bundle", &"Bundle(...)")
-            .field("similarity", &self.similarity)
+            .field("bounds", &self.bounds)
+            .field("triangles", &self.bvh.triangles().len())
+            .field("transform", &self.transform)
+            .field("material", &self.material)
             .finish()
     }
 }
@@ -156,6 +287,7 @@ pub enum ModelLoadError {
     Pso(PipelineStateError<String>),
     GfxTextureView(CombinedError),
     Image(ImageError),
+    AssetsFolder(GetAssetsFolderError),
 }
 
 impl fmt::Display for ModelLoadError {
@@ -167,6 +299,7 @@ impl fmt::Display for ModelLoadError {
             ModelLoadError::Pso(ref e) => write!(fmtr, "{}: {}", desc, e),
             ModelLoadError::GfxTextureView(ref e) => write!(fmtr, "{}: {}", desc, e),
             ModelLoadError::Image(ref e) => write!(fmtr, "{}: {}", desc, e),
+            ModelLoadError::AssetsFolder(ref e) => write!(fmtr, "{}: {}", desc, e),
         }
     }
 }
@@ -183,6 +316,7 @@ impl Error for ModelLoadError {
             ModelLoadError::Image(_) => {
                 "An error occurred while loading the texture image from disk"
             }
+            ModelLoadError::AssetsFolder(_) => "Could not find the assets folder for a texture",
         }
     }
 
@@ -193,10 +327,18 @@ impl Error for ModelLoadError {
             ModelLoadError::Pso(ref e) => Some(e),
             ModelLoadError::GfxTextureView(ref e) => Some(e),
             ModelLoadError::Image(ref e) => Some(e),
+            ModelLoadError::AssetsFolder(ref e) => Some(e),
         }
     }
 }
 
+impl From<GetAssetsFolderError> for ModelLoadError {
+    #[inline]
+    fn from(e: GetAssetsFolderError) -> Self {
+        ModelLoadError::AssetsFolder(e)
+    }
+}
+
 impl From<LoadObjError> for ModelLoadError {
     #[inline]
     fn from(e: LoadObjError) -> Self {
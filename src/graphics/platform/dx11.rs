@@ -1,4 +1,4 @@
-use super::{Backend, ContextBuilder, FactoryExt, WindowExt};
+use super::{Backend, ContextBuilder, FactoryExt, NativeWindow, WindowExt};
 use gfx::{CombinedError, Encoder};
 use gfx::format::{DepthFormat, Formatted, RenderFormat, TextureChannel, TextureSurface};
 use gfx::handle::{DepthStencilView, RenderTargetView};
@@ -17,6 +17,13 @@ impl WindowExt<Resources> for DxgiWindow {
     }
 }
 
+impl NativeWindow for DxgiWindow {
+    #[inline]
+    fn window(&self) -> &winit::Window {
+        &self.inner
+    }
+}
+
 impl FactoryExt<Resources> for Factory {
     type CommandBuffer = CommandBuffer<CommandList>;
     #[inline]
@@ -1,10 +1,14 @@
-use super::{Backend, ContextBuilder, FactoryExt, WindowExt};
-use gfx_device_gl::{CommandBuffer, Device, Factory, Resources};
+use super::{Backend, ContextBuilder, FactoryExt, NativeWindow, WindowExt};
+use gfx_device_gl::{self, CommandBuffer, Device, Factory, Resources};
 use gfx_window_glutin;
 use gfx::Encoder;
 use gfx::format::{DepthFormat, RenderFormat};
 use gfx::handle::{DepthStencilView, RenderTargetView};
-use glutin::{ContextBuilder as GlutinContextBuilder, ContextError, GlContext, GlWindow};
+use glutin::{Api, ContextBuilder as GlutinContextBuilder, ContextError, CreationError, GlContext,
+             GlRequest, GlWindow, HeadlessContext, HeadlessRendererBuilder};
+use num::{cast, NumCast, Zero};
+use std::error::Error;
+use std::fmt;
 use void::Void;
 use winit;
 
@@ -29,6 +33,13 @@ impl WindowExt<Resources> for GlWindow {
     }
 }
 
+impl NativeWindow for GlWindow {
+    #[inline]
+    fn window(&self) -> &winit::Window {
+        GlWindow::window(self)
+    }
+}
+
 impl FactoryExt<Resources> for Factory {
     type CommandBuffer = CommandBuffer;
 
@@ -65,3 +76,129 @@ impl<'a> Into<GlutinContextBuilder<'a>> for ContextBuilder {
         cb.with_vsync(self.is_vsync_enabled)
     }
 }
+
+/// Stands in for a window when rendering without a display. There is no swap
+/// chain, so rendering goes to an offscreen target and there is nothing to
+/// present.
+pub struct HeadlessWindow {
+    context: HeadlessContext,
+    dimensions: (u32, u32),
+}
+
+impl HeadlessWindow {
+    #[inline]
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+}
+
+impl fmt::Debug for HeadlessWindow {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.debug_struct("HeadlessWindow")
+            .field("dimensions", &self.dimensions)
+            .finish()
+    }
+}
+
+impl WindowExt<Resources> for HeadlessWindow {
+    type SwapBuffersError = ContextError;
+
+    #[inline]
+    fn swap_buffers(&self) -> Result<(), Self::SwapBuffersError> {
+        Ok(())
+    }
+}
+
+impl ::WindowExt for HeadlessWindow {
+    #[inline]
+    fn center_cursor(&self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn hide_and_grab_cursor(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    #[inline]
+    fn release_cursor(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn windowext_get_inner_size<N: NumCast + Zero + Default>(&self) -> (N, N) {
+        let (w, h) = self.dimensions;
+        (
+            cast(w).unwrap_or(Zero::zero()),
+            cast(h).unwrap_or(Zero::zero()),
+        )
+    }
+}
+
+/// Creates an OpenGL context which is not attached to any window. On Linux
+/// this goes through OSMesa, so it works on machines without a GPU or a
+/// display server.
+pub fn launch_gl_headless(
+    width: u32,
+    height: u32,
+) -> Result<(Backend, HeadlessWindow, Device, Factory), HeadlessError> {
+    let context = HeadlessRendererBuilder::new(width, height)
+        .with_gl(GlRequest::Specific(Api::OpenGl, (3, 2)))
+        .build()?;
+    unsafe { context.make_current()? };
+
+    let (device, factory) = gfx_device_gl::create(|s| context.get_proc_address(s) as *const _);
+    let window = HeadlessWindow {
+        context,
+        dimensions: (width, height),
+    };
+    Ok((Backend::Gl, window, device, factory))
+}
+
+#[derive(Debug)]
+pub enum HeadlessError {
+    Creation(CreationError),
+    Context(ContextError),
+}
+
+impl From<CreationError> for HeadlessError {
+    #[inline]
+    fn from(e: CreationError) -> Self {
+        HeadlessError::Creation(e)
+    }
+}
+
+impl From<ContextError> for HeadlessError {
+    #[inline]
+    fn from(e: ContextError) -> Self {
+        HeadlessError::Context(e)
+    }
+}
+
+impl fmt::Display for HeadlessError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            HeadlessError::Creation(ref e) => write!(fmtr, "{}: {}", desc, e),
+            HeadlessError::Context(ref e) => write!(fmtr, "{}: {}", desc, e),
+        }
+    }
+}
+
+impl Error for HeadlessError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            HeadlessError::Creation(_) => "Could not create a headless OpenGL context",
+            HeadlessError::Context(_) => "Could not make the headless context current",
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            HeadlessError::Creation(ref e) => Some(e),
+            HeadlessError::Context(ref e) => Some(e),
+        }
+    }
+}
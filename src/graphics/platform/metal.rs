@@ -1,4 +1,4 @@
-use super::{Backend, ContextBuilder, FactoryExt, WindowExt};
+use super::{Backend, ContextBuilder, FactoryExt, NativeWindow, WindowExt};
 use winit;
 use gfx::{CombinedError, Encoder, Factory};
 use gfx::format::{DepthFormat, Formatted, RenderFormat, TextureChannel, TextureSurface};
@@ -41,6 +41,13 @@ impl WindowExt<Resources> for MetalWindow {
     }
 }
 
+impl NativeWindow for MetalWindow {
+    #[inline]
+    fn window(&self) -> &winit::Window {
+        &*self
+    }
+}
+
 impl FactoryExt<Resources> for MetalFactory {
     type CommandBuffer = CommandBuffer;
     fn create_encoder(&mut self) -> Encoder<Resources, Self::CommandBuffer> {
@@ -9,17 +9,20 @@ use std::error::Error;
 #[cfg(feature = "gl")]
 mod gl;
 #[cfg(feature = "gl")]
-pub use self::gl::launch_gl;
+pub use self::gl::{launch_gl, launch_gl_headless, HeadlessError, HeadlessWindow};
+
+pub mod software;
+pub use self::software::{launch_software, SoftwareWindow};
 
 #[cfg(all(target_os = "macos", feature = "metal"))]
 mod metal;
 #[cfg(all(target_os = "macos", feature = "metal"))]
-pub use self::metal::launch_metal as launch_native;
+pub use self::metal::{launch_metal, launch_metal as launch_native};
 
 #[cfg(all(target_os = "windows", feature = "dx11"))]
 mod dx11;
 #[cfg(all(target_os = "windows", feature = "dx11"))]
-pub use self::dx11::launch_dx11 as launch_native;
+pub use self::dx11::{launch_dx11, launch_dx11 as launch_native};
 
 #[cfg(all(feature = "gl", not(any(feature = "metal", feature = "dx11"))))]
 pub use self::gl::launch_gl as launch_native;
@@ -61,6 +64,11 @@ pub trait WindowExt<R: Resources> {
     }
 }
 
+/// A window which is backed by a `winit` window.
+pub trait NativeWindow {
+    fn window(&self) -> &::winit::Window;
+}
+
 pub trait FactoryExt<R: Resources>: traits::FactoryExt<R> {
     type CommandBuffer: CommandBuffer<R>;
     fn create_encoder(&mut self) -> Encoder<R, Self::CommandBuffer>;
@@ -72,6 +80,7 @@ pub enum Backend {
     Metal,
     D3d11,
     Vulkan,
+    Software,
     #[doc(hidden)]
     __NonexhaustiveCheck,
 }
@@ -97,6 +106,14 @@ impl Backend {
         }
     }
 
+    pub fn is_software(&self) -> bool {
+        if let Backend::Software = *self {
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn select<'a>(&self, shaders: Shaders<'a>) -> ShaderPipeline<'a> {
         use self::Backend::*;
         match *self {
@@ -0,0 +1,348 @@
+//! A small renderer which runs entirely on the cpu, for tests and machines
+//! without a graphics device. It draws the same meshes and materials as the
+//! gfx pipelines, and is deterministic on every platform, but it is far too
+//! slow for interactive use.
+//!
+//! It is a reduced reference renderer rather than a gfx backend: it has its
+//! own scene and loop in `run_software`, instead of going through
+//! `FactoryExt`, and it draws no shadows, multisampling or overlays. Its
+//! images are only ever compared with its own golden images, never with the
+//! ones from the gpu.
+
+mod raster;
+mod shading;
+
+pub use self::raster::{ClipVertex, Framebuffer, Varyings, VARYING_COUNT};
+pub use self::shading::{Environment, MaterialTextures, SurfaceShader, Texture};
+
+use ::{Light, Vertex};
+use graphics::camera::CameraMatrices;
+use graphics::environment::{load_cube_faces, EnvironmentMap, LoadEnvironmentError};
+use graphics::load::{load_obj, Index, LoadObjError};
+use graphics::material::Material;
+use graphics::skybox::Background;
+use graphics::transform::Transform;
+use image::{self, ImageError, RgbaImage};
+use na::{Matrix4, Vector3, Vector4};
+use num::{cast, NumCast, Zero};
+use std::error::Error;
+use std::fmt;
+use super::Backend;
+use util::{find_asset, GetAssetsFolderError};
+
+/// Stands in for a window, with the frame kept in memory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SoftwareWindow {
+    framebuffer: Framebuffer,
+}
+
+impl SoftwareWindow {
+    #[inline]
+    pub fn framebuffer(&self) -> &Framebuffer {
+        &self.framebuffer
+    }
+
+    #[inline]
+    pub fn framebuffer_mut(&mut self) -> &mut Framebuffer {
+        &mut self.framebuffer
+    }
+
+    /// Copies the current frame into an image.
+    pub fn to_image(&self) -> RgbaImage {
+        let (width, height) = self.framebuffer.dimensions();
+        let bytes = self.framebuffer
+            .color()
+            .iter()
+            .flat_map(|pixel| pixel.iter().cloned())
+            .collect();
+        RgbaImage::from_raw(width as u32, height as u32, bytes)
+            .expect("The framebuffer is exactly the size of the image")
+    }
+}
+
+impl ::WindowExt for SoftwareWindow {
+    #[inline]
+    fn center_cursor(&self) -> Result<(), ()> {
+        Ok(())
+    }
+
+    #[inline]
+    fn hide_and_grab_cursor(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    #[inline]
+    fn release_cursor(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    fn windowext_get_inner_size<N: NumCast + Zero + Default>(&self) -> (N, N) {
+        let (w, h) = self.framebuffer.dimensions();
+        (
+            cast(w).unwrap_or(Zero::zero()),
+            cast(h).unwrap_or(Zero::zero()),
+        )
+    }
+}
+
+pub fn launch_software(width: u32, height: u32) -> (Backend, SoftwareWindow) {
+    let window = SoftwareWindow {
+        framebuffer: Framebuffer::new(width as usize, height as usize),
+    };
+    (Backend::Software, window)
+}
+
+/// The software counterpart of `Model`.
+#[derive(Clone, Debug)]
+pub struct SoftwareModel {
+    vertices: Vec<Vertex>,
+    indices: Vec<Index>,
+    textures: MaterialTextures,
+    pub transform: Transform,
+    pub material: Material,
+}
+
+impl SoftwareModel {
+    pub fn load(model_name: &str, material: Material) -> Result<Self, SoftwareModelError> {
+        let (vertices, indices) = load_obj(model_name)?;
+        let textures = MaterialTextures {
+            base_color: load_texture(material.base_color_texture.as_ref())?,
+            metallic_roughness: load_texture(material.metallic_roughness_texture.as_ref())?,
+            occlusion: load_texture(material.occlusion_texture.as_ref())?,
+            emissive: load_texture(material.emissive_texture.as_ref())?,
+        };
+        Ok(SoftwareModel {
+            vertices,
+            indices,
+            textures,
+            transform: Transform::default(),
+            material,
+        })
+    }
+
+    fn draw(
+        &self,
+        framebuffer: &mut Framebuffer,
+        view_projection: &Matrix4<f32>,
+        shader: &SurfaceShader,
+    ) {
+        let model = self.transform.to_homogeneous();
+        let normal_matrix = model.try_inverse().unwrap_or_else(Matrix4::identity).transpose();
+        let mvp = view_projection * model;
+
+        let clip_vertices = self.vertices
+            .iter()
+            .map(|v| {
+                let position = Vector4::new(v.pos[0], v.pos[1], v.pos[2], 1.0);
+                let world = model * position;
+                let normal = normal_matrix * Vector4::new(v.normal[0], v.normal[1], v.normal[2], 0.0);
+                ClipVertex {
+                    position: mvp * position,
+                    varyings: [
+                        world.x,
+                        world.y,
+                        world.z,
+                        normal.x,
+                        normal.y,
+                        normal.z,
+                        v.uv[0],
+                        v.uv[1],
+                    ],
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Mirroring the model turns its triangles inside out, so they are
+        // wound the other way to keep their fronts facing out.
+        let order = if self.transform.is_mirrored() { [0, 2, 1] } else { [0, 1, 2] };
+        let mut shade = |varyings: &Varyings| shader.shade(varyings);
+        for triangle in self.indices.chunks(3) {
+            if triangle.len() < 3 {
+                break;
+            }
+            let triangle = [
+                clip_vertices[triangle[order[0]] as usize],
+                clip_vertices[triangle[order[1]] as usize],
+                clip_vertices[triangle[order[2]] as usize],
+            ];
+            framebuffer.draw_triangle(&triangle, true, &mut shade);
+        }
+    }
+}
+
+fn load_texture(texture_name: Option<&String>) -> Result<Option<Texture>, SoftwareModelError> {
+    match texture_name {
+        Some(texture_name) => {
+            let img = image::open(find_asset(texture_name)?)?.to_rgba();
+            Ok(Some(Texture::new(img)))
+        }
+        None => Ok(None),
+    }
+}
+
+/// The software counterpart of the scene in `main`.
+#[derive(Clone, Debug)]
+pub struct SoftwareScene {
+    pub models: Vec<SoftwareModel>,
+    pub lights: Vec<Light>,
+    background: Background,
+    background_map: Option<EnvironmentMap>,
+    environment: Environment,
+}
+
+impl SoftwareScene {
+    pub fn new(
+        models: Vec<SoftwareModel>,
+        lights: Vec<Light>,
+        background: Background,
+        environment: &EnvironmentMap,
+        intensity: f32,
+    ) -> Result<Self, LoadEnvironmentError> {
+        let background_map = match background {
+            Background::Equirectangular(ref name) => Some(EnvironmentMap::load(name)?),
+            Background::CubeMap(ref faces) => {
+                Some(EnvironmentMap::from_cube_faces(&load_cube_faces(faces)?))
+            }
+            _ => None,
+        };
+        Ok(SoftwareScene {
+            models,
+            lights,
+            background,
+            background_map,
+            environment: Environment::new(environment, intensity),
+        })
+    }
+
+    #[inline]
+    pub fn clear_color(&self) -> [f32; 4] {
+        self.background.clear_color()
+    }
+
+    pub fn render(&self, window: &mut SoftwareWindow, camera: &CameraMatrices) {
+        let framebuffer = &mut window.framebuffer;
+        framebuffer.clear(self.clear_color());
+        framebuffer.clear_depth(1.0);
+        self.draw_background(framebuffer, camera);
+
+        let camera_position = camera.view
+            .try_inverse()
+            .map_or(Vector3::zeros(), |inverse| {
+                let column = inverse.column(3);
+                Vector3::new(column[0], column[1], column[2])
+            });
+        let view_projection = camera.projection * camera.view;
+
+        for model in &self.models {
+            let shader = SurfaceShader {
+                material: &model.material,
+                textures: &model.textures,
+                lights: &self.lights,
+                environment: &self.environment,
+                camera_position,
+            };
+            model.draw(framebuffer, &view_projection, &shader);
+        }
+    }
+
+    /// Shades every pixel with the direction it looks in, like the skybox
+    /// pipeline. The background is drawn first rather than last, since the cpu
+    /// gains nothing from skipping the covered pixels.
+    fn draw_background(&self, framebuffer: &mut Framebuffer, camera: &CameraMatrices) {
+        if let Background::Color(_) = self.background {
+            return;
+        }
+
+        let mut rotation = camera.view;
+        rotation[(0, 3)] = 0.0;
+        rotation[(1, 3)] = 0.0;
+        rotation[(2, 3)] = 0.0;
+        let inverse_view_projection = (camera.projection * rotation)
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+
+        let (width, height) = framebuffer.dimensions();
+        for y in 0..height {
+            for x in 0..width {
+                let ndc_x = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+                let ndc_y = 1.0 - (y as f32 + 0.5) / height as f32 * 2.0;
+                let unprojected = inverse_view_projection * Vector4::new(ndc_x, ndc_y, 1.0, 1.0);
+                let direction = Vector3::new(unprojected.x, unprojected.y, unprojected.z)
+                    .normalize();
+                let color = shading::shade_sky(
+                    &self.background,
+                    self.background_map.as_ref(),
+                    self.environment.intensity(),
+                    &direction,
+                );
+                if let Some(color) = color {
+                    let convert = |c: f32| (c.max(0.0).min(1.0) * 255.0 + 0.5) as u8;
+                    let pixel = [convert(color[0]), convert(color[1]), convert(color[2]), 255];
+                    framebuffer.set_pixel(x, y, pixel);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SoftwareModelError {
+    Obj(LoadObjError),
+    Image(ImageError),
+    AssetsFolder(GetAssetsFolderError),
+}
+
+impl From<LoadObjError> for SoftwareModelError {
+    #[inline]
+    fn from(e: LoadObjError) -> Self {
+        SoftwareModelError::Obj(e)
+    }
+}
+
+impl From<ImageError> for SoftwareModelError {
+    #[inline]
+    fn from(e: ImageError) -> Self {
+        SoftwareModelError::Image(e)
+    }
+}
+
+impl From<GetAssetsFolderError> for SoftwareModelError {
+    #[inline]
+    fn from(e: GetAssetsFolderError) -> Self {
+        SoftwareModelError::AssetsFolder(e)
+    }
+}
+
+impl fmt::Display for SoftwareModelError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            SoftwareModelError::Obj(ref e) => write!(fmtr, "{}: {}", desc, e),
+            SoftwareModelError::Image(ref e) => write!(fmtr, "{}: {}", desc, e),
+            SoftwareModelError::AssetsFolder(ref e) => write!(fmtr, "{}: {}", desc, e),
+        }
+    }
+}
+
+impl Error for SoftwareModelError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            SoftwareModelError::Obj(_) => "An error occurred while loading the obj file",
+            SoftwareModelError::Image(_) => "An error occurred while loading a texture",
+            SoftwareModelError::AssetsFolder(_) => {
+                "Could not find the assets folder for a texture"
+            }
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            SoftwareModelError::Obj(ref e) => Some(e),
+            SoftwareModelError::Image(ref e) => Some(e),
+            SoftwareModelError::AssetsFolder(ref e) => Some(e),
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use na::Vector4;
+
+/// The number of values interpolated across a triangle: the world position,
+/// the world normal and the texture coordinates.
+pub const VARYING_COUNT: usize = 8;
+
+pub type Varyings = [f32; VARYING_COUNT];
+
+/// A vertex after the vertex stage, in clip space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClipVertex {
+    pub position: Vector4<f32>,
+    pub varyings: Varyings,
+}
+
+impl ClipVertex {
+    fn lerp(&self, other: &ClipVertex, t: f32) -> ClipVertex {
+        let mut varyings = [0.0; VARYING_COUNT];
+        for i in 0..VARYING_COUNT {
+            varyings[i] = self.varyings[i] + (other.varyings[i] - self.varyings[i]) * t;
+        }
+        ClipVertex {
+            position: self.position + (other.position - self.position) * t,
+            varyings,
+        }
+    }
+
+    /// The signed distance to the near plane, which is positive in front of
+    /// it. As with OpenGL, the near plane is where `z == -w`.
+    #[inline]
+    fn near_distance(&self) -> f32 {
+        self.position.z + self.position.w
+    }
+}
+
+/// A colour and depth buffer, stored top row first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Framebuffer {
+    width: usize,
+    height: usize,
+    color: Vec<[u8; 4]>,
+    depth: Vec<f32>,
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Framebuffer {
+            width,
+            height,
+            color: vec![[0, 0, 0, 255]; width * height],
+            depth: vec![1.0; width * height],
+        }
+    }
+
+    #[inline]
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.width, self.height)
+    }
+
+    #[inline]
+    pub fn color(&self) -> &[[u8; 4]] {
+        &self.color
+    }
+
+    #[inline]
+    pub fn depth(&self) -> &[f32] {
+        &self.depth
+    }
+
+    #[inline]
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        self.color[y * self.width + x]
+    }
+
+    #[inline]
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        self.color[y * self.width + x] = color;
+    }
+
+    pub fn clear(&mut self, color: [f32; 4]) {
+        let color = to_unorm(color);
+        for pixel in &mut self.color {
+            *pixel = color;
+        }
+    }
+
+    pub fn clear_depth(&mut self, depth: f32) {
+        for d in &mut self.depth {
+            *d = depth;
+        }
+    }
+
+    /// Clips, culls and fills a triangle. The shader is given the
+    /// perspective-correct varyings of each covered pixel which passes the
+    /// depth test, and returns the colour to store, from 0 to 1.
+    ///
+    /// Triangles are front facing when they are counter-clockwise on screen,
+    /// like the default in gfx, and pixels pass the depth test when they are
+    /// nearer than or as near as what is already there.
+    pub fn draw_triangle<S>(&mut self, triangle: &[ClipVertex; 3], cull_back: bool, shader: &mut S)
+    where
+        S: FnMut(&Varyings) -> [f32; 4],
+    {
+        let polygon = clip_near(triangle);
+        for i in 1..polygon.len().saturating_sub(1) {
+            self.fill([&polygon[0], &polygon[i], &polygon[i + 1]], cull_back, shader);
+        }
+    }
+
+    fn fill<S>(&mut self, vertices: [&ClipVertex; 3], cull_back: bool, shader: &mut S)
+    where
+        S: FnMut(&Varyings) -> [f32; 4],
+    {
+        let (width, height) = (self.width as f32, self.height as f32);
+
+        // Window coordinates, with y pointing down the image.
+        let mut screen = [[0.0f32; 3]; 3];
+        let mut inverse_w = [0.0f32; 3];
+        for (i, v) in vertices.iter().enumerate() {
+            let w = v.position.w;
+            inverse_w[i] = 1.0 / w;
+            screen[i] = [
+                (v.position.x / w * 0.5 + 0.5) * width,
+                (0.5 - v.position.y / w * 0.5) * height,
+                v.position.z / w * 0.5 + 0.5,
+            ];
+        }
+
+        // Flipping y turns counter-clockwise triangles clockwise, so front
+        // faces have a negative area here.
+        let area = edge(&screen[0], &screen[1], &screen[2]);
+        if area == 0.0 || (cull_back && area > 0.0) {
+            return;
+        }
+
+        let min_x = screen.iter().map(|p| p[0]).fold(width, f32::min).max(0.0) as usize;
+        let max_x = screen.iter().map(|p| p[0]).fold(0.0, f32::max).min(width - 1.0) as usize;
+        let min_y = screen.iter().map(|p| p[1]).fold(height, f32::min).max(0.0) as usize;
+        let max_y = screen.iter().map(|p| p[1]).fold(0.0, f32::max).min(height - 1.0) as usize;
+
+        for y in min_y..max_y + 1 {
+            for x in min_x..max_x + 1 {
+                let p = [x as f32 + 0.5, y as f32 + 0.5, 0.0];
+                let b = [
+                    edge(&screen[1], &screen[2], &p) / area,
+                    edge(&screen[2], &screen[0], &p) / area,
+                    edge(&screen[0], &screen[1], &p) / area,
+                ];
+                if b[0] < 0.0 || b[1] < 0.0 || b[2] < 0.0 {
+                    continue;
+                }
+
+                let depth = b[0] * screen[0][2] + b[1] * screen[1][2] + b[2] * screen[2][2];
+                let index = y * self.width + x;
+                if depth > self.depth[index] || depth > 1.0 {
+                    continue;
+                }
+
+                let weights = [b[0] * inverse_w[0], b[1] * inverse_w[1], b[2] * inverse_w[2]];
+                let total = weights[0] + weights[1] + weights[2];
+                let mut varyings = [0.0; VARYING_COUNT];
+                for i in 0..VARYING_COUNT {
+                    varyings[i] = (weights[0] * vertices[0].varyings[i] +
+                                   weights[1] * vertices[1].varyings[i] +
+                                   weights[2] * vertices[2].varyings[i]) / total;
+                }
+
+                self.depth[index] = depth;
+                self.color[index] = to_unorm(shader(&varyings));
+            }
+        }
+    }
+}
+
+#[inline]
+fn edge(a: &[f32; 3], b: &[f32; 3], p: &[f32; 3]) -> f32 {
+    (b[0] - a[0]) * (p[1] - a[1]) - (b[1] - a[1]) * (p[0] - a[0])
+}
+
+#[inline]
+fn to_unorm(color: [f32; 4]) -> [u8; 4] {
+    let convert = |c: f32| (c.max(0.0).min(1.0) * 255.0 + 0.5) as u8;
+    [convert(color[0]), convert(color[1]), convert(color[2]), convert(color[3])]
+}
+
+/// Cuts off the part of the triangle behind the near plane, leaving a convex
+/// polygon of up to four vertices.
+fn clip_near(triangle: &[ClipVertex; 3]) -> Vec<ClipVertex> {
+    let mut polygon = Vec::with_capacity(4);
+    for i in 0..3 {
+        let current = &triangle[i];
+        let next = &triangle[(i + 1) % 3];
+        let (d_current, d_next) = (current.near_distance(), next.near_distance());
+
+        if d_current >= 0.0 {
+            polygon.push(*current);
+        }
+        if (d_current >= 0.0) != (d_next >= 0.0) {
+            polygon.push(current.lerp(next, d_current / (d_current - d_next)));
+        }
+    }
+    polygon
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vertex(x: f32, y: f32, z: f32) -> ClipVertex {
+        ClipVertex {
+            position: Vector4::new(x, y, z, 1.0),
+            varyings: [0.0; VARYING_COUNT],
+        }
+    }
+
+    #[test]
+    fn fills_covered_pixels() {
+        let mut framebuffer = Framebuffer::new(4, 4);
+        let triangle = [vertex(-1.0, -1.0, 0.0), vertex(3.0, -1.0, 0.0), vertex(-1.0, 3.0, 0.0)];
+        framebuffer.draw_triangle(&triangle, true, &mut |_| [1.0, 0.0, 0.0, 1.0]);
+        assert!(framebuffer.color().iter().all(|&p| p == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn culls_back_faces() {
+        let mut framebuffer = Framebuffer::new(4, 4);
+        let triangle = [vertex(-1.0, -1.0, 0.0), vertex(-1.0, 3.0, 0.0), vertex(3.0, -1.0, 0.0)];
+        framebuffer.draw_triangle(&triangle, true, &mut |_| [1.0, 0.0, 0.0, 1.0]);
+        assert!(framebuffer.color().iter().all(|&p| p == [0, 0, 0, 255]));
+
+        framebuffer.draw_triangle(&triangle, false, &mut |_| [1.0, 0.0, 0.0, 1.0]);
+        assert!(framebuffer.color().iter().all(|&p| p == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn keeps_the_nearest_surface() {
+        let mut framebuffer = Framebuffer::new(4, 4);
+        let near = [vertex(-1.0, -1.0, -0.5), vertex(3.0, -1.0, -0.5), vertex(-1.0, 3.0, -0.5)];
+        let far = [vertex(-1.0, -1.0, 0.5), vertex(3.0, -1.0, 0.5), vertex(-1.0, 3.0, 0.5)];
+        framebuffer.draw_triangle(&near, true, &mut |_| [0.0, 1.0, 0.0, 1.0]);
+        framebuffer.draw_triangle(&far, true, &mut |_| [1.0, 0.0, 0.0, 1.0]);
+        assert_eq!(framebuffer.pixel(1, 1), [0, 255, 0, 255]);
+        assert!((framebuffer.depth()[5] - 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn clips_against_the_near_plane() {
+        let behind = vertex(0.0, 0.0, -2.0);
+        let triangle = [behind, vertex(1.0, 0.0, 0.0), vertex(0.0, 1.0, 0.0)];
+        let polygon = clip_near(&triangle);
+        assert_eq!(polygon.len(), 4);
+        assert!(polygon.iter().all(|v| v.near_distance() >= -1e-6));
+    }
+}
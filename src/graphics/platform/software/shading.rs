@@ -0,0 +1,293 @@
+//! A port of `standard.fs` and `skybox.fs`. Shadows are not rendered, so every
+//! light reaches every surface which faces it.
+
+use ::{Light, LightKind};
+use graphics::environment::{EnvironmentMap, SH_COEFFICIENTS};
+use graphics::material::Material;
+use graphics::skybox::Background;
+use image::RgbaImage;
+use na::Vector3;
+use std::f32::consts::PI;
+use super::raster::Varyings;
+
+const DIELECTRIC_F0: f32 = 0.04;
+
+/// A texture sampled bilinearly with clamped coordinates, like the sampler
+/// the models use on the gpu.
+#[derive(Clone, Debug)]
+pub struct Texture {
+    image: RgbaImage,
+}
+
+impl Texture {
+    #[inline]
+    pub fn new(image: RgbaImage) -> Self {
+        Texture { image }
+    }
+
+    /// Returns the texel values from 0 to 1, without any colour conversion.
+    pub fn sample(&self, (u, v): (f32, f32)) -> [f32; 4] {
+        let (width, height) = self.image.dimensions();
+        let x = (u * width as f32 - 0.5).max(0.0).min(width as f32 - 1.0);
+        let y = (v * height as f32 - 0.5).max(0.0).min(height as f32 - 1.0);
+        let (x0, y0) = (x.floor() as u32, y.floor() as u32);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let (tx, ty) = (x - x0 as f32, y - y0 as f32);
+
+        let texel = |x, y| self.image.get_pixel(x, y).data;
+        let (a, b, c, d) = (texel(x0, y0), texel(x1, y0), texel(x0, y1), texel(x1, y1));
+        let mut out = [0.0; 4];
+        for i in 0..4 {
+            let top = a[i] as f32 * (1.0 - tx) + b[i] as f32 * tx;
+            let bottom = c[i] as f32 * (1.0 - tx) + d[i] as f32 * tx;
+            out[i] = (top * (1.0 - ty) + bottom * ty) / 255.0;
+        }
+        out
+    }
+}
+
+/// The textures of a `Material`. A missing texture samples as white.
+#[derive(Clone, Debug, Default)]
+pub struct MaterialTextures {
+    pub base_color: Option<Texture>,
+    pub metallic_roughness: Option<Texture>,
+    pub occlusion: Option<Texture>,
+    pub emissive: Option<Texture>,
+}
+
+#[inline]
+fn sample_or_white(texture: &Option<Texture>, uv: (f32, f32)) -> [f32; 4] {
+    texture.as_ref().map_or([1.0; 4], |t| t.sample(uv))
+}
+
+/// The image based lighting, precomputed the same way as for the gpu.
+#[derive(Clone, Debug)]
+pub struct Environment {
+    sh: [Vector3<f32>; SH_COEFFICIENTS],
+    specular_levels: Vec<EnvironmentMap>,
+    intensity: f32,
+}
+
+impl Environment {
+    pub fn new(map: &EnvironmentMap, intensity: f32) -> Self {
+        Environment {
+            sh: map.irradiance_sh(),
+            specular_levels: map.specular_levels(),
+            intensity,
+        }
+    }
+
+    #[inline]
+    pub fn intensity(&self) -> f32 {
+        self.intensity
+    }
+
+    /// The irradiance arriving at a surface with the normal `n`, divided by pi.
+    fn irradiance(&self, n: &Vector3<f32>) -> Vector3<f32> {
+        let sh = &self.sh;
+        let irradiance = sh[0] * 0.282095 + sh[1] * 0.488603 * n.y + sh[2] * 0.488603 * n.z +
+            sh[3] * 0.488603 * n.x + sh[4] * 1.092548 * n.x * n.y +
+            sh[5] * 1.092548 * n.y * n.z +
+            sh[6] * 0.315392 * (3.0 * n.z * n.z - 1.0) +
+            sh[7] * 1.092548 * n.x * n.z +
+            sh[8] * 0.546274 * (n.x * n.x - n.y * n.y);
+        irradiance.map(|c| c.max(0.0))
+    }
+
+    /// Blends between the two mip levels nearest to the roughness, like
+    /// trilinear filtering of the specular map.
+    fn prefiltered(&self, direction: &Vector3<f32>, roughness: f32) -> Vector3<f32> {
+        let max_level = (self.specular_levels.len() - 1) as f32;
+        let level = roughness * max_level;
+        let lower = level.floor() as usize;
+        let upper = (lower + 1).min(self.specular_levels.len() - 1);
+        let t = level - lower as f32;
+        self.specular_levels[lower].sample(direction) * (1.0 - t) +
+            self.specular_levels[upper].sample(direction) * t
+    }
+}
+
+/// Everything which is the same for every fragment of a model.
+#[derive(Debug)]
+pub struct SurfaceShader<'a> {
+    pub material: &'a Material,
+    pub textures: &'a MaterialTextures,
+    pub lights: &'a [Light],
+    pub environment: &'a Environment,
+    pub camera_position: Vector3<f32>,
+}
+
+impl<'a> SurfaceShader<'a> {
+    pub fn shade(&self, varyings: &Varyings) -> [f32; 4] {
+        let world_position = Vector3::new(varyings[0], varyings[1], varyings[2]);
+        let normal = Vector3::new(varyings[3], varyings[4], varyings[5]);
+        let uv = (varyings[6], varyings[7]);
+        let material = self.material;
+        let textures = self.textures;
+
+        let base_color_sample = sample_or_white(&textures.base_color, uv);
+        let base_color = srgb_to_linear(rgb(&base_color_sample))
+            .component_mul(&rgb(&material.base_color));
+        let alpha = base_color_sample[3] * material.base_color[3];
+
+        let metallic_roughness = sample_or_white(&textures.metallic_roughness, uv);
+        let roughness = (metallic_roughness[1] * material.roughness).max(0.04).min(1.0);
+        let metallic = (metallic_roughness[2] * material.metallic).max(0.0).min(1.0);
+
+        let occlusion_sample = sample_or_white(&textures.occlusion, uv)[0];
+        let occlusion = 1.0 + (occlusion_sample - 1.0) * material.occlusion_strength;
+        let emissive = srgb_to_linear(rgb(&sample_or_white(&textures.emissive, uv)))
+            .component_mul(&Vector3::from_column_slice(&material.emissive));
+
+        let n = normal.normalize();
+        let v = (self.camera_position - world_position).normalize();
+        let n_dot_v = n.dot(&v).max(0.0001);
+
+        let f0 = Vector3::repeat(DIELECTRIC_F0) * (1.0 - metallic) + base_color * metallic;
+        let diffuse_color = base_color * (1.0 - metallic);
+
+        let mut total_lighting = Vector3::zeros();
+        for light in self.lights {
+            let (l, radiance) = match light.kind {
+                LightKind::Directional => {
+                    (-light.direction.normalize(), rgb(&light.color) * light.power)
+                }
+                LightKind::Point => {
+                    let to_light = light.position.coords - world_position;
+                    let distance_squared = to_light.dot(&to_light).max(0.0001);
+                    let radiance =
+                        rgb(&light.color) * light.power / (4.0 * PI * distance_squared);
+                    (to_light.normalize(), radiance)
+                }
+            };
+
+            let h = (v + l).normalize();
+            let n_dot_l = n.dot(&l).max(0.0);
+            let n_dot_h = n.dot(&h).max(0.0);
+            let h_dot_v = h.dot(&v).max(0.0);
+
+            let f = fresnel_schlick(h_dot_v, &f0);
+            let d = distribution_ggx(n_dot_h, roughness);
+            let g = geometry_smith(n_dot_v, n_dot_l, roughness);
+
+            let specular = f * (d * g / (4.0 * n_dot_v * n_dot_l).max(0.0001));
+            let diffuse = (Vector3::repeat(1.0) - f).component_mul(&diffuse_color) / PI;
+
+            total_lighting += (diffuse + specular).component_mul(&radiance) * n_dot_l;
+        }
+
+        let environment = self.environment;
+        let ambient_fresnel = fresnel_schlick_roughness(n_dot_v, &f0, roughness);
+        let ambient_diffuse = environment
+            .irradiance(&n)
+            .component_mul(&diffuse_color)
+            .component_mul(&(Vector3::repeat(1.0) - ambient_fresnel));
+
+        let reflected = n * (2.0 * n.dot(&v)) - v;
+        let prefiltered = environment.prefiltered(&reflected, roughness);
+        let (brdf_x, brdf_y) = environment_brdf(roughness, n_dot_v);
+        let ambient_specular =
+            prefiltered.component_mul(&(ambient_fresnel * brdf_x + Vector3::repeat(brdf_y)));
+
+        let ambient = (ambient_diffuse + ambient_specular) * occlusion * environment.intensity;
+        let color = ambient + total_lighting + emissive;
+        to_display(color, alpha)
+    }
+}
+
+/// Tone maps and gamma encodes a linear colour, the same way as the shaders.
+pub fn to_display(color: Vector3<f32>, alpha: f32) -> [f32; 4] {
+    let mapped = color.map(|c| {
+        let c = c.max(0.0);
+        (c / (c + 1.0)).powf(1.0 / 2.2)
+    });
+    [mapped.x, mapped.y, mapped.z, alpha]
+}
+
+#[inline]
+fn rgb(color: &[f32; 4]) -> Vector3<f32> {
+    Vector3::new(color[0], color[1], color[2])
+}
+
+#[inline]
+pub fn srgb_to_linear(color: Vector3<f32>) -> Vector3<f32> {
+    color.map(|c| c.powf(2.2))
+}
+
+// Trowbridge-Reitz GGX normal distribution function.
+fn distribution_ggx(n_dot_h: f32, roughness: f32) -> f32 {
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let denominator = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    a2 / (PI * denominator * denominator)
+}
+
+fn geometry_schlick_ggx(n_dot_v: f32, roughness: f32) -> f32 {
+    let r = roughness + 1.0;
+    let k = r * r / 8.0;
+    n_dot_v / (n_dot_v * (1.0 - k) + k)
+}
+
+// Smith's method, combining the masking and shadowing terms.
+fn geometry_smith(n_dot_v: f32, n_dot_l: f32, roughness: f32) -> f32 {
+    geometry_schlick_ggx(n_dot_v, roughness) * geometry_schlick_ggx(n_dot_l, roughness)
+}
+
+fn fresnel_schlick(cos_theta: f32, f0: &Vector3<f32>) -> Vector3<f32> {
+    f0 + (Vector3::repeat(1.0) - f0) * (1.0 - cos_theta).powi(5)
+}
+
+fn fresnel_schlick_roughness(cos_theta: f32, f0: &Vector3<f32>, roughness: f32) -> Vector3<f32> {
+    let max = f0.map(|c| c.max(1.0 - roughness));
+    f0 + (max - f0) * (1.0 - cos_theta).powi(5)
+}
+
+// Analytical fit of the split-sum environment BRDF, from Karis' "Physically
+// Based Shading on Mobile".
+fn environment_brdf(roughness: f32, n_dot_v: f32) -> (f32, f32) {
+    let c0 = [-1.0, -0.0275, -0.572, 0.022];
+    let c1 = [1.0, 0.0425, 1.04, -0.04];
+    let r = [
+        roughness * c0[0] + c1[0],
+        roughness * c0[1] + c1[1],
+        roughness * c0[2] + c1[2],
+        roughness * c0[3] + c1[3],
+    ];
+    let a004 = (r[0] * r[0]).min((-9.28 * n_dot_v).exp2()) * r[0] + r[1];
+    (-1.04 * a004 + r[2], 1.04 * a004 + r[3])
+}
+
+/// The colour of the background in the given direction, or `None` when the
+/// background is a plain clear colour. Images, including cube maps, are drawn
+/// from `equirect`, which they were resampled into.
+pub fn shade_sky(
+    background: &Background,
+    equirect: Option<&EnvironmentMap>,
+    intensity: f32,
+    direction: &Vector3<f32>,
+) -> Option<[f32; 4]> {
+    let color = match *background {
+        Background::Color(_) => return None,
+        Background::Gradient {
+            zenith,
+            horizon,
+            ground,
+        } => {
+            let (zenith, horizon, ground) = (
+                Vector3::from_column_slice(&zenith),
+                Vector3::from_column_slice(&horizon),
+                Vector3::from_column_slice(&ground),
+            );
+            let height = direction.y;
+            if height >= 0.0 {
+                horizon + (zenith - horizon) * height.sqrt()
+            } else {
+                horizon + (ground - horizon) * (-height).sqrt()
+            }
+        }
+        Background::Equirectangular(_) | Background::CubeMap(_) => {
+            equirect.map_or(Vector3::zeros(), |map| map.sample(direction))
+        }
+    };
+    Some(to_display(color * intensity, 1.0))
+}
@@ -0,0 +1,422 @@
+//! Shadow maps for the lights which cast shadows, all kept as layers of one
+//! 2D depth texture array.
+//!
+//! A directional light takes `CASCADE_COUNT` layers, one per cascade. A point
+//! light takes six layers, one for each face of a cube around it, each drawn
+//! with its own 90 degree perspective transform, in the order +x, -x, +y, -y,
+//! +z, -z which `standard.fs` picks the face by. This is not a cube texture,
+//! so filtering does not cross from one face to the next, and the edges of the
+//! faces can show faint seams in the shadow.
+
+use ::{Light, LightKind, ModelLocals, ShaderLight, Vertex};
+use gfx::{self, CombinedError, CommandBuffer, Encoder, PipelineState, PipelineStateError,
+          Primitive, Resources, UpdateError};
+use gfx::format::{ChannelType, Depth32F, Formatted, Swizzle};
+use gfx::handle::{Buffer, DepthStencilView, Sampler, ShaderResourceView};
+use gfx::memory::{Bind, Usage};
+use gfx::state::{Comparison, FrontFace, Rasterizer};
+use gfx::texture::{AaMode, DepthStencilFlags, FilterMethod, Kind, SamplerInfo, WrapMode};
+use graphics::camera::CameraMatrices;
+use graphics::frame::FrameResources;
+use graphics::model::Model;
+use graphics::platform::{Backend, FactoryExt};
+use graphics::transform::Transform;
+use na::{self, Isometry3, Matrix4, Orthographic3, Perspective3, Point3, Vector3, Vector4};
+use std::error::Error;
+use std::fmt;
+
+gfx_defines! {
+    #[derive(Default)]
+    constant ShadowLocals {
+        transform: [[f32; 4]; 4] = "shadow_transform",
+    }
+
+    #[derive(Default)]
+    constant ShadowTransform {
+        transform: [[f32; 4]; 4] = "shadow_matrix",
+    }
+
+    pipeline shadow_pipe {
+        vbuf: gfx::VertexBuffer<Vertex> = (),
+        model_locals: gfx::ConstantBuffer<ModelLocals> = "model_locals",
+        shadow_locals: gfx::ConstantBuffer<ShadowLocals> = "shadow_locals",
+        out_depth: gfx::DepthTarget<ShadowFormat> =
+            gfx::preset::depth::LESS_EQUAL_WRITE,
+    }
+}
+
+pub type ShadowFormat = Depth32F;
+type ShadowSurface = <ShadowFormat as Formatted>::Surface;
+
+/// The number of layers in the shadow map array. Directional lights take up
+/// `CASCADE_COUNT` layers and point lights take up one layer per cube face.
+pub const MAX_SHADOW_LAYERS: usize = 16;
+pub const CASCADE_COUNT: usize = 3;
+const CUBE_FACE_COUNT: usize = 6;
+
+const SHADOW_MAP_SIZE: u16 = 1024;
+
+// Shadows from directional lights are only drawn up to this distance from the
+// camera, so that the cascades do not get stretched over the whole far plane.
+const MAX_SHADOW_DISTANCE: f32 = 40.0;
+const CASCADE_SPLIT_LAMBDA: f32 = 0.6;
+
+const POINT_SHADOW_NEAR: f32 = 0.05;
+const POINT_SHADOW_FAR: f32 = 30.0;
+
+const GLSL_SHADOW_VERT_SRC: &'static [u8] = include_bytes!("../../data/shader/glsl/shadow.vs");
+const GLSL_SHADOW_FRAG_SRC: &'static [u8] = include_bytes!("../../data/shader/glsl/shadow.fs");
+
+const MSL_SHADOW_VERT_SRC: &'static [u8] = include_bytes!("../../data/shader/msl/shadow.vs");
+const MSL_SHADOW_FRAG_SRC: &'static [u8] = include_bytes!("../../data/shader/msl/shadow.fs");
+
+pub struct ShadowMaps<R: Resources> {
+    pso: PipelineState<R, shadow_pipe::Meta>,
+    /// Draws mirrored models, whose triangles wind the other way.
+    mirrored_pso: PipelineState<R, shadow_pipe::Meta>,
+    locals: Buffer<R, ShadowLocals>,
+    layers: Vec<DepthStencilView<R, ShadowFormat>>,
+    resource: ShaderResourceView<R, f32>,
+    sampler: Sampler<R>,
+    transforms: Vec<Matrix4<f32>>,
+}
+
+impl<R: Resources> ShadowMaps<R> {
+    pub fn new<F: FactoryExt<R>>(factory: &mut F, backend: &Backend) -> Result<Self, ShadowMapsError> {
+        let program = if backend.is_gl() {
+            factory.link_program(GLSL_SHADOW_VERT_SRC, GLSL_SHADOW_FRAG_SRC).unwrap()
+        } else {
+            factory.link_program(MSL_SHADOW_VERT_SRC, MSL_SHADOW_FRAG_SRC).unwrap()
+        };
+
+        let rasterizer = Rasterizer::new_fill().with_cull_back().with_offset(2.0, 2);
+        let pso = factory.create_pipeline_from_program(
+            &program,
+            Primitive::TriangleList,
+            rasterizer,
+            shadow_pipe::new(),
+        )?;
+        let mirrored_pso = factory.create_pipeline_from_program(
+            &program,
+            Primitive::TriangleList,
+            Rasterizer {
+                front_face: FrontFace::Clockwise,
+                ..rasterizer
+            },
+            shadow_pipe::new(),
+        )?;
+
+        let texture = factory
+            .create_texture::<ShadowSurface>(
+                Kind::D2Array(
+                    SHADOW_MAP_SIZE,
+                    SHADOW_MAP_SIZE,
+                    MAX_SHADOW_LAYERS as u16,
+                    AaMode::Single,
+                ),
+                1,
+                Bind::SHADER_RESOURCE | Bind::DEPTH_STENCIL,
+                Usage::Data,
+                Some(ChannelType::Float),
+            )
+            .map_err(CombinedError::from)?;
+
+        let resource = factory
+            .view_texture_as_shader_resource::<ShadowFormat>(&texture, (0, 0), Swizzle::new())
+            .map_err(CombinedError::from)?;
+
+        let mut layers = Vec::with_capacity(MAX_SHADOW_LAYERS);
+        for layer in 0..MAX_SHADOW_LAYERS {
+            let dsv = factory
+                .view_texture_as_depth_stencil(
+                    &texture,
+                    0,
+                    Some(layer as u16),
+                    DepthStencilFlags::empty(),
+                )
+                .map_err(CombinedError::from)?;
+            layers.push(dsv);
+        }
+
+        let sampler = {
+            let mut info = SamplerInfo::new(FilterMethod::Bilinear, WrapMode::Clamp);
+            info.comparison = Some(Comparison::LessEqual);
+            factory.create_sampler(info)
+        };
+
+        Ok(ShadowMaps {
+            pso,
+            mirrored_pso,
+            locals: factory.create_constant_buffer(1),
+            layers,
+            resource,
+            sampler,
+            transforms: Vec::with_capacity(MAX_SHADOW_LAYERS),
+        })
+    }
+
+    #[inline]
+    pub fn resource(&self) -> (ShaderResourceView<R, f32>, Sampler<R>) {
+        (self.resource.clone(), self.sampler.clone())
+    }
+
+    /// Assigns shadow map layers to the lights which cast shadows and computes
+    /// the light transforms for each layer. Lights which do not fit into the
+    /// shadow map array are drawn without shadows.
+    ///
+    /// Returns the lights in the form expected by the shaders, along with the
+    /// view space distance at which each cascade ends.
+    pub fn prepare(
+        &mut self,
+        lights: &[Light],
+        camera: &CameraMatrices,
+    ) -> (Vec<ShaderLight>, [f32; 4]) {
+        self.transforms.clear();
+
+        let cascades = Cascades::new(camera);
+
+        let shader_lights = lights
+            .iter()
+            .map(|light| {
+                let mut shader_light = ShaderLight::from(light);
+                if !light.casts_shadows {
+                    return shader_light;
+                }
+
+                let layers_needed = match light.kind {
+                    LightKind::Point => CUBE_FACE_COUNT,
+                    LightKind::Directional => CASCADE_COUNT,
+                };
+                if self.transforms.len() + layers_needed > MAX_SHADOW_LAYERS {
+                    return shader_light;
+                }
+
+                shader_light.shadow_index = self.transforms.len() as i32;
+                match light.kind {
+                    LightKind::Point => {
+                        self.transforms.extend(point_light_transforms(&light.position))
+                    }
+                    LightKind::Directional => {
+                        self.transforms.extend(cascades.light_transforms(&light.direction))
+                    }
+                }
+                shader_light
+            })
+            .collect();
+
+        (shader_lights, cascades.splits)
+    }
+
+    /// Renders the depth of every model into each shadow map layer which was
+    /// assigned in the last call to `prepare`. The models' locals must already
+    /// be up to date, placing each model at the transform given with it.
+    pub fn encode<C: CommandBuffer<R>>(
+        &self,
+        encoder: &mut Encoder<R, C>,
+        frame: &FrameResources<R>,
+        models: &[Model<R>],
+        transforms: &[Transform],
+    ) -> Result<(), UpdateError<usize>> {
+        if self.transforms.is_empty() {
+            return Ok(());
+        }
+
+        let transforms = self.transforms
+            .iter()
+            .map(|m| ShadowTransform { transform: *m.as_ref() })
+            .collect::<Vec<_>>();
+        encoder.update_buffer(&frame.shadow_transforms, &transforms, 0)?;
+
+        for (transform, layer) in self.transforms.iter().zip(self.layers.iter()) {
+            encoder.clear_depth(layer, 1.0);
+            encoder.update_constant_buffer(
+                &self.locals,
+                &ShadowLocals { transform: *transform.as_ref() },
+            );
+            for (model, model_transform) in models.iter().zip(transforms) {
+                let pso = if model_transform.is_mirrored() {
+                    &self.mirrored_pso
+                } else {
+                    &self.pso
+                };
+                model.encode_shadow(encoder, pso, &self.locals, layer);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Resources> fmt::Debug for ShadowMaps<R> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.debug_struct("ShadowMaps")
+            .field("pso", &"PipelineState(...)")
+            .field("layers", &self.layers.len())
+            .field("transforms", &self.transforms)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum ShadowMapsError {
+    Pso(PipelineStateError<String>),
+    Texture(CombinedError),
+}
+
+impl fmt::Display for ShadowMapsError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            ShadowMapsError::Pso(ref e) => write!(fmtr, "{}: {}", desc, e),
+            ShadowMapsError::Texture(ref e) => write!(fmtr, "{}: {}", desc, e),
+        }
+    }
+}
+
+impl Error for ShadowMapsError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            ShadowMapsError::Pso(_) => "There was an error creating the shadow pso",
+            ShadowMapsError::Texture(_) => "An error occurred while creating the shadow map texture",
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            ShadowMapsError::Pso(ref e) => Some(e),
+            ShadowMapsError::Texture(ref e) => Some(e),
+        }
+    }
+}
+
+impl<'a> From<PipelineStateError<&'a str>> for ShadowMapsError {
+    #[inline]
+    fn from(e: PipelineStateError<&'a str>) -> Self {
+        ShadowMapsError::Pso(e.into())
+    }
+}
+
+impl From<CombinedError> for ShadowMapsError {
+    #[inline]
+    fn from(e: CombinedError) -> Self {
+        ShadowMapsError::Texture(e)
+    }
+}
+
+/// The view frustum of the camera, split into `CASCADE_COUNT` slices.
+struct Cascades {
+    corners: [[Point3<f32>; 8]; CASCADE_COUNT],
+    splits: [f32; 4],
+}
+
+impl Cascades {
+    fn new(camera: &CameraMatrices) -> Self {
+        let CameraMatrices { view, projection, near, far: camera_far } = *camera;
+        let far = camera_far.min(near + MAX_SHADOW_DISTANCE);
+
+        // Blend between logarithmic and uniform splits, so that the near
+        // cascades get most of the resolution without the far ones becoming
+        // uselessly thin.
+        let mut splits = [far; 4];
+        for i in 0..CASCADE_COUNT {
+            let p = (i + 1) as f32 / CASCADE_COUNT as f32;
+            let log = near * (far / near).powf(p);
+            let uniform = near + (far - near) * p;
+            splits[i] = CASCADE_SPLIT_LAMBDA * log + (1.0 - CASCADE_SPLIT_LAMBDA) * uniform;
+        }
+
+        let inverse_view_projection = (projection * view)
+            .try_inverse()
+            .unwrap_or_else(Matrix4::identity);
+        let unproject = |x: f32, y: f32, z: f32| {
+            let v = inverse_view_projection * Vector4::new(x, y, z, 1.0);
+            Point3::new(v.x / v.w, v.y / v.w, v.z / v.w)
+        };
+
+        let mut frustum_near = [na::origin(); 4];
+        let mut frustum_far = [na::origin(); 4];
+        for (i, &(x, y)) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)].iter().enumerate() {
+            frustum_near[i] = unproject(x, y, -1.0);
+            frustum_far[i] = unproject(x, y, 1.0);
+        }
+        let along_frustum = |i: usize, distance: f32| {
+            let t = (distance - near) / (camera_far - near);
+            frustum_near[i] + (frustum_far[i] - frustum_near[i]) * t
+        };
+
+        let mut corners = [[na::origin(); 8]; CASCADE_COUNT];
+        let mut slice_start = near;
+        for cascade in 0..CASCADE_COUNT {
+            let slice_end = splits[cascade];
+            for i in 0..4 {
+                corners[cascade][i] = along_frustum(i, slice_start);
+                corners[cascade][i + 4] = along_frustum(i, slice_end);
+            }
+            slice_start = slice_end;
+        }
+
+        Cascades { corners, splits }
+    }
+
+    fn light_transforms(&self, direction: &Vector3<f32>) -> Vec<Matrix4<f32>> {
+        self.corners
+            .iter()
+            .map(|corners| {
+                // Fit the cascade in a bounding sphere so that the size of the
+                // projection does not change as the camera rotates.
+                let center = corners
+                    .iter()
+                    .fold(Vector3::new(0.0, 0.0, 0.0), |acc, p| acc + p.coords)
+                    / corners.len() as f32;
+                let center = Point3::from_coordinates(center);
+                let radius = corners
+                    .iter()
+                    .map(|p| na::distance(p, &center))
+                    .fold(0.0f32, f32::max);
+
+                let eye = center - *direction * (radius * 2.0);
+                let view = Isometry3::look_at_rh(&eye, &center, &up_vector_for(direction));
+                let projection =
+                    Orthographic3::new(-radius, radius, -radius, radius, 0.0, radius * 4.0);
+                projection.to_homogeneous() * view.to_homogeneous()
+            })
+            .collect()
+    }
+}
+
+fn point_light_transforms(position: &Point3<f32>) -> Vec<Matrix4<f32>> {
+    // Must be kept in the same order as the face selection in `standard.fs`.
+    let faces = [
+        Vector3::x(),
+        -Vector3::x(),
+        Vector3::y(),
+        -Vector3::y(),
+        Vector3::z(),
+        -Vector3::z(),
+    ];
+    let projection = Perspective3::new(
+        1.0,
+        ::std::f32::consts::FRAC_PI_2,
+        POINT_SHADOW_NEAR,
+        POINT_SHADOW_FAR,
+    );
+    faces
+        .iter()
+        .map(|face| {
+            let target = *position + *face;
+            let view = Isometry3::look_at_rh(position, &target, &up_vector_for(face));
+            projection.to_homogeneous() * view.to_homogeneous()
+        })
+        .collect()
+}
+
+#[inline]
+fn up_vector_for(direction: &Vector3<f32>) -> Vector3<f32> {
+    if direction.y.abs() > 0.99 {
+        Vector3::z()
+    } else {
+        Vector3::y()
+    }
+}
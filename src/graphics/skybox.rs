@@ -0,0 +1,333 @@
+use ::{ColorFormat, DepthFormat};
+use gfx::{self, Bundle, CombinedError, CommandBuffer, Encoder, PipelineStateError, Primitive,
+          Resources};
+use gfx::format::Rgba32F;
+use gfx::handle::{DepthStencilView, RenderTargetView};
+use gfx::state::Rasterizer;
+use gfx::texture::{AaMode, FilterMethod, Kind, Mipmap, SamplerInfo, WrapMode};
+use graphics::camera::CameraMatrices;
+use graphics::environment::{load_cube_faces, EnvironmentMap, LoadEnvironmentError};
+use graphics::platform::{Backend, FactoryExt};
+use std::error::Error;
+use std::fmt;
+
+gfx_defines! {
+    vertex SkyVertex {
+        pos: [f32; 2] = "position",
+    }
+
+    #[derive(Default)]
+    constant SkyLocals {
+        inverse_view_projection: [[f32; 4]; 4] = "inverse_view_projection",
+        zenith: [f32; 4] = "zenith_color",
+        horizon: [f32; 4] = "horizon_color",
+        ground: [f32; 4] = "ground_color",
+        mode: u32 = "sky_mode",
+        intensity: f32 = "sky_intensity",
+    }
+
+    pipeline skybox_pipe {
+        vbuf: gfx::VertexBuffer<SkyVertex> = (),
+        sky_locals: gfx::ConstantBuffer<SkyLocals> = "sky_locals",
+        equirect_map: gfx::TextureSampler<[f32; 4]> = "equirect_map",
+        cube_map: gfx::TextureSampler<[f32; 4]> = "cube_map",
+        out: gfx::RenderTarget<ColorFormat> = "Target0",
+        main_depth: gfx::DepthTarget<DepthFormat> =
+            gfx::preset::depth::LESS_EQUAL_TEST,
+    }
+}
+
+const SKY_MODE_GRADIENT: u32 = 0;
+const SKY_MODE_EQUIRECTANGULAR: u32 = 1;
+const SKY_MODE_CUBE_MAP: u32 = 2;
+
+const GLSL_SKYBOX_VERT_SRC: &'static [u8] = include_bytes!("../../data/shader/glsl/skybox.vs");
+const GLSL_SKYBOX_FRAG_SRC: &'static [u8] = include_bytes!("../../data/shader/glsl/skybox.fs");
+
+const MSL_SKYBOX_VERT_SRC: &'static [u8] = include_bytes!("../../data/shader/msl/skybox.vs");
+const MSL_SKYBOX_FRAG_SRC: &'static [u8] = include_bytes!("../../data/shader/msl/skybox.fs");
+
+/// What is drawn behind the scene's geometry.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Background {
+    /// Clears the screen to a single colour.
+    Color([f32; 4]),
+    /// A procedural sky, blending from the ground colour below the horizon to
+    /// the zenith colour straight up.
+    Gradient {
+        zenith: [f32; 3],
+        horizon: [f32; 3],
+        ground: [f32; 3],
+    },
+    /// An equirectangular image in the assets folder, or at an existing path,
+    /// either a Radiance `.hdr` file or an sRGB image.
+    Equirectangular(String),
+    /// Six square sRGB images found the same way, in the order +X, -X, +Y, -Y,
+    /// +Z, -Z.
+    CubeMap([String; 6]),
+}
+
+impl Background {
+    #[inline]
+    pub fn clear_color(&self) -> [f32; 4] {
+        match *self {
+            Background::Color(color) => color,
+            _ => [0.0, 0.0, 0.0, 1.0],
+        }
+    }
+}
+
+pub struct Skybox<R: Resources> {
+    background: Background,
+    intensity: f32,
+    bundle: Option<Bundle<R, skybox_pipe::Data<R>>>,
+}
+
+impl<R: Resources> Skybox<R> {
+    pub fn new<F: FactoryExt<R>>(
+        factory: &mut F,
+        backend: &Backend,
+        rtv: RenderTargetView<R, ColorFormat>,
+        dsv: DepthStencilView<R, DepthFormat>,
+        background: Background,
+        intensity: f32,
+    ) -> Result<Self, SkyboxError> {
+        if let Background::Color(_) = background {
+            return Ok(Skybox {
+                background,
+                intensity,
+                bundle: None,
+            });
+        }
+
+        let program = if backend.is_gl() {
+            factory.link_program(GLSL_SKYBOX_VERT_SRC, GLSL_SKYBOX_FRAG_SRC).unwrap()
+        } else {
+            factory.link_program(MSL_SKYBOX_VERT_SRC, MSL_SKYBOX_FRAG_SRC).unwrap()
+        };
+
+        let pso = factory.create_pipeline_from_program(
+            &program,
+            Primitive::TriangleList,
+            Rasterizer::new_fill(),
+            skybox_pipe::new(),
+        )?;
+
+        // A single triangle which covers the whole screen.
+        let vertices = [
+            SkyVertex { pos: [-1.0, -1.0] },
+            SkyVertex { pos: [3.0, -1.0] },
+            SkyVertex { pos: [-1.0, 3.0] },
+        ];
+        let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&vertices, ());
+
+        let black = [0u8, 0, 0, 255];
+        let equirect_map = match background {
+            Background::Equirectangular(ref name) => {
+                let map = EnvironmentMap::load(name)?;
+                let (width, height) = map.dimensions();
+                let pixels = map.pixels()
+                    .iter()
+                    .map(|p| [p.x.to_bits(), p.y.to_bits(), p.z.to_bits(), 1.0f32.to_bits()])
+                    .collect::<Vec<_>>();
+                let kind = Kind::D2(width as u16, height as u16, AaMode::Single);
+                factory
+                    .create_texture_immutable::<Rgba32F>(kind, Mipmap::Provided, &[&pixels])?
+                    .1
+            }
+            _ => {
+                let kind = Kind::D2(1, 1, AaMode::Single);
+                factory
+                    .create_texture_immutable_u8::<ColorFormat>(
+                        kind,
+                        Mipmap::Provided,
+                        &[&black[..]],
+                    )?
+                    .1
+            }
+        };
+
+        let cube_map = match background {
+            Background::CubeMap(ref faces) => {
+                let images = load_cube_faces(faces)?;
+                let (size, _) = images[0].dimensions();
+                let data = images.iter().map(|img| &img[..]).collect::<Vec<_>>();
+                factory
+                    .create_texture_immutable_u8::<ColorFormat>(
+                        Kind::Cube(size as u16),
+                        Mipmap::Provided,
+                        &data,
+                    )?
+                    .1
+            }
+            _ => {
+                let faces = [&black[..]; 6];
+                factory
+                    .create_texture_immutable_u8::<ColorFormat>(
+                        Kind::Cube(1),
+                        Mipmap::Provided,
+                        &faces,
+                    )?
+                    .1
+            }
+        };
+
+        let sampler = {
+            let mut info = SamplerInfo::new(FilterMethod::Bilinear, WrapMode::Clamp);
+            info.wrap_mode.0 = WrapMode::Tile;
+            factory.create_sampler(info)
+        };
+
+        let data = skybox_pipe::Data {
+            vbuf,
+            sky_locals: factory.create_constant_buffer(1),
+            equirect_map: (equirect_map, sampler.clone()),
+            cube_map: (cube_map, sampler),
+            out: rtv,
+            main_depth: dsv,
+        };
+
+        Ok(Skybox {
+            background,
+            intensity,
+            bundle: Some(Bundle::new(slice, pso, data)),
+        })
+    }
+
+    #[inline]
+    pub fn background(&self) -> &Background {
+        &self.background
+    }
+
+    /// Draws the background wherever nothing has been drawn yet. This should
+    /// come after the opaque geometry, so that covered pixels fail the depth
+    /// test instead of being shaded.
+    pub fn encode<C: CommandBuffer<R>>(
+        &self,
+        encoder: &mut Encoder<R, C>,
+        camera: &CameraMatrices,
+    ) {
+        let bundle = match self.bundle {
+            Some(ref bundle) => bundle,
+            None => return,
+        };
+
+        // Only the camera's rotation matters for the background.
+        let mut rotation = camera.view;
+        rotation[(0, 3)] = 0.0;
+        rotation[(1, 3)] = 0.0;
+        rotation[(2, 3)] = 0.0;
+        let inverse_view_projection = (camera.projection * rotation)
+            .try_inverse()
+            .unwrap_or_else(::na::Matrix4::identity);
+
+        let opaque = |c: [f32; 3]| [c[0], c[1], c[2], 1.0];
+        let (mode, zenith, horizon, ground) = match self.background {
+            Background::Gradient {
+                zenith,
+                horizon,
+                ground,
+            } => (SKY_MODE_GRADIENT, opaque(zenith), opaque(horizon), opaque(ground)),
+            Background::Equirectangular(_) => {
+                (SKY_MODE_EQUIRECTANGULAR, [0.0; 4], [0.0; 4], [0.0; 4])
+            }
+            Background::CubeMap(_) => (SKY_MODE_CUBE_MAP, [0.0; 4], [0.0; 4], [0.0; 4]),
+            Background::Color(_) => unreachable!(),
+        };
+
+        encoder.update_constant_buffer(
+            &bundle.data.sky_locals,
+            &SkyLocals {
+                inverse_view_projection: *inverse_view_projection.as_ref(),
+                zenith,
+                horizon,
+                ground,
+                mode,
+                intensity: self.intensity,
+            },
+        );
+        bundle.encode(encoder);
+    }
+
+    #[inline]
+    pub fn set_targets(
+        &mut self,
+        rtv: RenderTargetView<R, ColorFormat>,
+        dsv: DepthStencilView<R, DepthFormat>,
+    ) {
+        if let Some(ref mut bundle) = self.bundle {
+            bundle.data.out = rtv;
+            bundle.data.main_depth = dsv;
+        }
+    }
+}
+
+impl<R: Resources> fmt::Debug for Skybox<R> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.debug_struct("Skybox")
+            .field("background", &self.background)
+            .field("intensity", &self.intensity)
+            .field("bundle", &self.bundle.as_ref().map(|_| "Bundle(...)"))
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum SkyboxError {
+    Pso(PipelineStateError<String>),
+    Texture(CombinedError),
+    Environment(LoadEnvironmentError),
+}
+
+impl fmt::Display for SkyboxError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            SkyboxError::Pso(ref e) => write!(fmtr, "{}: {}", desc, e),
+            SkyboxError::Texture(ref e) => write!(fmtr, "{}: {}", desc, e),
+            SkyboxError::Environment(ref e) => write!(fmtr, "{}: {}", desc, e),
+        }
+    }
+}
+
+impl Error for SkyboxError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            SkyboxError::Pso(_) => "There was an error creating the skybox pso",
+            SkyboxError::Texture(_) => "An error occured while loading the skybox on the gpu",
+            SkyboxError::Environment(_) => "The skybox images could not be loaded",
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            SkyboxError::Pso(ref e) => Some(e),
+            SkyboxError::Texture(ref e) => Some(e),
+            SkyboxError::Environment(ref e) => Some(e),
+        }
+    }
+}
+
+impl<'a> From<PipelineStateError<&'a str>> for SkyboxError {
+    #[inline]
+    fn from(e: PipelineStateError<&'a str>) -> Self {
+        SkyboxError::Pso(e.into())
+    }
+}
+
+impl From<CombinedError> for SkyboxError {
+    #[inline]
+    fn from(e: CombinedError) -> Self {
+        SkyboxError::Texture(e)
+    }
+}
+
+impl From<LoadEnvironmentError> for SkyboxError {
+    #[inline]
+    fn from(e: LoadEnvironmentError) -> Self {
+        SkyboxError::Environment(e)
+    }
+}
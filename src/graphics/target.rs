@@ -0,0 +1,361 @@
+use ::{ColorFormat, DepthFormat};
+use gfx::{self, Bundle, CombinedError, CommandBuffer, Encoder, PipelineStateError, Primitive,
+          Resources};
+use gfx::format::{ChannelTyped, Formatted, RenderFormat, Swizzle, TextureFormat};
+use gfx::handle::{DepthStencilView, RawTexture, RenderTargetView, ShaderResourceView, Texture};
+use gfx::memory::{Bind, Usage};
+use gfx::state::Rasterizer;
+use gfx::texture::{AaMode, FilterMethod, Kind, SamplerInfo, Size, WrapMode};
+use gfx::traits::Factory;
+use graphics::platform::{Backend, FactoryExt};
+use std::error::Error;
+use std::fmt;
+
+gfx_defines! {
+    vertex BlitVertex {
+        pos: [f32; 2] = "position",
+    }
+
+    pipeline blit_pipe {
+        vbuf: gfx::VertexBuffer<BlitVertex> = (),
+        source: gfx::TextureSampler<[f32; 4]> = "source",
+        out: gfx::RenderTarget<ColorFormat> = "Target0",
+    }
+
+    pipeline resolve_pipe {
+        vbuf: gfx::VertexBuffer<BlitVertex> = (),
+        source: gfx::TextureSampler<[f32; 4]> = "source",
+        samples: gfx::Global<i32> = "samples",
+        out: gfx::RenderTarget<ColorFormat> = "Target0",
+    }
+}
+
+const GLSL_BLIT_VERT_SRC: &'static [u8] = include_bytes!("../../data/shader/glsl/blit.vs");
+const GLSL_BLIT_FRAG_SRC: &'static [u8] = include_bytes!("../../data/shader/glsl/blit.fs");
+
+const MSL_BLIT_VERT_SRC: &'static [u8] = include_bytes!("../../data/shader/msl/blit.vs");
+const MSL_BLIT_FRAG_SRC: &'static [u8] = include_bytes!("../../data/shader/msl/blit.fs");
+
+const GLSL_RESOLVE_FRAG_SRC: &'static [u8] = include_bytes!("../../data/shader/glsl/resolve.fs");
+const MSL_RESOLVE_FRAG_SRC: &'static [u8] = include_bytes!("../../data/shader/msl/resolve.fs");
+
+const FULLSCREEN_TRIANGLE: [BlitVertex; 3] = [
+    BlitVertex { pos: [-1.0, -1.0] },
+    BlitVertex { pos: [3.0, -1.0] },
+    BlitVertex { pos: [-1.0, 3.0] },
+];
+
+/// Like `Factory::create_render_target`, except that the texture can also be
+/// copied from, so that its contents can be read back to the cpu.
+pub fn create_readable_render_target<R, F, T>(
+    factory: &mut F,
+    width: Size,
+    height: Size,
+) -> Result<
+    (Texture<R, T::Surface>, ShaderResourceView<R, T::View>, RenderTargetView<R, T>),
+    CombinedError,
+>
+where
+    R: Resources,
+    F: Factory<R>,
+    T: RenderFormat + TextureFormat,
+{
+    let kind = Kind::D2(width, height, AaMode::Single);
+    let channel = <T::Channel as ChannelTyped>::get_channel_type();
+    let bind = Bind::SHADER_RESOURCE | Bind::RENDER_TARGET | Bind::TRANSFER_SRC;
+    let texture = factory.create_texture(kind, 1, bind, Usage::Data, Some(channel))?;
+    let resource =
+        factory.view_texture_as_shader_resource::<T>(&texture, (0, 0), Swizzle::new())?;
+    let target = factory.view_texture_as_render_target(&texture, 0, None)?;
+    Ok((texture, resource, target))
+}
+
+/// A colour and depth target which the scene is drawn into before it is shown,
+/// so that every frame is also available as a texture.
+///
+/// With more than one sample, the scene is drawn into multisampled targets
+/// instead, which have to be resolved into the readable texture with
+/// `Resolve` before the frame is shown or read back.
+pub struct OffscreenTarget<R: Resources> {
+    color: Texture<R, <ColorFormat as Formatted>::Surface>,
+    color_view: ShaderResourceView<R, [f32; 4]>,
+    resolved_rtv: RenderTargetView<R, ColorFormat>,
+    multisampled: Option<Multisampled<R>>,
+    rtv: RenderTargetView<R, ColorFormat>,
+    dsv: DepthStencilView<R, DepthFormat>,
+}
+
+struct Multisampled<R: Resources> {
+    samples: u8,
+    color_view: ShaderResourceView<R, [f32; 4]>,
+}
+
+impl<R: Resources> OffscreenTarget<R> {
+    pub fn new<F: Factory<R>>(
+        factory: &mut F,
+        width: Size,
+        height: Size,
+        samples: u8,
+    ) -> Result<Self, CombinedError> {
+        let (color, color_view, resolved_rtv) =
+            create_readable_render_target::<_, _, ColorFormat>(factory, width, height)?;
+
+        let (multisampled, rtv, dsv) = if samples > 1 {
+            let kind = Kind::D2(width, height, AaMode::Multi(samples));
+            let color_channel =
+                <<ColorFormat as Formatted>::Channel as ChannelTyped>::get_channel_type();
+            let ms_color = factory.create_texture(
+                kind,
+                1,
+                Bind::SHADER_RESOURCE | Bind::RENDER_TARGET,
+                Usage::Data,
+                Some(color_channel),
+            )?;
+            let ms_color_view = factory.view_texture_as_shader_resource::<ColorFormat>(
+                &ms_color,
+                (0, 0),
+                Swizzle::new(),
+            )?;
+            let rtv = factory.view_texture_as_render_target(&ms_color, 0, None)?;
+
+            let depth_channel =
+                <<DepthFormat as Formatted>::Channel as ChannelTyped>::get_channel_type();
+            let ms_depth = factory.create_texture(
+                kind,
+                1,
+                Bind::DEPTH_STENCIL,
+                Usage::Data,
+                Some(depth_channel),
+            )?;
+            let dsv = factory.view_texture_as_depth_stencil_trivial::<DepthFormat>(&ms_depth)?;
+
+            let multisampled = Multisampled {
+                samples,
+                color_view: ms_color_view,
+            };
+            (Some(multisampled), rtv, dsv)
+        } else {
+            let (_, _, dsv) = factory.create_depth_stencil::<DepthFormat>(width, height)?;
+            (None, resolved_rtv.clone(), dsv)
+        };
+
+        Ok(OffscreenTarget {
+            color,
+            color_view,
+            resolved_rtv,
+            multisampled,
+            rtv,
+            dsv,
+        })
+    }
+
+    /// The view to draw the scene into, which is multisampled if the target is.
+    #[inline]
+    pub fn rtv(&self) -> &RenderTargetView<R, ColorFormat> {
+        &self.rtv
+    }
+
+    #[inline]
+    pub fn dsv(&self) -> &DepthStencilView<R, DepthFormat> {
+        &self.dsv
+    }
+
+    /// The single sampled texture which holds the finished frame.
+    #[inline]
+    pub fn color_texture(&self) -> &RawTexture<R> {
+        self.color.raw()
+    }
+
+    #[inline]
+    pub fn color_view(&self) -> &ShaderResourceView<R, [f32; 4]> {
+        &self.color_view
+    }
+
+    #[inline]
+    pub fn samples(&self) -> u8 {
+        self.multisampled.as_ref().map_or(1, |ms| ms.samples)
+    }
+
+    #[inline]
+    pub fn dimensions(&self) -> (Size, Size) {
+        let (w, h, _, _) = self.rtv.get_dimensions();
+        (w, h)
+    }
+}
+
+impl<R: Resources> fmt::Debug for OffscreenTarget<R> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.debug_struct("OffscreenTarget")
+            .field("dimensions", &self.dimensions())
+            .field("samples", &self.samples())
+            .finish()
+    }
+}
+
+/// Copies an offscreen target onto the window with a fullscreen triangle.
+pub struct Blit<R: Resources> {
+    bundle: Bundle<R, blit_pipe::Data<R>>,
+}
+
+impl<R: Resources> Blit<R> {
+    pub fn new<F: FactoryExt<R>>(
+        factory: &mut F,
+        backend: &Backend,
+        source: &OffscreenTarget<R>,
+        rtv: RenderTargetView<R, ColorFormat>,
+    ) -> Result<Self, BlitError> {
+        let program = if backend.is_gl() {
+            factory.link_program(GLSL_BLIT_VERT_SRC, GLSL_BLIT_FRAG_SRC).unwrap()
+        } else {
+            factory.link_program(MSL_BLIT_VERT_SRC, MSL_BLIT_FRAG_SRC).unwrap()
+        };
+
+        let pso = factory.create_pipeline_from_program(
+            &program,
+            Primitive::TriangleList,
+            Rasterizer::new_fill(),
+            blit_pipe::new(),
+        )?;
+
+        let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&FULLSCREEN_TRIANGLE, ());
+        let sampler = factory.create_sampler(SamplerInfo::new(FilterMethod::Scale, WrapMode::Clamp));
+
+        let data = blit_pipe::Data {
+            vbuf,
+            source: (source.color_view().clone(), sampler),
+            out: rtv,
+        };
+        Ok(Blit { bundle: Bundle::new(slice, pso, data) })
+    }
+
+    /// Reads from a different target, after the old one has been recreated at
+    /// a new size.
+    #[inline]
+    pub fn set_source(&mut self, source: &OffscreenTarget<R>) {
+        self.bundle.data.source.0 = source.color_view().clone();
+    }
+
+    #[inline]
+    pub fn encode<C: CommandBuffer<R>>(&self, encoder: &mut Encoder<R, C>) {
+        self.bundle.encode(encoder)
+    }
+
+    /// Writes to a different target, such as the window's after a resize.
+    #[inline]
+    pub fn set_target(&mut self, rtv: RenderTargetView<R, ColorFormat>) {
+        self.bundle.data.out = rtv;
+    }
+}
+
+impl<R: Resources> fmt::Debug for Blit<R> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.debug_struct("Blit").finish()
+    }
+}
+
+/// Averages the samples of a multisampled `OffscreenTarget` into its readable
+/// texture. Does nothing for targets with a single sample, which are drawn
+/// into that texture directly.
+pub struct Resolve<R: Resources> {
+    bundle: Option<Bundle<R, resolve_pipe::Data<R>>>,
+}
+
+impl<R: Resources> Resolve<R> {
+    pub fn new<F: FactoryExt<R>>(
+        factory: &mut F,
+        backend: &Backend,
+        target: &OffscreenTarget<R>,
+    ) -> Result<Self, BlitError> {
+        let multisampled = match target.multisampled {
+            Some(ref multisampled) => multisampled,
+            None => return Ok(Resolve { bundle: None }),
+        };
+
+        let program = if backend.is_gl() {
+            factory.link_program(GLSL_BLIT_VERT_SRC, GLSL_RESOLVE_FRAG_SRC).unwrap()
+        } else {
+            factory.link_program(MSL_BLIT_VERT_SRC, MSL_RESOLVE_FRAG_SRC).unwrap()
+        };
+
+        let pso = factory.create_pipeline_from_program(
+            &program,
+            Primitive::TriangleList,
+            Rasterizer::new_fill(),
+            resolve_pipe::new(),
+        )?;
+
+        let (vbuf, slice) = factory.create_vertex_buffer_with_slice(&FULLSCREEN_TRIANGLE, ());
+        let sampler = factory.create_sampler(SamplerInfo::new(FilterMethod::Scale, WrapMode::Clamp));
+
+        let data = resolve_pipe::Data {
+            vbuf,
+            source: (multisampled.color_view.clone(), sampler),
+            samples: multisampled.samples as i32,
+            out: target.resolved_rtv.clone(),
+        };
+        Ok(Resolve { bundle: Some(Bundle::new(slice, pso, data)) })
+    }
+
+    /// Resolves a different target, after the old one has been recreated at a
+    /// new size with the same number of samples.
+    pub fn set_target(&mut self, target: &OffscreenTarget<R>) {
+        if let (Some(ref mut bundle), Some(ref multisampled)) =
+            (self.bundle.as_mut(), target.multisampled.as_ref())
+        {
+            bundle.data.source.0 = multisampled.color_view.clone();
+            bundle.data.out = target.resolved_rtv.clone();
+        }
+    }
+
+    #[inline]
+    pub fn encode<C: CommandBuffer<R>>(&self, encoder: &mut Encoder<R, C>) {
+        if let Some(ref bundle) = self.bundle {
+            bundle.encode(encoder)
+        }
+    }
+}
+
+impl<R: Resources> fmt::Debug for Resolve<R> {
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.debug_struct("Resolve")
+            .field("is_multisampled", &self.bundle.is_some())
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub enum BlitError {
+    Pso(PipelineStateError<String>),
+}
+
+impl fmt::Display for BlitError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BlitError::Pso(ref e) => write!(fmtr, "{}: {}", self.description(), e),
+        }
+    }
+}
+
+impl Error for BlitError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            BlitError::Pso(_) => "There was an error creating the blit pso",
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            BlitError::Pso(ref e) => Some(e),
+        }
+    }
+}
+
+impl<'a> From<PipelineStateError<&'a str>> for BlitError {
+    #[inline]
+    fn from(e: PipelineStateError<&'a str>) -> Self {
+        BlitError::Pso(e.into())
+    }
+}
@@ -0,0 +1,172 @@
+use na::{Matrix4, Point3, UnitQuaternion, Vector3};
+
+/// Where a model is placed. It is scaled along its own axes and turned about
+/// its pivot, and then moved by `translation`, so that a model with no
+/// translation keeps its pivot where it was in the mesh.
+///
+/// Unlike a similarity, the scale can be different along each axis, which
+/// stretches the model, and negative, which mirrors it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+    pub scale: Vector3<f32>,
+    /// The point in the mesh which the model is scaled and turned about. It
+    /// is the origin of the mesh unless it is set.
+    pub pivot: Point3<f32>,
+}
+
+impl Default for Transform {
+    #[inline]
+    fn default() -> Self {
+        Transform {
+            translation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+            pivot: Point3::origin(),
+        }
+    }
+}
+
+impl Transform {
+    #[inline]
+    pub fn from_translation(translation: Vector3<f32>) -> Self {
+        Transform {
+            translation,
+            ..Default::default()
+        }
+    }
+
+    /// Where the pivot ends up, which is what the model is placed by.
+    #[inline]
+    pub fn position(&self) -> Point3<f32> {
+        self.pivot + self.translation
+    }
+
+    /// Whether the model is mirrored, which turns its triangles inside out.
+    #[inline]
+    pub fn is_mirrored(&self) -> bool {
+        self.scale.x * self.scale.y * self.scale.z < 0.0
+    }
+
+    /// The model matrix.
+    pub fn to_homogeneous(&self) -> Matrix4<f32> {
+        Matrix4::new_translation(&(self.position() - Point3::origin()))
+            * self.rotation.to_homogeneous()
+            * Matrix4::new_nonuniform_scaling(&self.scale)
+            * Matrix4::new_translation(&(Point3::origin() - self.pivot))
+    }
+
+    #[inline]
+    pub fn transform_point(&self, point: &Point3<f32>) -> Point3<f32> {
+        self.position() + self.rotation * (*point - self.pivot).component_mul(&self.scale)
+    }
+
+    /// Where a point in the world is in the mesh. The scale must not be zero
+    /// along any axis.
+    #[inline]
+    pub fn inverse_transform_point(&self, point: &Point3<f32>) -> Point3<f32> {
+        self.pivot + self.inverse_transform_vector(&(*point - self.position()))
+    }
+
+    #[inline]
+    pub fn inverse_transform_vector(&self, vector: &Vector3<f32>) -> Vector3<f32> {
+        (self.rotation.inverse() * *vector).component_div(&self.scale)
+    }
+
+    /// Turns the whole model about the origin of the world, moving it as well
+    /// as turning it unless it is at the origin.
+    pub fn append_rotation(&mut self, rotation: &UnitQuaternion<f32>) {
+        let position = *rotation * (self.position() - Point3::origin());
+        self.translation = position - (self.pivot - Point3::origin());
+        self.rotation = *rotation * self.rotation;
+    }
+
+    /// Blends two transforms, with `alpha` 0 giving `previous` and 1 this one.
+    pub fn interpolated(&self, previous: &Transform, alpha: f32) -> Transform {
+        let rotation = previous
+            .rotation
+            .try_slerp(&self.rotation, alpha, 1.0e-6)
+            .unwrap_or(self.rotation);
+        // A scale which changed sign jumps to its new value, rather than
+        // passing through zero and flattening the model on the way.
+        let mut scale = previous.scale + (self.scale - previous.scale) * alpha;
+        for i in 0..3 {
+            if previous.scale[i] * self.scale[i] < 0.0 {
+                scale[i] = self.scale[i];
+            }
+        }
+        Transform {
+            translation: previous.translation + (self.translation - previous.translation) * alpha,
+            rotation,
+            scale,
+            pivot: previous.pivot + (self.pivot - previous.pivot) * alpha,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Vector4;
+    use std::f32::consts::PI;
+
+    fn stretched() -> Transform {
+        Transform {
+            translation: Vector3::new(1.0, 2.0, 3.0),
+            rotation: UnitQuaternion::from_euler_angles(0.3, -0.7, 1.1),
+            scale: Vector3::new(2.0, -0.5, 3.0),
+            pivot: Point3::new(0.5, -1.0, 0.25),
+        }
+    }
+
+    #[test]
+    fn matrix_transforms_points_like_the_transform() {
+        let transform = stretched();
+        let matrix = transform.to_homogeneous();
+        let point = Point3::new(-1.5, 0.25, 4.0);
+        let expected = transform.transform_point(&point);
+        let actual = matrix * Vector4::new(point.x, point.y, point.z, 1.0);
+        assert!((Vector3::new(actual.x, actual.y, actual.z) - expected.coords).norm() < 1.0e-4);
+    }
+
+    #[test]
+    fn inverse_undoes_the_transform() {
+        let transform = stretched();
+        let point = Point3::new(-1.5, 0.25, 4.0);
+        let back = transform.inverse_transform_point(&transform.transform_point(&point));
+        assert!((back - point).norm() < 1.0e-4);
+        assert!(transform.is_mirrored());
+    }
+
+    #[test]
+    fn pivot_stays_put_without_translation() {
+        let transform = Transform {
+            translation: Vector3::zeros(),
+            ..stretched()
+        };
+        let pivot = transform.transform_point(&transform.pivot);
+        assert!((pivot - transform.pivot).norm() < 1.0e-5);
+    }
+
+    #[test]
+    fn appending_a_rotation_turns_about_the_world_origin() {
+        let mut transform = Transform::from_translation(Vector3::new(1.0, 0.0, 0.0));
+        transform.pivot = Point3::new(0.0, 0.0, 1.0);
+        transform.append_rotation(&UnitQuaternion::from_euler_angles(0.0, PI / 2.0, 0.0));
+        let position = transform.position();
+        assert!((position - Point3::new(1.0, 0.0, -1.0)).norm() < 1.0e-5);
+    }
+
+    #[test]
+    fn mirroring_between_steps_does_not_flatten() {
+        let previous = stretched();
+        let current = Transform {
+            scale: Vector3::new(-2.0, -1.5, 3.0),
+            ..previous
+        };
+        let halfway = current.interpolated(&previous, 0.5);
+        assert_eq!(halfway.scale, Vector3::new(-2.0, -1.0, 3.0));
+        assert_eq!(halfway.is_mirrored(), current.is_mirrored());
+    }
+}
@@ -0,0 +1,192 @@
+//! The things the viewer can be asked to do, independent of the keys which
+//! ask for them.
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Action {
+    MoveForwards,
+    MoveBackwards,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    Sprint,
+    MoveSlowly,
+    Jump,
+    Orbit,
+    Pan,
+    ToggleCameraMode,
+    ToggleWalking,
+    Select,
+    FrameSelected,
+    GizmoTranslate,
+    GizmoRotate,
+    GizmoScale,
+    Snap,
+    ToggleProjection,
+    ViewFront,
+    ViewBack,
+    ViewLeft,
+    ViewRight,
+    ViewTop,
+    ViewBottom,
+    WidenFov,
+    NarrowFov,
+    PushNearPlane,
+    PullNearPlane,
+    PushFarPlane,
+    PullFarPlane,
+    AddKeyframe,
+    PlayCameraPath,
+    RewindCameraPath,
+    ToggleFps,
+    Screenshot,
+    ToggleRecording,
+    Quit,
+}
+
+impl Action {
+    pub const ALL: &'static [Action] = &[
+        Action::MoveForwards,
+        Action::MoveBackwards,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::Sprint,
+        Action::MoveSlowly,
+        Action::Jump,
+        Action::Orbit,
+        Action::Pan,
+        Action::ToggleCameraMode,
+        Action::ToggleWalking,
+        Action::Select,
+        Action::FrameSelected,
+        Action::GizmoTranslate,
+        Action::GizmoRotate,
+        Action::GizmoScale,
+        Action::Snap,
+        Action::ToggleProjection,
+        Action::ViewFront,
+        Action::ViewBack,
+        Action::ViewLeft,
+        Action::ViewRight,
+        Action::ViewTop,
+        Action::ViewBottom,
+        Action::WidenFov,
+        Action::NarrowFov,
+        Action::PushNearPlane,
+        Action::PullNearPlane,
+        Action::PushFarPlane,
+        Action::PullFarPlane,
+        Action::AddKeyframe,
+        Action::PlayCameraPath,
+        Action::RewindCameraPath,
+        Action::ToggleFps,
+        Action::Screenshot,
+        Action::ToggleRecording,
+        Action::Quit,
+    ];
+
+    /// The name of the action, as it is written in the config and in
+    /// recordings.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Action::MoveForwards => "move_forwards",
+            Action::MoveBackwards => "move_backwards",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::Sprint => "sprint",
+            Action::MoveSlowly => "move_slowly",
+            Action::Jump => "jump",
+            Action::Orbit => "orbit",
+            Action::Pan => "pan",
+            Action::ToggleCameraMode => "toggle_camera_mode",
+            Action::ToggleWalking => "toggle_walking",
+            Action::Select => "select",
+            Action::FrameSelected => "frame_selected",
+            Action::GizmoTranslate => "gizmo_translate",
+            Action::GizmoRotate => "gizmo_rotate",
+            Action::GizmoScale => "gizmo_scale",
+            Action::Snap => "snap",
+            Action::ToggleProjection => "toggle_projection",
+            Action::ViewFront => "view_front",
+            Action::ViewBack => "view_back",
+            Action::ViewLeft => "view_left",
+            Action::ViewRight => "view_right",
+            Action::ViewTop => "view_top",
+            Action::ViewBottom => "view_bottom",
+            Action::WidenFov => "widen_fov",
+            Action::NarrowFov => "narrow_fov",
+            Action::PushNearPlane => "push_near_plane",
+            Action::PullNearPlane => "pull_near_plane",
+            Action::PushFarPlane => "push_far_plane",
+            Action::PullFarPlane => "pull_far_plane",
+            Action::AddKeyframe => "add_keyframe",
+            Action::PlayCameraPath => "play_camera_path",
+            Action::RewindCameraPath => "rewind_camera_path",
+            Action::ToggleFps => "toggle_fps",
+            Action::Screenshot => "screenshot",
+            Action::ToggleRecording => "toggle_recording",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL.iter().cloned().find(|action| action.name() == name)
+    }
+}
+
+impl fmt::Display for Action {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.write_str(self.name())
+    }
+}
+
+/// Things which can be done partly, such as moving slowly with a stick pushed
+/// only a little way. Values go from -1 to 1, with positive values moving
+/// right, forwards or up, and turning right or down.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AnalogueAction {
+    MoveRight,
+    MoveForwards,
+    MoveUp,
+    TurnRight,
+    TurnDown,
+}
+
+impl AnalogueAction {
+    pub const ALL: &'static [AnalogueAction] = &[
+        AnalogueAction::MoveRight,
+        AnalogueAction::MoveForwards,
+        AnalogueAction::MoveUp,
+        AnalogueAction::TurnRight,
+        AnalogueAction::TurnDown,
+    ];
+
+    /// The name of the action, as it is written in recordings.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            AnalogueAction::MoveRight => "move_right",
+            AnalogueAction::MoveForwards => "move_forwards",
+            AnalogueAction::MoveUp => "move_up",
+            AnalogueAction::TurnRight => "turn_right",
+            AnalogueAction::TurnDown => "turn_down",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AnalogueAction> {
+        AnalogueAction::ALL.iter().cloned().find(|action| action.name() == name)
+    }
+}
+
+impl fmt::Display for AnalogueAction {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        fmtr.write_str(self.name())
+    }
+}
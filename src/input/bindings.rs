@@ -0,0 +1,273 @@
+//! Turns keys, mouse buttons and gamepad buttons into actions, as the config
+//! binds them.
+//!
+//! A binding is written as the name of an input, such as `"W"`,
+//! `"MouseRight"` or `"GamepadSouth"`, optionally after other inputs which
+//! must be held at the same time, joined with `+`, as in `"Ctrl+S"`. `Ctrl`,
+//! `Shift`, `Alt` and `Logo` stand for the key on either side. An action can
+//! have any number of bindings, and is active while any one of them is held.
+
+use config::KeyBindings;
+use input::action::Action;
+use input::gamepad::GamepadButton;
+use input::keys::key_from_name;
+use input::recording::InputEvent;
+use std::collections::HashSet;
+use winit::{MouseButton, VirtualKeyCode};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Input {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+impl Input {
+    pub fn from_name(name: &str) -> Option<Input> {
+        if let Some(key) = key_from_name(name) {
+            return Some(Input::Key(key));
+        }
+        if let Some(button) = GamepadButton::from_name(name) {
+            return Some(Input::Gamepad(button));
+        }
+        let button = if name.eq_ignore_ascii_case("MouseLeft") {
+            MouseButton::Left
+        } else if name.eq_ignore_ascii_case("MouseRight") {
+            MouseButton::Right
+        } else if name.eq_ignore_ascii_case("MouseMiddle") {
+            MouseButton::Middle
+        } else if name.get(..5).map_or(false, |prefix| prefix.eq_ignore_ascii_case("Mouse")) {
+            MouseButton::Other(name[5..].parse().ok()?)
+        } else {
+            return None;
+        };
+        Some(Input::Mouse(button))
+    }
+}
+
+/// One part of a binding, which is either a single input or a modifier key on
+/// either side of the keyboard.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum Part {
+    Input(Input),
+    EitherKey(VirtualKeyCode, VirtualKeyCode),
+}
+
+impl Part {
+    fn from_name(name: &str) -> Option<Part> {
+        let either = |left, right| Some(Part::EitherKey(left, right));
+        match name.to_ascii_lowercase().as_str() {
+            "ctrl" | "control" => either(VirtualKeyCode::LControl, VirtualKeyCode::RControl),
+            "shift" => either(VirtualKeyCode::LShift, VirtualKeyCode::RShift),
+            "alt" => either(VirtualKeyCode::LAlt, VirtualKeyCode::RAlt),
+            "logo" => either(VirtualKeyCode::LWin, VirtualKeyCode::RWin),
+            _ => Input::from_name(name).map(Part::Input),
+        }
+    }
+
+    fn matches(&self, input: Input) -> bool {
+        match *self {
+            Part::Input(part) => part == input,
+            Part::EitherKey(left, right) => {
+                input == Input::Key(left) || input == Input::Key(right)
+            }
+        }
+    }
+
+    fn is_held(&self, held: &HashSet<Input>) -> bool {
+        held.iter().any(|&input| self.matches(input))
+    }
+}
+
+/// An input, and the inputs which must be held with it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Binding {
+    modifiers: Vec<Part>,
+    trigger: Part,
+}
+
+impl Binding {
+    /// Parses a binding like `"Ctrl+S"`, or returns `None` if any part of it
+    /// is not the name of an input.
+    pub fn parse(text: &str) -> Option<Binding> {
+        let mut parts = text.split('+')
+            .map(|name| Part::from_name(name.trim()))
+            .collect::<Option<Vec<_>>>()?;
+        let trigger = parts.pop()?;
+        Some(Binding {
+            modifiers: parts,
+            trigger,
+        })
+    }
+
+    fn is_held(&self, held: &HashSet<Input>) -> bool {
+        self.trigger.is_held(held) && self.modifiers.iter().all(|part| part.is_held(held))
+    }
+}
+
+/// Tracks which inputs are held, and reports actions starting and stopping.
+#[derive(Clone, Debug)]
+pub struct ActionMap {
+    bindings: Vec<(Action, Binding)>,
+    held: HashSet<Input>,
+    active: HashSet<Action>,
+}
+
+impl ActionMap {
+    /// Bindings which are not valid are left out, see
+    /// `KeyBindings::invalid_bindings`.
+    pub fn new(key_bindings: &KeyBindings) -> Self {
+        let bindings = Action::ALL
+            .iter()
+            .flat_map(|&action| {
+                key_bindings
+                    .bindings(action)
+                    .iter()
+                    .filter_map(move |text| Binding::parse(text).map(|b| (action, b)))
+            })
+            .collect();
+        ActionMap {
+            bindings,
+            held: HashSet::new(),
+            active: HashSet::new(),
+        }
+    }
+
+    /// Notes an input being pressed or released, and adds the actions this
+    /// starts or stops to `events`.
+    ///
+    /// When several bindings of the same input are held, only the ones with
+    /// the most modifiers start their actions, so that `Ctrl+S` does not also
+    /// do what `S` does.
+    pub fn on_input(&mut self, input: Input, is_pressed: bool, events: &mut Vec<InputEvent>) {
+        if is_pressed {
+            // Keys repeat while they are held, which does not start anything
+            // new.
+            if !self.held.insert(input) {
+                return;
+            }
+            let held = &self.held;
+            let triggered = self.bindings
+                .iter()
+                .filter(|&&(_, ref binding)| {
+                    binding.trigger.matches(input) && binding.is_held(held)
+                })
+                .collect::<Vec<_>>();
+            let most_modifiers = triggered
+                .iter()
+                .map(|&&(_, ref binding)| binding.modifiers.len())
+                .max()
+                .unwrap_or(0);
+            for &&(action, ref binding) in &triggered {
+                if binding.modifiers.len() == most_modifiers && self.active.insert(action) {
+                    events.push(InputEvent::Action(action, true));
+                }
+            }
+        } else {
+            self.held.remove(&input);
+            self.stop_released_actions(events);
+        }
+    }
+
+    /// Releases everything, such as when the window loses focus and so will
+    /// not be told about inputs being released.
+    pub fn release_all(&mut self, events: &mut Vec<InputEvent>) {
+        self.held.clear();
+        self.stop_released_actions(events);
+    }
+
+    fn stop_released_actions(&mut self, events: &mut Vec<InputEvent>) {
+        let held = &self.held;
+        let bindings = &self.bindings;
+        let mut stopped = self.active
+            .iter()
+            .cloned()
+            .filter(|&action| {
+                !bindings.iter().any(|&(a, ref binding)| a == action && binding.is_held(held))
+            })
+            .collect::<Vec<_>>();
+        // The set has no order, and recordings should not depend on it.
+        stopped.sort_by_key(|action| Action::ALL.iter().position(|a| a == action));
+        for action in stopped {
+            self.active.remove(&action);
+            events.push(InputEvent::Action(action, false));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: VirtualKeyCode) -> Input {
+        Input::Key(key)
+    }
+
+    fn input(map: &mut ActionMap, input: Input, is_pressed: bool) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        map.on_input(input, is_pressed, &mut events);
+        events
+    }
+
+    #[test]
+    fn bindings_parse_inputs_and_modifiers() {
+        let binding = Binding::parse("Ctrl+Shift+s").unwrap();
+        assert_eq!(binding.modifiers.len(), 2);
+        assert_eq!(binding.trigger, Part::Input(key(VirtualKeyCode::S)));
+        assert_eq!(Input::from_name("MouseRight"), Some(Input::Mouse(MouseButton::Right)));
+        assert_eq!(Input::from_name("Mouse4"), Some(Input::Mouse(MouseButton::Other(4))));
+        assert_eq!(Binding::parse("Ctrl+Nothing"), None);
+        assert_eq!(Binding::parse(""), None);
+    }
+
+    #[test]
+    fn chord_with_the_most_modifiers_wins() {
+        let mut map = ActionMap::new(&KeyBindings::default());
+        let ctrl = key(VirtualKeyCode::LControl);
+        assert_eq!(input(&mut map, ctrl, true), vec![InputEvent::Action(Action::Snap, true)]);
+
+        // Ctrl is still held, so it snaps as well, but P and Numpad1 do what
+        // they do with Ctrl rather than on their own.
+        let rewind = input(&mut map, key(VirtualKeyCode::P), true);
+        assert_eq!(rewind, vec![InputEvent::Action(Action::RewindCameraPath, true)]);
+        let back = input(&mut map, key(VirtualKeyCode::Numpad1), true);
+        assert_eq!(back, vec![InputEvent::Action(Action::ViewBack, true)]);
+    }
+
+    #[test]
+    fn releasing_the_modifier_first_stops_the_chord() {
+        let mut map = ActionMap::new(&KeyBindings::default());
+        input(&mut map, key(VirtualKeyCode::RControl), true);
+        input(&mut map, key(VirtualKeyCode::P), true);
+
+        // Releasing the other Ctrl key leaves Ctrl held.
+        assert!(input(&mut map, key(VirtualKeyCode::LControl), false).is_empty());
+        let released = input(&mut map, key(VirtualKeyCode::RControl), false);
+        assert_eq!(
+            released,
+            vec![
+                InputEvent::Action(Action::Snap, false),
+                InputEvent::Action(Action::RewindCameraPath, false),
+            ]
+        );
+        // P on its own was never started, so nothing is left to stop.
+        assert!(input(&mut map, key(VirtualKeyCode::P), false).is_empty());
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut map = ActionMap::new(&KeyBindings::default());
+        input(&mut map, key(VirtualKeyCode::W), true);
+        input(&mut map, key(VirtualKeyCode::LShift), true);
+        let mut events = Vec::new();
+        map.release_all(&mut events);
+        assert_eq!(
+            events,
+            vec![
+                InputEvent::Action(Action::MoveForwards, false),
+                InputEvent::Action(Action::Sprint, false),
+            ]
+        );
+        assert!(input(&mut map, key(VirtualKeyCode::W), false).is_empty());
+    }
+}
@@ -0,0 +1,390 @@
+//! Gamepads, which move the camera with their sticks and triggers and do
+//! actions with their buttons.
+//!
+//! Buttons are named after their position on the pad rather than the label
+//! printed on them, since labels differ between makers. Gamepads can be
+//! connected and disconnected while the viewer runs.
+
+#![cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+
+use config::GamepadConfig;
+#[cfg(feature = "gamepad")]
+use gilrs::{self, EventType, Gilrs};
+use input::action::AnalogueAction;
+use input::bindings::{ActionMap, Input};
+use input::recording::InputEvent;
+#[cfg(test)]
+use std::collections::VecDeque;
+use std::collections::{BTreeMap, HashMap};
+
+macro_rules! gamepad_buttons {
+    ($($button:ident),* $(,)*) => {
+        #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+        pub enum GamepadButton {
+            $($button,)*
+        }
+
+        impl GamepadButton {
+            /// The button with the given name, which is `Gamepad` followed by
+            /// the name of the variant, matched ignoring case.
+            pub fn from_name(name: &str) -> Option<GamepadButton> {
+                let name = match (name.get(..PREFIX.len()), name.get(PREFIX.len()..)) {
+                    (Some(prefix), Some(name)) if prefix.eq_ignore_ascii_case(PREFIX) => name,
+                    _ => return None,
+                };
+                $(
+                    if name.eq_ignore_ascii_case(stringify!($button)) {
+                        return Some(GamepadButton::$button);
+                    }
+                )*
+                None
+            }
+
+            #[cfg(feature = "gamepad")]
+            fn from_gilrs(button: gilrs::Button) -> Option<GamepadButton> {
+                match button {
+                    $(gilrs::Button::$button => Some(GamepadButton::$button),)*
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+const PREFIX: &str = "Gamepad";
+
+gamepad_buttons! {
+    South, East, North, West,
+    LeftTrigger, LeftTrigger2, RightTrigger, RightTrigger2,
+    Select, Start, Mode,
+    LeftThumb, RightThumb,
+    DPadUp, DPadDown, DPadLeft, DPadRight,
+}
+
+/// The analogue inputs of a gamepad. Sticks go from -1 to 1, with up being
+/// positive, and triggers from 0 to 1.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Connected,
+    Disconnected,
+    Button(GamepadButton, bool),
+    Axis(GamepadAxis, f32),
+}
+
+/// Somewhere gamepad events come from.
+pub trait GamepadSource {
+    /// The next event from any gamepad, with the id of the gamepad it came
+    /// from.
+    fn next_event(&mut self) -> Option<(usize, GamepadEvent)>;
+}
+
+#[cfg(feature = "gamepad")]
+impl GamepadSource for Gilrs {
+    fn next_event(&mut self) -> Option<(usize, GamepadEvent)> {
+        loop {
+            let event = Gilrs::next_event(self)?;
+            let gamepad_event = match event.event {
+                EventType::Connected => Some(GamepadEvent::Connected),
+                EventType::Disconnected => Some(GamepadEvent::Disconnected),
+                EventType::ButtonPressed(button, _) => {
+                    GamepadButton::from_gilrs(button).map(|b| GamepadEvent::Button(b, true))
+                }
+                EventType::ButtonReleased(button, _) => {
+                    GamepadButton::from_gilrs(button).map(|b| GamepadEvent::Button(b, false))
+                }
+                EventType::ButtonChanged(gilrs::Button::LeftTrigger2, value, _) => {
+                    Some(GamepadEvent::Axis(GamepadAxis::LeftTrigger, value))
+                }
+                EventType::ButtonChanged(gilrs::Button::RightTrigger2, value, _) => {
+                    Some(GamepadEvent::Axis(GamepadAxis::RightTrigger, value))
+                }
+                EventType::AxisChanged(axis, value, _) => {
+                    let axis = match axis {
+                        gilrs::Axis::LeftStickX => Some(GamepadAxis::LeftStickX),
+                        gilrs::Axis::LeftStickY => Some(GamepadAxis::LeftStickY),
+                        gilrs::Axis::RightStickX => Some(GamepadAxis::RightStickX),
+                        gilrs::Axis::RightStickY => Some(GamepadAxis::RightStickY),
+                        _ => None,
+                    };
+                    axis.map(|axis| GamepadEvent::Axis(axis, value))
+                }
+                _ => None,
+            };
+            if let Some(gamepad_event) = gamepad_event {
+                return Some((event.id.into(), gamepad_event));
+            }
+        }
+    }
+}
+
+/// Opens the platform's gamepads, or returns `None` if it has none.
+#[cfg(feature = "gamepad")]
+pub fn open_gamepads(config: &GamepadConfig) -> Option<Gamepads<Gilrs>> {
+    match Gilrs::new() {
+        Ok(gilrs) => Some(Gamepads::new(gilrs, config.clone())),
+        Err(e) => {
+            eprintln!("Gamepads cannot be used: {}", e);
+            None
+        }
+    }
+}
+
+/// A gamepad which is driven by code rather than by a player.
+#[cfg(test)]
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VirtualGamepad {
+    events: VecDeque<(usize, GamepadEvent)>,
+}
+
+#[cfg(test)]
+impl VirtualGamepad {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    #[inline]
+    pub fn send(&mut self, id: usize, event: GamepadEvent) {
+        self.events.push_back((id, event));
+    }
+}
+
+#[cfg(test)]
+impl GamepadSource for VirtualGamepad {
+    #[inline]
+    fn next_event(&mut self) -> Option<(usize, GamepadEvent)> {
+        self.events.pop_front()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Pad {
+    left_stick: (f32, f32),
+    right_stick: (f32, f32),
+    left_trigger: f32,
+    right_trigger: f32,
+    held: Vec<GamepadButton>,
+}
+
+/// Tracks the connected gamepads, and turns what they do into input events.
+#[derive(Debug)]
+pub struct Gamepads<S> {
+    source: S,
+    config: GamepadConfig,
+    pads: BTreeMap<usize, Pad>,
+    /// The last value reported for each analogue action.
+    values: HashMap<AnalogueAction, f32>,
+}
+
+impl<S: GamepadSource> Gamepads<S> {
+    pub fn new(source: S, config: GamepadConfig) -> Self {
+        Gamepads {
+            source,
+            config,
+            pads: BTreeMap::new(),
+            values: HashMap::new(),
+        }
+    }
+
+    /// Reads the events from the source, passes buttons on to the action map,
+    /// and adds an event for every analogue action which changed.
+    ///
+    /// The sticks and triggers of every connected gamepad are added together,
+    /// so that any of them can be used.
+    pub fn poll(&mut self, action_map: &mut ActionMap, events: &mut Vec<InputEvent>) {
+        while let Some((id, event)) = self.source.next_event() {
+            match event {
+                GamepadEvent::Connected => {
+                    eprintln!("Gamepad {} connected", id);
+                    self.pads.entry(id).or_insert_with(Pad::default);
+                }
+                GamepadEvent::Disconnected => {
+                    eprintln!("Gamepad {} disconnected", id);
+                    if let Some(pad) = self.pads.remove(&id) {
+                        for button in pad.held {
+                            action_map.on_input(Input::Gamepad(button), false, events);
+                        }
+                    }
+                }
+                GamepadEvent::Button(button, is_pressed) => {
+                    let pad = self.pads.entry(id).or_insert_with(Pad::default);
+                    pad.held.retain(|&held| held != button);
+                    if is_pressed {
+                        pad.held.push(button);
+                    }
+                    action_map.on_input(Input::Gamepad(button), is_pressed, events);
+                }
+                GamepadEvent::Axis(axis, value) => {
+                    let pad = self.pads.entry(id).or_insert_with(Pad::default);
+                    match axis {
+                        GamepadAxis::LeftStickX => pad.left_stick.0 = value,
+                        GamepadAxis::LeftStickY => pad.left_stick.1 = value,
+                        GamepadAxis::RightStickX => pad.right_stick.0 = value,
+                        GamepadAxis::RightStickY => pad.right_stick.1 = value,
+                        GamepadAxis::LeftTrigger => pad.left_trigger = value,
+                        GamepadAxis::RightTrigger => pad.right_trigger = value,
+                    }
+                }
+            }
+        }
+
+        for &action in AnalogueAction::ALL {
+            let value = self.value(action);
+            if self.values.get(&action).map_or(value != 0.0, |&previous| previous != value) {
+                self.values.insert(action, value);
+                events.push(InputEvent::Analogue(action, value));
+            }
+        }
+    }
+
+    fn value(&self, action: AnalogueAction) -> f32 {
+        let config = &self.config;
+        let total = self.pads
+            .values()
+            .map(|pad| {
+                let stick = |(x, y)| shape_stick(x, y, config.dead_zone, config.response_exponent);
+                let trigger = |value| {
+                    shape_trigger(value, config.trigger_dead_zone, config.response_exponent)
+                };
+                match action {
+                    AnalogueAction::MoveRight => stick(pad.left_stick).0,
+                    AnalogueAction::MoveForwards => stick(pad.left_stick).1,
+                    AnalogueAction::MoveUp => {
+                        trigger(pad.right_trigger) - trigger(pad.left_trigger)
+                    }
+                    AnalogueAction::TurnRight => stick(pad.right_stick).0,
+                    AnalogueAction::TurnDown => -stick(pad.right_stick).1,
+                }
+            })
+            .sum::<f32>();
+        total.max(-1.0).min(1.0)
+    }
+}
+
+/// Ignores the stick while it is within `dead_zone` of the centre, and raises
+/// how far it is past that to the power of `exponent`, so that small
+/// movements can be made precisely. The dead zone is round rather than square,
+/// so that pushing a stick diagonally does not snap it to an axis.
+pub fn shape_stick(x: f32, y: f32, dead_zone: f32, exponent: f32) -> (f32, f32) {
+    let length = (x * x + y * y).sqrt();
+    if length <= dead_zone || length == 0.0 {
+        return (0.0, 0.0);
+    }
+    let shaped = shape_trigger(length.min(1.0), dead_zone, exponent);
+    (x / length * shaped, y / length * shaped)
+}
+
+/// Like `shape_stick`, for a value from 0 to 1.
+pub fn shape_trigger(value: f32, dead_zone: f32, exponent: f32) -> f32 {
+    if value <= dead_zone {
+        return 0.0;
+    }
+    ((value - dead_zone) / (1.0 - dead_zone)).min(1.0).powf(exponent)
+}
+
+#[cfg(test)]
+mod tests {
+    use config::Config;
+    use input::action::Action;
+    use super::*;
+
+    fn gamepads() -> (Gamepads<VirtualGamepad>, ActionMap) {
+        let mut config = Config::default();
+        config.key_bindings.quit = vec!["GamepadStart".to_owned()];
+        let gamepads = Gamepads::new(VirtualGamepad::new(), GamepadConfig::default());
+        (gamepads, ActionMap::new(&config.key_bindings))
+    }
+
+    fn poll(
+        gamepads: &mut Gamepads<VirtualGamepad>,
+        action_map: &mut ActionMap,
+    ) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        gamepads.poll(action_map, &mut events);
+        events
+    }
+
+    #[test]
+    fn stick_within_dead_zone_does_nothing() {
+        let (mut gamepads, mut action_map) = gamepads();
+        gamepads.source.send(0, GamepadEvent::Connected);
+        gamepads.source.send(0, GamepadEvent::Axis(GamepadAxis::LeftStickX, 0.1));
+        gamepads.source.send(0, GamepadEvent::Axis(GamepadAxis::LeftStickY, -0.05));
+        assert_eq!(poll(&mut gamepads, &mut action_map), vec![]);
+    }
+
+    #[test]
+    fn sticks_move_and_turn() {
+        let (mut gamepads, mut action_map) = gamepads();
+        gamepads.source.send(0, GamepadEvent::Axis(GamepadAxis::LeftStickY, 1.0));
+        gamepads.source.send(0, GamepadEvent::Axis(GamepadAxis::RightStickX, -1.0));
+        assert_eq!(
+            poll(&mut gamepads, &mut action_map),
+            vec![
+                InputEvent::Analogue(AnalogueAction::MoveForwards, 1.0),
+                InputEvent::Analogue(AnalogueAction::TurnRight, -1.0),
+            ]
+        );
+
+        // Nothing changed, so nothing is reported again.
+        assert_eq!(poll(&mut gamepads, &mut action_map), vec![]);
+    }
+
+    #[test]
+    fn response_curve_makes_small_movements_smaller() {
+        let config = GamepadConfig::default();
+        let (_, y) = shape_stick(0.0, 0.6, config.dead_zone, config.response_exponent);
+        let linear = (0.6 - config.dead_zone) / (1.0 - config.dead_zone);
+        assert!(y > 0.0 && y < linear);
+        assert_eq!(shape_stick(0.0, 1.0, config.dead_zone, config.response_exponent), (0.0, 1.0));
+    }
+
+    #[test]
+    fn triggers_move_up_and_down() {
+        let (mut gamepads, mut action_map) = gamepads();
+        gamepads.source.send(0, GamepadEvent::Axis(GamepadAxis::RightTrigger, 1.0));
+        assert_eq!(
+            poll(&mut gamepads, &mut action_map),
+            vec![InputEvent::Analogue(AnalogueAction::MoveUp, 1.0)]
+        );
+        gamepads.source.send(0, GamepadEvent::Axis(GamepadAxis::LeftTrigger, 1.0));
+        assert_eq!(
+            poll(&mut gamepads, &mut action_map),
+            vec![InputEvent::Analogue(AnalogueAction::MoveUp, 0.0)]
+        );
+    }
+
+    #[test]
+    fn disconnecting_releases_everything() {
+        let (mut gamepads, mut action_map) = gamepads();
+        gamepads.source.send(3, GamepadEvent::Connected);
+        gamepads.source.send(3, GamepadEvent::Button(GamepadButton::Start, true));
+        gamepads.source.send(3, GamepadEvent::Axis(GamepadAxis::LeftStickX, 1.0));
+        assert_eq!(
+            poll(&mut gamepads, &mut action_map),
+            vec![
+                InputEvent::Action(Action::Quit, true),
+                InputEvent::Analogue(AnalogueAction::MoveRight, 1.0),
+            ]
+        );
+
+        gamepads.source.send(3, GamepadEvent::Disconnected);
+        assert_eq!(
+            poll(&mut gamepads, &mut action_map),
+            vec![
+                InputEvent::Action(Action::Quit, false),
+                InputEvent::Analogue(AnalogueAction::MoveRight, 0.0),
+            ]
+        );
+    }
+}
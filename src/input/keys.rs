@@ -0,0 +1,42 @@
+//! Names for keys, as they are written in the config file.
+
+use winit::VirtualKeyCode;
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)*) => {
+        /// The name of a key, which is the name of its `VirtualKeyCode`
+        /// variant, or `None` for keys which cannot be bound.
+        pub fn key_name(key: VirtualKeyCode) -> Option<&'static str> {
+            match key {
+                $(VirtualKeyCode::$key => Some(stringify!($key)),)*
+                _ => None,
+            }
+        }
+
+        /// The key with the given name. Names are matched ignoring case, so
+        /// `"w"` and `"W"` are the same key.
+        pub fn key_from_name(name: &str) -> Option<VirtualKeyCode> {
+            $(
+                if name.eq_ignore_ascii_case(stringify!($key)) {
+                    return Some(VirtualKeyCode::$key);
+                }
+            )*
+            None
+        }
+    };
+}
+
+key_names! {
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Insert, Home, Delete, End, PageDown, PageUp,
+    Left, Up, Right, Down,
+    Back, Return, Space, Tab,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4,
+    Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    Add, Subtract, Multiply, Divide, Decimal, NumpadEnter,
+    Apostrophe, Backslash, Comma, Equals, Grave, LBracket, Minus, Period, RBracket,
+    Semicolon, Slash,
+    LAlt, LControl, LShift, LWin, RAlt, RControl, RShift, RWin,
+}
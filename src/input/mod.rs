@@ -0,0 +1,5 @@
+pub mod action;
+pub mod bindings;
+pub mod gamepad;
+pub mod keys;
+pub mod recording;
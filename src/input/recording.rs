@@ -0,0 +1,467 @@
+//! Records the input the viewer acts on, with the time each frame took, so
+//! that a session can be played back exactly.
+//!
+//! Recordings are plain text, one line per frame or event, so that they can be
+//! attached to bug reports and read or trimmed by hand:
+//!
+//! ```text
+//! gfx_tutorial recording 1
+//! size 1280 720
+//! camera 7 0.05 120 0.3 45 0.1 100 0 false 10 8 3 0.25 4 5 9.81 0.3 1.8 1.6
+//! update_rate 120
+//! gizmo 0.15 0.5 15 0.25
+//! frame 0.016
+//! action move_forwards press
+//! look 3 -1.5
+//! analogue turn_right 0.25
+//! scroll -1
+//! pick 0.25 -0.5
+//! drag 0.3 -0.5
+//! frame 0.017
+//! focus false
+//! ```
+//!
+//! Each `frame` line starts a frame, and the events after it happened during
+//! that frame.
+
+use config::{CameraConfig, GizmoConfig, GraphicsConfig};
+use input::action::{Action, AnalogueAction};
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::{FromStr, SplitWhitespace};
+
+const HEADER: &str = "gfx_tutorial recording 1";
+
+/// Input, as the viewer acts on it rather than as the window reports it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InputEvent {
+    /// An action was started or stopped.
+    Action(Action, bool),
+    /// The mouse moved this far right and down. While the cursor is grabbed
+    /// this is the raw movement of the mouse, which is usually in pixels, and
+    /// otherwise it is how far the cursor moved in logical pixels.
+    Look(f32, f32),
+    /// The mouse wheel turned this many lines, with positive values away from
+    /// the user.
+    Scroll(f32),
+    /// An analogue action changed to a value from -1 to 1.
+    Analogue(AnalogueAction, f32),
+    /// Whatever is under this point on the screen was selected, where the
+    /// point is in normalized device coordinates, from -1 to 1 with y up.
+    Pick(f32, f32),
+    /// The cursor moved to this point on the screen while selecting, which
+    /// drags whatever was grabbed. The point is given as for `Pick`.
+    Drag(f32, f32),
+    /// The window gained or lost focus.
+    Focus(bool),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordedFrame {
+    /// How long the frame took, in seconds.
+    pub dt_s: f64,
+    pub events: Vec<InputEvent>,
+}
+
+/// The settings which change what input does, and the input itself.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recording {
+    pub window_size: (u32, u32),
+    pub camera: CameraConfig,
+    pub update_rate: f64,
+    pub gizmo: GizmoConfig,
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, RecordingError> {
+        let file = BufReader::new(File::open(path)?);
+        let mut lines = file.lines().enumerate();
+        let mut next_line = || -> Result<Option<(usize, String)>, RecordingError> {
+            match lines.next() {
+                Some((i, line)) => Ok(Some((i + 1, line?))),
+                None => Ok(None),
+            }
+        };
+
+        match next_line()? {
+            Some((_, ref line)) if line.trim() == HEADER => (),
+            _ => return Err(RecordingError::NotARecording),
+        }
+
+        let mut recording = Recording {
+            window_size: (0, 0),
+            camera: Default::default(),
+            update_rate: GraphicsConfig::default().update_rate,
+            // Recordings made before the gizmo was added have no gizmo line.
+            gizmo: Default::default(),
+            frames: Vec::new(),
+        };
+        while let Some((number, line)) = next_line()? {
+            if recording.parse_line(&line).is_none() {
+                return Err(RecordingError::Parse(number, line));
+            }
+        }
+        Ok(recording)
+    }
+
+    /// Adds what the line says to the recording, or returns `None` if the line
+    /// is not valid.
+    fn parse_line(&mut self, line: &str) -> Option<()> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            None => (),
+            Some("size") => self.window_size = (parse(&mut words)?, parse(&mut words)?),
+            Some("camera") => {
+                // Recordings made before a setting was added stop short, and
+                // replay as the camera behaved before it. That is the default
+                // for most settings, but the camera used to start and stop
+                // moving immediately.
+                let defaults = CameraConfig {
+                    acceleration: 0.0,
+                    damping: 0.0,
+                    ..Default::default()
+                };
+                self.camera = CameraConfig {
+                    move_speed: parse(&mut words)?,
+                    mouse_degrees_per_pixel: parse(&mut words)?,
+                    turn_speed: parse_or(&mut words, defaults.turn_speed)?,
+                    orbit_speed: parse_or(&mut words, defaults.orbit_speed)?,
+                    fov: parse_or(&mut words, defaults.fov)?,
+                    near_plane: parse_or(&mut words, defaults.near_plane)?,
+                    far_plane: parse_or(&mut words, defaults.far_plane)?,
+                    mouse_smoothing: parse_or(&mut words, defaults.mouse_smoothing)?,
+                    invert_y: parse_or(&mut words, defaults.invert_y)?,
+                    acceleration: parse_or(&mut words, defaults.acceleration)?,
+                    damping: parse_or(&mut words, defaults.damping)?,
+                    sprint_multiplier: parse_or(&mut words, defaults.sprint_multiplier)?,
+                    slow_multiplier: parse_or(&mut words, defaults.slow_multiplier)?,
+                    walk_speed: parse_or(&mut words, defaults.walk_speed)?,
+                    jump_speed: parse_or(&mut words, defaults.jump_speed)?,
+                    gravity: parse_or(&mut words, defaults.gravity)?,
+                    player_radius: parse_or(&mut words, defaults.player_radius)?,
+                    player_height: parse_or(&mut words, defaults.player_height)?,
+                    eye_height: parse_or(&mut words, defaults.eye_height)?,
+                };
+            }
+            Some("update_rate") => {
+                // A step of no time at all would never finish.
+                let rate: f64 = parse(&mut words)?;
+                if rate.is_nan() || rate <= 0.0 {
+                    return None;
+                }
+                self.update_rate = rate;
+            }
+            Some("gizmo") => {
+                self.gizmo = GizmoConfig {
+                    size: parse(&mut words)?,
+                    translate_step: parse(&mut words)?,
+                    rotate_step: parse(&mut words)?,
+                    scale_step: parse(&mut words)?,
+                };
+            }
+            Some("frame") => {
+                // Frames can take no time, but time never runs backwards.
+                let dt_s: f64 = parse(&mut words)?;
+                if dt_s.is_nan() || dt_s < 0.0 {
+                    return None;
+                }
+                self.frames.push(RecordedFrame {
+                    dt_s,
+                    events: Vec::new(),
+                });
+            }
+            Some(kind) => {
+                let event = parse_event(kind, &mut words)?;
+                self.frames.last_mut()?.events.push(event);
+            }
+        }
+        Some(())
+    }
+}
+
+fn parse<T: FromStr>(words: &mut SplitWhitespace) -> Option<T> {
+    words.next().and_then(|word| word.parse().ok())
+}
+
+/// Like `parse`, but gives `default` if there are no more words.
+fn parse_or<T: FromStr>(words: &mut SplitWhitespace, default: T) -> Option<T> {
+    match words.next() {
+        Some(word) => word.parse().ok(),
+        None => Some(default),
+    }
+}
+
+fn parse_event(kind: &str, words: &mut SplitWhitespace) -> Option<InputEvent> {
+    match kind {
+        "action" => {
+            let action = words.next().and_then(Action::from_name)?;
+            match words.next() {
+                Some("press") => Some(InputEvent::Action(action, true)),
+                Some("release") => Some(InputEvent::Action(action, false)),
+                _ => None,
+            }
+        }
+        "look" => Some(InputEvent::Look(parse(words)?, parse(words)?)),
+        "scroll" => Some(InputEvent::Scroll(parse(words)?)),
+        "pick" => Some(InputEvent::Pick(parse(words)?, parse(words)?)),
+        "drag" => Some(InputEvent::Drag(parse(words)?, parse(words)?)),
+        "analogue" => {
+            let action = words.next().and_then(AnalogueAction::from_name)?;
+            Some(InputEvent::Analogue(action, parse(words)?))
+        }
+        "focus" => Some(InputEvent::Focus(parse(words)?)),
+        _ => None,
+    }
+}
+
+/// Writes a recording as the viewer runs. Each frame is written as soon as it
+/// is recorded, so that the recording survives the viewer crashing.
+#[derive(Debug)]
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        window_size: (u32, u32),
+        camera: &CameraConfig,
+        update_rate: f64,
+        gizmo: &GizmoConfig,
+    ) -> Result<Self, RecordingError> {
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "{}", HEADER)?;
+        writeln!(file, "size {} {}", window_size.0, window_size.1)?;
+        writeln!(
+            file,
+            "camera {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {}",
+            camera.move_speed,
+            camera.mouse_degrees_per_pixel,
+            camera.turn_speed,
+            camera.orbit_speed,
+            camera.fov,
+            camera.near_plane,
+            camera.far_plane,
+            camera.mouse_smoothing,
+            camera.invert_y,
+            camera.acceleration,
+            camera.damping,
+            camera.sprint_multiplier,
+            camera.slow_multiplier,
+            camera.walk_speed,
+            camera.jump_speed,
+            camera.gravity,
+            camera.player_radius,
+            camera.player_height,
+            camera.eye_height
+        )?;
+        writeln!(file, "update_rate {}", update_rate)?;
+        writeln!(
+            file,
+            "gizmo {} {} {} {}",
+            gizmo.size,
+            gizmo.translate_step,
+            gizmo.rotate_step,
+            gizmo.scale_step
+        )?;
+        Ok(Recorder { file })
+    }
+
+    pub fn record(&mut self, dt_s: f64, events: &[InputEvent]) -> Result<(), RecordingError> {
+        writeln!(self.file, "frame {}", dt_s)?;
+        for event in events {
+            match *event {
+                InputEvent::Action(action, is_pressed) => {
+                    let state = if is_pressed { "press" } else { "release" };
+                    writeln!(self.file, "action {} {}", action, state)?;
+                }
+                InputEvent::Look(x, y) => writeln!(self.file, "look {} {}", x, y)?,
+                InputEvent::Scroll(lines) => writeln!(self.file, "scroll {}", lines)?,
+                InputEvent::Pick(x, y) => writeln!(self.file, "pick {} {}", x, y)?,
+                InputEvent::Drag(x, y) => writeln!(self.file, "drag {} {}", x, y)?,
+                InputEvent::Analogue(action, value) => {
+                    writeln!(self.file, "analogue {} {}", action, value)?
+                }
+                InputEvent::Focus(gained) => writeln!(self.file, "focus {}", gained)?,
+            }
+        }
+        self.file.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    NotARecording,
+    /// A line which is not valid, with its line number.
+    Parse(usize, String),
+}
+
+impl From<io::Error> for RecordingError {
+    #[inline]
+    fn from(e: io::Error) -> Self {
+        RecordingError::Io(e)
+    }
+}
+
+impl fmt::Display for RecordingError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            RecordingError::Io(ref e) => write!(fmtr, "{}: {}", desc, e),
+            RecordingError::Parse(number, ref line) => {
+                write!(fmtr, "{} on line {}: {}", desc, number, line)
+            }
+            RecordingError::NotARecording => write!(fmtr, "{}", desc),
+        }
+    }
+}
+
+impl Error for RecordingError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            RecordingError::Io(_) => "An io error occurred while accessing the recording",
+            RecordingError::NotARecording => "The file is not an input recording",
+            RecordingError::Parse(..) => "The recording contains a line which is not valid",
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            RecordingError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    /// A file in the temporary directory which no other test run is using.
+    fn temp_file(name: &str) -> PathBuf {
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        env::temp_dir().join(format!("{}_{}.txt", name, time.subsec_nanos()))
+    }
+
+    fn load_text(name: &str, text: &str) -> Result<Recording, RecordingError> {
+        let file = temp_file(name);
+        File::create(&file).unwrap().write_all(text.as_bytes()).unwrap();
+        let recording = Recording::load(&file);
+        fs::remove_file(&file).unwrap();
+        recording
+    }
+
+    fn is_parse_error(result: Result<Recording, RecordingError>, line_number: usize) -> bool {
+        match result {
+            Err(RecordingError::Parse(number, _)) => number == line_number,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn recorded_input_loads_unchanged() {
+        let camera = CameraConfig {
+            invert_y: true,
+            mouse_smoothing: 0.3,
+            ..Default::default()
+        };
+        let gizmo = GizmoConfig {
+            rotate_step: 5.0,
+            ..Default::default()
+        };
+        let frames = vec![
+            RecordedFrame {
+                dt_s: 0.0,
+                events: vec![
+                    InputEvent::Action(Action::MoveForwards, true),
+                    InputEvent::Look(3.0, -1.5),
+                    InputEvent::Scroll(-1.0),
+                    InputEvent::Analogue(AnalogueAction::TurnRight, 0.25),
+                ],
+            },
+            RecordedFrame {
+                dt_s: 1.0 / 60.0,
+                events: Vec::new(),
+            },
+            RecordedFrame {
+                dt_s: 0.1,
+                events: vec![
+                    InputEvent::Pick(0.25, -0.5),
+                    InputEvent::Drag(0.3, -0.1),
+                    InputEvent::Action(Action::MoveForwards, false),
+                    InputEvent::Focus(false),
+                ],
+            },
+        ];
+
+        let file = temp_file("recorded_input_loads_unchanged");
+        {
+            let mut recorder = Recorder::create(&file, (1280, 720), &camera, 90.0, &gizmo).unwrap();
+            for frame in &frames {
+                recorder.record(frame.dt_s, &frame.events).unwrap();
+            }
+        }
+        let loaded = Recording::load(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+        assert_eq!(
+            loaded,
+            Recording {
+                window_size: (1280, 720),
+                camera,
+                update_rate: 90.0,
+                gizmo,
+                frames,
+            }
+        );
+    }
+
+    #[test]
+    fn short_camera_line_moves_as_it_used_to() {
+        let text = "gfx_tutorial recording 1\nsize 800 600\ncamera 5 0.1\nframe 0.02\n";
+        let recording = load_text("short_camera_line", text).unwrap();
+        let defaults = CameraConfig::default();
+        assert_eq!(
+            recording.camera,
+            CameraConfig {
+                move_speed: 5.0,
+                mouse_degrees_per_pixel: 0.1,
+                acceleration: 0.0,
+                damping: 0.0,
+                ..defaults
+            }
+        );
+        assert_eq!(recording.gizmo, GizmoConfig::default());
+        assert_eq!(recording.update_rate, GraphicsConfig::default().update_rate);
+        assert_eq!(recording.frames.len(), 1);
+    }
+
+    #[test]
+    fn events_must_follow_a_frame() {
+        let text = "gfx_tutorial recording 1\nsize 800 600\nscroll 1\nframe 0.02\n";
+        assert!(is_parse_error(load_text("event_before_frame", text), 3));
+    }
+
+    #[test]
+    fn time_must_pass_forwards() {
+        let header = "gfx_tutorial recording 1\nsize 800 600\n";
+        for &line in &["update_rate 0", "update_rate NaN", "frame -0.01", "frame NaN"] {
+            let text = format!("{}{}\n", header, line);
+            assert!(is_parse_error(load_text("time_must_pass", &text), 3), "{}", line);
+        }
+        let text = format!("{}frame 0\n", header);
+        assert!(load_text("zero_frame", &text).is_ok());
+    }
+}
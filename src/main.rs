@@ -1,7 +1,9 @@
 #![warn(missing_debug_implementations)]
 
 extern crate alga;
+extern crate app_dirs;
 extern crate apply;
+extern crate clap;
 extern crate ang;
 extern crate find_folder;
 #[macro_use]
@@ -12,13 +14,18 @@ extern crate image;
 extern crate lazy_static;
 extern crate num;
 extern crate nalgebra as na;
+extern crate serde;
 #[macro_use]
-extern crate scopeguard;
+extern crate serde_derive;
 extern crate time;
+extern crate toml;
 extern crate void;
 extern crate wavefront_obj;
 extern crate winit;
 
+#[cfg(feature = "gamepad")]
+extern crate gilrs;
+
 #[cfg(feature = "gl")]
 extern crate gfx_device_gl;
 #[cfg(feature = "gl")]
@@ -36,28 +43,61 @@ extern crate gfx_window_dxgi;
 #[cfg(all(target_os = "windows", feature = "dx11"))]
 extern crate gfx_device_dx11;
 
+mod config;
 mod controllers;
 mod lazy_load;
 mod graphics;
+mod input;
+mod options;
+mod physics;
+mod timing;
 mod util;
 
 use ang::Degrees;
 use apply::Apply;
-use controllers::camera_controller::CameraController;
+use config::{BackgroundImage, Config, ConfigFile};
+use controllers::camera_path::CameraPath;
+use controllers::camera_rig::{CameraRig, ViewPreset};
+use controllers::gizmo::Gizmo;
 use gfx::{CommandBuffer, Device, Encoder, Resources, UpdateError};
+use gfx::handle::{DepthStencilView, RenderTargetView};
+use gfx::texture::Size;
 use gfx_glyph::{FontId, GlyphBrushBuilder, Layout, BuiltInLineBreaker, Scale, Section};
-use graphics::camera::{Camera, CameraMatrices};
+use graphics::bounds::Aabb;
+use graphics::camera::Camera;
+use graphics::capture::{self, FrameCapture};
+use graphics::environment::{load_cube_faces, EnvironmentLocals, EnvironmentMap,
+                            EnvironmentResources};
 use graphics::fps_counter::FpsCounter;
+use graphics::frame::FrameResources;
+use graphics::lines::Lines;
+use graphics::material::Material;
 use graphics::model::Model;
-use graphics::platform::{self, ContextBuilder, FactoryExt as PlFactoryExt, WindowExt as PlatformWindow};
-use na::{Point3, UnitQuaternion};
+use graphics::shadow::{ShadowMaps, ShadowTransform};
+use graphics::skybox::{Background, Skybox};
+use graphics::target::{Blit, OffscreenTarget, Resolve};
+use graphics::transform::Transform;
+use input::action::Action;
+use input::bindings::{ActionMap, Input};
+use input::recording::{InputEvent, RecordedFrame, Recorder, Recording};
+use graphics::platform::software::{SoftwareModel, SoftwareScene};
+use graphics::platform::{self, Backend, ContextBuilder, FactoryExt as PlFactoryExt, NativeWindow,
+                         WindowExt as PlatformWindow};
+use na::{Point3, UnitQuaternion, Vector3};
 use num::{cast, NumCast, Zero};
+use options::{HeadlessOptions, Options, OptionsError, SceneKind};
+use physics::collision::CollisionMesh;
+use physics::ray::Ray;
 use std::borrow::Borrow;
+use std::env;
+use std::error::Error;
 use std::fs::File;
 use std::io::Read;
 use std::ops::Div;
-use std::time::Duration as StdDuration;
+use std::path::Path;
+use std::process;
 use time::{Duration, PreciseTime};
+use timing::{FixedTimestep, FrameLimiter};
 
 gfx_defines! {
     #[derive(Default)]
@@ -72,26 +112,51 @@ gfx_defines! {
         col: [f32; 4] = "color",
         pos: [f32; 3] = "position",
         power: f32 = "power",
+        dir: [f32; 3] = "direction",
+        kind: u32 = "kind",
+        shadow_index: i32 = "shadow_index",
+        padding: [i32; 3] = "_padding",
     }
 
     #[derive(Default)]
-    constant VertLocals {
-        projection: [[f32; 4]; 4] = "projection_matrix",
+    constant ModelLocals {
         model: [[f32; 4]; 4] = "model_matrix",
-        view: [[f32; 4]; 4] = "view_matrix",
+        normal: [[f32; 4]; 4] = "normal_matrix",
+    }
+
+    #[derive(Default)]
+    constant MaterialLocals {
+        base_color: [f32; 4] = "base_color_factor",
+        emissive: [f32; 3] = "emissive_factor",
+        metallic: f32 = "metallic_factor",
+        roughness: f32 = "roughness_factor",
+        occlusion_strength: f32 = "occlusion_strength",
     }
 
     #[derive(Default)]
-    constant SharedLocals {
+    constant FrameLocals {
+        projection: [[f32; 4]; 4] = "projection_matrix",
+        view: [[f32; 4]; 4] = "view_matrix",
+        camera_position: [f32; 4] = "camera_position",
+        cascade_splits: [f32; 4] = "cascade_splits",
+        time: f32 = "time",
         num_lights: u32 = "num_lights",
     }
 
     pipeline pipe {
         vbuf: gfx::VertexBuffer<Vertex> = (),
-        vert_locals: gfx::ConstantBuffer<VertLocals> = "vert_locals",
-        shared_locals: gfx::ConstantBuffer<SharedLocals> = "shared_locals",
+        model_locals: gfx::ConstantBuffer<ModelLocals> = "model_locals",
+        frame_locals: gfx::ConstantBuffer<FrameLocals> = "frame_locals",
+        material_locals: gfx::ConstantBuffer<MaterialLocals> = "material_locals",
         main_texture: gfx::TextureSampler<[f32; 4]> = "color_texture",
+        metallic_roughness_texture: gfx::TextureSampler<[f32; 4]> = "metallic_roughness_texture",
+        occlusion_texture: gfx::TextureSampler<[f32; 4]> = "occlusion_texture",
+        emissive_texture: gfx::TextureSampler<[f32; 4]> = "emissive_texture",
         lights: gfx::ConstantBuffer<ShaderLight> = "lights_array",
+        shadow_transforms: gfx::ConstantBuffer<ShadowTransform> = "shadow_transforms_array",
+        shadow_map: gfx::TextureSampler<f32> = "shadow_map",
+        environment_locals: gfx::ConstantBuffer<EnvironmentLocals> = "environment_locals",
+        specular_map: gfx::TextureSampler<[f32; 4]> = "specular_map",
         out: gfx::RenderTarget<ColorFormat> = "Target0",
         main_depth: gfx::DepthTarget<DepthFormat> =
             gfx::preset::depth::LESS_EQUAL_WRITE,
@@ -107,25 +172,81 @@ const GLSL_FRAG_SRC: &'static [u8] = include_bytes!("../data/shader/glsl/standar
 const MSL_VERT_SRC: &'static [u8] = include_bytes!("../data/shader/msl/standard.vs");
 const MSL_FRAG_SRC: &'static [u8] = include_bytes!("../data/shader/msl/standard.fs");
 
-const CLEAR_COLOR: [f32; 4] = [0.005, 0.005, 0.1, 1.0];
+/// Where screenshots and captured sequences are written, relative to the
+/// working directory.
+const CAPTURE_DIRECTORY: &str = "screenshots";
 
-const SPEED: f32 = 7.0;
-const MOUSE_SPEED: f32 = 4.0;
+/// The sky which lights the scene when the config names no background.
+const ENVIRONMENT_MAP: &str = "img/environment.hdr";
+const DEFAULT_TEXTURE: &str = "img/checker.png";
+
+/// The distance between the centres of models given on the command line.
+const MODEL_SPACING: f32 = 3.0;
+const AMBIENT_COLOR: [f32; 3] = [0.03, 0.03, 0.03];
+
+#[cfg(all(target_os = "macos", feature = "metal"))]
+const DEFAULT_BACKEND: Backend = Backend::Metal;
+#[cfg(not(all(target_os = "macos", feature = "metal")))]
+const DEFAULT_BACKEND: Backend = Backend::Gl;
+
+/// How many pixels of smooth scrolling, as touchpads report it, count as one
+/// line of a mouse wheel.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// How many degrees the field of view changes each time it is widened or
+/// narrowed.
+const FOV_STEP: f32 = 5.0;
+/// How much each press moves a clip plane, as a factor of its distance, since
+/// useful distances range from millimetres to kilometres.
+const CLIP_PLANE_STEP: f32 = 1.25;
+
+/// The time between frames in headless mode, which does not use the clock.
+const HEADLESS_FRAME_TIME: f64 = 1.0 / 60.0;
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum LightKind {
+    Point,
+    Directional,
+}
 
 #[derive(Clone, Debug, PartialEq)]
 pub struct Light {
+    kind: LightKind,
     position: Point3<f32>,
+    direction: Vector3<f32>,
     color: [f32; 4],
     power: f32,
+    casts_shadows: bool,
 }
 
 impl Light {
     #[inline]
     fn new(position: Point3<f32>, color: [f32; 4], power: f32) -> Self {
         Light {
+            kind: LightKind::Point,
             position,
+            direction: -Vector3::y(),
             color,
             power,
+            casts_shadows: false,
+        }
+    }
+
+    #[inline]
+    fn directional(direction: Vector3<f32>, color: [f32; 4], power: f32) -> Self {
+        Light {
+            kind: LightKind::Directional,
+            direction: direction.normalize(),
+            ..Light::new(na::origin(), color, power)
+        }
+    }
+
+    #[inline]
+    fn with_shadows_enabled(self, casts_shadows: bool) -> Self {
+        Light {
+            casts_shadows,
+            ..self
         }
     }
 }
@@ -142,44 +263,172 @@ impl<L: Borrow<Light>> From<L> for ShaderLight {
     fn from(l: L) -> Self {
         let l = l.borrow();
         let na::coordinates::XYZ { x, y, z } = *l.position;
+        let kind = match l.kind {
+            LightKind::Point => 0,
+            LightKind::Directional => 1,
+        };
         ShaderLight {
             pos: [x, y, z],
             col: l.color,
             power: l.power,
+            dir: [l.direction.x, l.direction.y, l.direction.z],
+            kind,
+            shadow_index: -1,
+            padding: [0; 3],
         }
     }
 }
 
 const MAX_LIGHTS: usize = 10;
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct Scene<R: Resources> {
-    lights: Vec<ShaderLight>,
+    frame: FrameResources<R>,
+    shadows: ShadowMaps<R>,
+    skybox: Skybox<R>,
+    lines: Lines<R>,
+    lights: Vec<Light>,
     models: Vec<Model<R>>,
+    /// Where each model was before the last step, to draw from.
+    previous_transforms: Vec<Transform>,
+    /// The triangles of every model where it is now, to walk on.
+    collision: CollisionMesh,
+    selected: Option<Pick>,
+    /// The handles for moving the selected model around.
+    gizmo: Gizmo,
+}
+
+/// Where a ray hit one of the models of a scene.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Pick {
+    /// The index of the model in the scene.
+    model: usize,
+    /// The index of the triangle in the model's mesh.
+    triangle: usize,
+    position: Point3<f32>,
 }
 
 impl<R: Resources> Scene<R> {
-    fn new<L: Into<ShaderLight>>(lights: Vec<L>, models: Vec<Model<R>>) -> Self {
-        let lights = lights.into_iter().map(Into::into).collect();
-        Scene { lights, models }
+    fn new(
+        frame: FrameResources<R>,
+        shadows: ShadowMaps<R>,
+        skybox: Skybox<R>,
+        lines: Lines<R>,
+        gizmo: Gizmo,
+        lights: Vec<Light>,
+        models: Vec<Model<R>>,
+    ) -> Self {
+        let previous_transforms = models.iter().map(|model| model.transform).collect();
+        let collision = collision_mesh(&models);
+        Scene {
+            frame,
+            shadows,
+            skybox,
+            lines,
+            lights,
+            models,
+            previous_transforms,
+            collision,
+            selected: None,
+            gizmo,
+        }
+    }
+
+    /// The nearest place on a model which the ray hits, if it hits one.
+    fn pick(&self, ray: &Ray) -> Option<Pick> {
+        self.models
+            .iter()
+            .enumerate()
+            .filter_map(|(index, model)| model.cast_ray(ray).map(|hit| (index, hit)))
+            .fold(None, |nearest: Option<(usize, _)>, (index, hit)| match nearest {
+                Some((_, nearest_hit)) if nearest_hit.distance <= hit.distance => nearest,
+                _ => Some((index, hit)),
+            })
+            .map(|(model, hit)| Pick {
+                model,
+                triangle: hit.triangle,
+                position: ray.at(hit.distance),
+            })
     }
 
-    fn update_views<W: PlatformWindow<R>>(&mut self, window: &W) {
+    /// The box around all of the models, or `None` if there are none.
+    fn bounds(&self) -> Option<Aabb> {
+        let mut bounds = self.models.iter().map(Model::bounds);
+        let first = bounds.next()?;
+        Some(bounds.fold(first, |all, model| all.union(&model)))
+    }
+
+    #[inline]
+    fn clear_color(&self) -> [f32; 4] {
+        self.skybox.background().clear_color()
+    }
+
+    fn set_targets(
+        &mut self,
+        rtv: &RenderTargetView<R, ColorFormat>,
+        dsv: &DepthStencilView<R, DepthFormat>,
+    ) {
         for model in &mut self.models {
-            model.update_views(window)
+            model.set_targets(rtv.clone(), dsv.clone());
         }
+        self.skybox.set_targets(rtv.clone(), dsv.clone());
+        self.lines.set_target(rtv.clone());
     }
 
+    /// Advances the scene's animation by one step of `dt_s` seconds. The model
+    /// being dragged by the gizmo is held still, so that it stays under the
+    /// handle.
+    fn update(&mut self, dt_s: f32) {
+        let rot = model_rotation(dt_s);
+        let held = match self.selected {
+            Some(pick) if self.gizmo.is_dragging() => Some(pick.model),
+            _ => None,
+        };
+        let models = self.models.iter_mut().zip(&mut self.previous_transforms).enumerate();
+        for (index, (model, previous)) in models {
+            *previous = model.transform;
+            if held != Some(index) {
+                model.transform.append_rotation(&rot);
+            }
+        }
+        self.collision = collision_mesh(&self.models);
+    }
+
+    /// Draws the scene `alpha` of the way from the step before the last one to
+    /// the last one.
     fn render<CBuf: CommandBuffer<R>, Cam: Camera>(
-        &self,
+        &mut self,
         encoder: &mut Encoder<R, CBuf>,
         camera: &Cam,
+        time: f32,
+        alpha: f32,
     ) -> Result<(), UpdateError<usize>> {
-        let CameraMatrices { view, projection } = camera.matrices();
-        for model in &self.models {
-            model.update_matrices(encoder, &view, &projection);
-            model.update_lights(encoder, &self.lights)?;
-            model.encode(encoder);
+        let matrices = camera.matrices();
+        let (lights, cascade_splits) = self.shadows.prepare(&self.lights, &matrices);
+        self.frame.update(encoder, &matrices, cascade_splits, time, &lights)?;
+
+        let transforms = self.models
+            .iter()
+            .zip(&self.previous_transforms)
+            .map(|(model, previous)| model.transform.interpolated(previous, alpha))
+            .collect::<Vec<_>>();
+        for (index, (model, transform)) in self.models.iter().zip(&transforms).enumerate() {
+            let is_selected = self.selected.map_or(false, |pick| pick.model == index);
+            model.update_locals(encoder, transform, is_selected);
+        }
+
+        self.shadows.encode(encoder, &self.frame, &self.models, &transforms)?;
+
+        for (model, transform) in self.models.iter().zip(&transforms) {
+            model.encode(encoder, transform);
+        }
+
+        self.skybox.encode(encoder, &matrices);
+
+        // The gizmo goes over everything, so that it can always be grabbed.
+        if let Some(pick) = self.selected {
+            let lines = self.gizmo.lines(&transforms[pick.model], &matrices);
+            self.lines.encode(encoder, &matrices, &lines)?;
         }
         Ok(())
     }
@@ -236,28 +485,542 @@ impl Styling {
     }
 }
 
+/// Loads the models, lights and background which make up the demo scene.
+fn load_scene<R: Resources, F: PlFactoryExt<R>>(
+    factory: &mut F,
+    backend: &Backend,
+    options: &Options,
+    config: &Config,
+    rtv: &RenderTargetView<R, ColorFormat>,
+    dsv: &DepthStencilView<R, DepthFormat>,
+) -> Scene<R> {
+    let shadows = ShadowMaps::new(factory, backend)
+        .expect("Could not create shadow maps");
+    let (map, background) = load_environment(config);
+    let environment = EnvironmentResources::new(factory, &map, 1.0)
+        .expect("Could not create environment map");
+    let frame = FrameResources::new(factory, &shadows, &environment);
+    let skybox = Skybox::new(
+        factory,
+        backend,
+        rtv.clone(),
+        dsv.clone(),
+        background,
+        1.0,
+    ).expect("Could not create skybox");
+    let lines = Lines::new(factory, backend, rtv.clone()).expect("Could not create lines");
+
+    let models = scene_models(options)
+        .into_iter()
+        .map(|(name, material, transform)| {
+            let mut model = Model::load(
+                factory,
+                backend,
+                &frame,
+                rtv.clone(),
+                dsv.clone(),
+                &name,
+                material,
+                config.graphics.cache_bvh,
+            ).unwrap_or_else(|e| exit_with_load_error(&name, &e));
+            model.transform = transform;
+            model
+        })
+        .collect();
+
+    let gizmo = Gizmo::new(&config.gizmo);
+    Scene::new(frame, shadows, skybox, lines, gizmo, scene_lights(), models)
+}
+
+/// Loads the image based lighting, falling back to a uniform ambient light
+/// under a gradient sky when the map is missing. The config can replace the
+/// sky with a flat colour either way.
+fn load_environment(config: &Config) -> (EnvironmentMap, Background) {
+    let default_image = BackgroundImage::Equirectangular(ENVIRONMENT_MAP.to_owned());
+    let image = config.graphics.background.as_ref().unwrap_or(&default_image);
+    let loaded = match *image {
+        BackgroundImage::Equirectangular(ref name) => EnvironmentMap::load(name)
+            .map(|map| (map, Background::Equirectangular(name.clone()))),
+        BackgroundImage::CubeMap(ref faces) => load_cube_faces(faces).map(|images| {
+            (EnvironmentMap::from_cube_faces(&images), Background::CubeMap(faces.clone()))
+        }),
+    };
+    let (map, background) = match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            let name = match *image {
+                BackgroundImage::Equirectangular(ref name) => name.clone(),
+                BackgroundImage::CubeMap(ref faces) => faces.join(", "),
+            };
+            eprintln!("Using uniform ambient light, could not load {}: {}", name, e);
+            let background = Background::Gradient {
+                zenith: [0.05, 0.15, 0.45],
+                horizon: [0.45, 0.5, 0.6],
+                ground: [0.05, 0.05, 0.05],
+            };
+            (EnvironmentMap::uniform(AMBIENT_COLOR), background)
+        }
+    };
+    match config.graphics.background_color {
+        Some(color) => (map, Background::Color(color)),
+        None => (map, background),
+    }
+}
+
+/// The models in the scene, with the position each one is placed at. Models
+/// given on the command line are lined up along the x axis, and replace the
+/// built in scene.
+fn scene_models(options: &Options) -> Vec<(String, Material, Transform)> {
+    let texture = options.texture.as_ref().map_or(DEFAULT_TEXTURE, String::as_str);
+
+    if options.models.is_empty() && options.scene == SceneKind::Materials {
+        let suzanne = |x: f32, material: Material| {
+            let transform = Transform::from_translation(Vector3::new(x, 0.0, 0.0));
+            ("suzanne".to_owned(), material, transform)
+        };
+        return vec![
+            suzanne(
+                -MODEL_SPACING,
+                Material {
+                    base_color: [0.8, 0.1, 0.1, 1.0],
+                    roughness: 0.2,
+                    ..Default::default()
+                },
+            ),
+            suzanne(
+                0.0,
+                Material {
+                    base_color: [0.1, 0.6, 0.2, 1.0],
+                    roughness: 0.8,
+                    ..Default::default()
+                },
+            ),
+            suzanne(
+                MODEL_SPACING,
+                Material {
+                    base_color: [1.0, 0.8, 0.4, 1.0],
+                    metallic: 1.0,
+                    roughness: 0.3,
+                    ..Default::default()
+                },
+            ),
+            (
+                "floor".to_owned(),
+                Material {
+                    base_color: [0.6, 0.6, 0.6, 1.0],
+                    roughness: 0.9,
+                    ..Default::default()
+                },
+                Transform::from_translation(Vector3::new(0.0, -1.5, 0.0)),
+            ),
+        ];
+    }
+
+    if options.models.is_empty() {
+        return vec![
+            (
+                "suzanne".to_owned(),
+                Material {
+                    metallic: 0.8,
+                    roughness: 0.3,
+                    ..Material::with_base_color_texture(texture)
+                },
+                Transform::from_translation(Vector3::new(0.0, 2.0, 0.0)),
+            ),
+            (
+                "cube".to_owned(),
+                Material::with_base_color_texture(texture),
+                Transform::from_translation(Vector3::new(0.0, -2.0, 0.0)),
+            ),
+            // Something to stand on while walking.
+            (
+                "floor".to_owned(),
+                Material::with_base_color_texture(texture),
+                Transform::from_translation(Vector3::new(0.0, -6.0, 0.0)),
+            ),
+        ];
+    }
+
+    let first_x = -MODEL_SPACING * (options.models.len() - 1) as f32 / 2.0;
+    options
+        .models
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let x = first_x + MODEL_SPACING * i as f32;
+            let transform = Transform::from_translation(Vector3::new(x, 0.0, 0.0));
+            (name.clone(), Material::with_base_color_texture(texture), transform)
+        })
+        .collect()
+}
+
+fn scene_lights() -> Vec<Light> {
+    let l1 = Light::new(Point3::new(0.0, 3.0, -2.0), [1.0, 0.0, 0.0, 1.0], 300.0)
+        .with_shadows_enabled(true);
+    let l2 = Light::new(Point3::new(0.0, 1.6, 0.0), [1.0, 0.0, 0.0, 1.0], 400.0);
+    let l3 = Light::new(Point3::new(1.5, -3.0, 0.0), [1.0, 0.0, 1.0, 0.3], 300.0);
+    let l4 = Light::new(Point3::new(0.0, -1.8, 0.0), [1.0, 0.0, 1.0, 1.0], 400.0);
+    let sun = Light::directional(Vector3::new(-0.3, -1.0, 0.4), [1.0, 0.95, 0.8, 1.0], 2.0)
+        .with_shadows_enabled(true);
+
+    vec![l1, l2, l3, l4, sun]
+}
+
+/// How far the models spin in `dt_s` seconds.
+fn model_rotation(dt_s: f32) -> UnitQuaternion<f32> {
+    UnitQuaternion::from_euler_angles(0.0, Degrees(25.0 * dt_s).in_radians(), 0.0)
+}
+
+/// The triangles of every model, where they are placed.
+fn collision_mesh<R: Resources>(models: &[Model<R>]) -> CollisionMesh {
+    CollisionMesh::new(models.iter().map(|model| (model.bvh(), model.transform)))
+}
+
 fn main() {
-    let mut events_loop = winit::EventsLoop::new();
+    let mut config_file = ConfigFile::load();
+    let options = Options::parse(env::args_os(), &config_file.config).unwrap_or_else(|e| match e {
+        OptionsError::Clap(e) => e.exit(),
+        e => {
+            eprintln!("{}", e);
+            process::exit(2);
+        }
+    });
+
+    let recording = options.replay.as_ref().map(|path| {
+        Recording::load(path).unwrap_or_else(|e| {
+            eprintln!("Could not load {}: {}", path.display(), e);
+            process::exit(1);
+        })
+    });
+
+    match options.headless {
+        Some(ref headless) if options.backend == Some(Backend::Software) => {
+            run_software(&options, headless, &Config::default())
+        }
+        #[cfg(feature = "gl")]
+        Some(ref headless) => match recording {
+            Some(ref recording) => {
+                let mut config = Config::default();
+                config.camera = recording.camera.clone();
+                config.graphics.update_rate = recording.update_rate;
+                config.gizmo = recording.gizmo.clone();
+                let (width, height) = options.window.size.unwrap_or(recording.window_size);
+                let headless = HeadlessOptions {
+                    width,
+                    height,
+                    ..headless.clone()
+                };
+                run_headless(&options, &headless, &config, Some(recording))
+            }
+            None => run_headless(&options, headless, &Config::default(), None),
+        },
+        // Options only allow other backends headless when gl was compiled in.
+        #[cfg(not(feature = "gl"))]
+        Some(_) => unreachable!(),
+        None => {
+            let invalid_bindings = config_file.config.key_bindings.invalid_bindings();
+            if !invalid_bindings.is_empty() {
+                eprintln!(
+                    "Ignoring bindings in the config which are not valid: {}",
+                    invalid_bindings.join(", ")
+                );
+            }
+
+            run_windowed(&options, &mut config_file.config, recording.as_ref());
+
+            match config_file.save_if_changed() {
+                Ok(true) => {
+                    if let Some(path) = config_file.path() {
+                        eprintln!("Saved settings to {}", path.display());
+                    }
+                }
+                Ok(false) => (),
+                Err(e) => eprintln!("Could not save settings: {}", e),
+            }
+        }
+    }
+}
+
+/// Reports a model, or one of its textures, which could not be loaded, and
+/// exits, since a scene with a model missing is not worth showing.
+fn exit_with_load_error<E: Error>(name: &str, e: &E) -> ! {
+    eprintln!("Could not load {}: {}", name, e);
+    process::exit(1);
+}
+
+/// Reports a screenshot or captured frame which could not be written when
+/// running headless, where the images are the whole point of running.
+fn exit_with_write_error<E: Error>(path: &Path, e: &E) -> ! {
+    eprintln!("Could not write {}: {}", path.display(), e);
+    process::exit(1);
+}
+
+/// Loads a camera path, or exits if it cannot be read.
+fn load_camera_path(path: &Path) -> CameraPath {
+    CameraPath::load(path).unwrap_or_else(|e| {
+        eprintln!("Could not load {}: {}", path.display(), e);
+        process::exit(1);
+    })
+}
+
+/// Renders a fixed number of frames offscreen and exits, without opening a
+/// window or grabbing the cursor. When replaying, every frame of the recording
+/// is rendered, with the time and input it was recorded with. Given a camera
+/// path, the camera flies along it from the first frame.
+#[cfg(feature = "gl")]
+fn run_headless(
+    options: &Options,
+    headless: &HeadlessOptions,
+    config: &Config,
+    recording: Option<&Recording>,
+) {
+    let (backend, window, mut device, mut factory) =
+        platform::launch_gl_headless(headless.width, headless.height).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            process::exit(1);
+        });
+
+    let mut encoder = factory.create_encoder();
+    let target = OffscreenTarget::new(
+        &mut factory,
+        headless.width as Size,
+        headless.height as Size,
+        options.samples,
+    ).expect("Could not create offscreen target");
+    let resolve = Resolve::new(&mut factory, &backend, &target).expect("Could not create resolve");
+
+    let mut scene =
+        load_scene(&mut factory, &backend, options, config, target.rtv(), target.dsv());
+    let mut cam_controller = CameraRig::new(&window, &config.camera);
+    if let Some(ref path) = options.camera_path {
+        cam_controller = cam_controller.with_camera_path(load_camera_path(path));
+        cam_controller.toggle_path_playback();
+    }
+    let mut previous_camera = cam_controller.clone();
+
+    let mut sequence = headless.capture_directory.as_ref().map(|dir| {
+        let mut capture = FrameCapture::new(dir.clone());
+        capture.start_sequence();
+        capture
+    });
+
+    let frames = match recording {
+        Some(recording) => recording.frames.clone(),
+        None => (0..headless.frames)
+            .map(|frame| RecordedFrame {
+                dt_s: if frame == 0 { 0.0 } else { HEADLESS_FRAME_TIME },
+                events: Vec::new(),
+            })
+            .collect(),
+    };
+
+    let mut timestep = FixedTimestep::new(config.graphics.update_rate);
+    let mut is_paused = false;
+    for frame in &frames {
+        // Screenshots and the fps counter are left to the command line.
+        let actions = apply_input(&frame.events, &mut scene, &mut cam_controller, &mut is_paused);
+        if actions.contains(&Action::Quit) {
+            break;
+        }
+        if is_paused {
+            timestep.discard_remainder();
+            continue;
+        }
+
+        cam_controller.smooth_look(frame.dt_s as f32);
+        for _ in 0..timestep.advance(frame.dt_s) {
+            previous_camera = cam_controller.clone();
+            cam_controller.apply_input(timestep.step(), &scene.collision);
+            scene.update(timestep.step());
+        }
+        let alpha = timestep.alpha();
+        let camera = cam_controller.interpolated(&previous_camera, alpha);
+
+        encoder.clear(target.rtv(), scene.clear_color());
+        encoder.clear_depth(target.dsv(), 1.0);
+
+        scene
+            .render(&mut encoder, &camera, timestep.time(), alpha)
+            .expect("Could not render scene");
+        resolve.encode(&mut encoder);
+
+        if let Some(ref mut sequence) = sequence {
+            let texture = target.color_texture();
+            let captured =
+                sequence.capture(&mut factory, &mut encoder, &mut device, &backend, texture);
+            if let Err(e) = captured {
+                exit_with_write_error(sequence.directory(), &e);
+            }
+        }
+
+        encoder.flush(&mut device);
+        device.cleanup();
+    }
+
+    if let Some(ref path) = headless.screenshot {
+        let texture = target.color_texture();
+        let image = capture::read_pixels(&mut factory, &mut encoder, &mut device, &backend, texture)
+            .expect("Could not read back the last frame");
+        if let Err(e) = image.save(path) {
+            exit_with_write_error(path, &e);
+        }
+    }
+}
+
+/// Renders like `run_headless`, but on the cpu. Shadows are not drawn.
+fn run_software(options: &Options, headless: &HeadlessOptions, config: &Config) {
+    let (_backend, mut window) = platform::launch_software(headless.width, headless.height);
+    let mut cam_controller = CameraRig::new(&window, &config.camera);
+    if let Some(ref path) = options.camera_path {
+        cam_controller = cam_controller.with_camera_path(load_camera_path(path));
+        cam_controller.toggle_path_playback();
+    }
+
+    let (map, background) = load_environment(config);
+    let models = scene_models(options)
+        .into_iter()
+        .map(|(name, material, transform)| {
+            let mut model = SoftwareModel::load(&name, material)
+                .unwrap_or_else(|e| exit_with_load_error(&name, &e));
+            model.transform = transform;
+            model
+        })
+        .collect();
+    let mut scene = SoftwareScene::new(models, scene_lights(), background, &map, 1.0)
+        .expect("Could not load background");
+
+    let mut sequence = headless.capture_directory.as_ref().map(|dir| {
+        let mut capture = FrameCapture::new(dir.clone());
+        capture.start_sequence();
+        capture
+    });
+
+    for frame in 0..headless.frames {
+        if frame > 0 {
+            let rot = model_rotation(HEADLESS_FRAME_TIME as f32);
+            for model in &mut scene.models {
+                model.transform.append_rotation(&rot);
+            }
+            // Software models are not collided with.
+            cam_controller.apply_input(HEADLESS_FRAME_TIME as f32, &CollisionMesh::default());
+        }
+
+        scene.render(&mut window, &cam_controller.matrices());
+
+        if let Some(ref mut sequence) = sequence {
+            if let Err(e) = sequence.save(&window.to_image()) {
+                exit_with_write_error(sequence.directory(), &e);
+            }
+        }
+    }
+
+    if let Some(ref path) = headless.screenshot {
+        if let Err(e) = window.to_image().save(path) {
+            exit_with_write_error(path, &e);
+        }
+    }
+}
+
+/// Opens the viewer, which may change the config while it runs.
+fn run_windowed(options: &Options, config: &mut Config, recording: Option<&Recording>) {
+    let events_loop = winit::EventsLoop::new();
     let builder = {
         let primary_monitor = events_loop.get_primary_monitor();
-        let (win_w, win_h) = primary_monitor.get_dimensions();
-        winit::WindowBuilder::new()
+        let (win_w, win_h) = options
+            .window
+            .size
+            .unwrap_or_else(|| primary_monitor.get_dimensions());
+        let builder = winit::WindowBuilder::new()
             .with_dimensions(win_w, win_h)
-            // .with_fullscreen(primary_monitor)
-            .with_title("Gfx Example")
+            .with_title("Gfx Example");
+        if options.window.is_fullscreen {
+            builder.with_fullscreen(Some(primary_monitor))
+        } else {
+            builder
+        }
     };
+    let context = ContextBuilder::new().with_vsync_enabled(options.window.is_vsync_enabled);
 
-    let (backend, window, mut device, mut factory, main_color, main_depth) =
-        platform::launch_gl::<ColorFormat, gfx::format::DepthStencil>(
-            builder,
-            &events_loop,
-            ContextBuilder::new().with_vsync_enabled(true),
-        ).expect("Could not create window or graphics device");
+    match options.backend.unwrap_or(DEFAULT_BACKEND) {
+        #[cfg(feature = "gl")]
+        Backend::Gl => {
+            let (backend, window, device, factory, main_color, main_depth) =
+                platform::launch_gl::<ColorFormat, DepthFormat>(builder, &events_loop, context)
+                    .expect("Could not create window or graphics device");
+            run_window(
+                options,
+                config,
+                recording,
+                events_loop,
+                backend,
+                window,
+                device,
+                factory,
+                main_color,
+                main_depth,
+            )
+        }
+        #[cfg(all(target_os = "macos", feature = "metal"))]
+        Backend::Metal => {
+            let (backend, window, device, factory, main_color, main_depth) =
+                platform::launch_metal::<ColorFormat, DepthFormat>(builder, &events_loop, context)
+                    .expect("Could not create window or graphics device");
+            run_window(
+                options,
+                config,
+                recording,
+                events_loop,
+                backend,
+                window,
+                device,
+                factory,
+                main_color,
+                main_depth,
+            )
+        }
+        #[cfg(all(target_os = "windows", feature = "dx11"))]
+        Backend::D3d11 => {
+            let (backend, window, device, factory, main_color, main_depth) =
+                platform::launch_dx11::<ColorFormat, DepthFormat>(builder, &events_loop, context)
+                    .expect("Could not create window or graphics device");
+            run_window(
+                options,
+                config,
+                recording,
+                events_loop,
+                backend,
+                window,
+                device,
+                factory,
+                main_color,
+                main_depth,
+            )
+        }
+        backend => panic!("The {:?} backend cannot open a window", backend),
+    }
+}
 
-    window.hide_and_grab_cursor().expect(
+fn run_window<R, W, D, F>(
+    options: &Options,
+    config: &mut Config,
+    recording: Option<&Recording>,
+    mut events_loop: winit::EventsLoop,
+    backend: Backend,
+    window: W,
+    mut device: D,
+    mut factory: F,
+    mut main_color: RenderTargetView<R, ColorFormat>,
+    mut main_depth: DepthStencilView<R, DepthFormat>,
+) where
+    R: Resources,
+    W: PlatformWindow<R> + NativeWindow,
+    D: Device<Resources = R, CommandBuffer = F::CommandBuffer>,
+    F: PlFactoryExt<R> + Clone,
+{
+    window.window().hide_and_grab_cursor().expect(
         "Could not set cursor state",
     );
-    window.center_cursor().expect(
+    window.window().center_cursor().expect(
         "Could not set cursor position",
     );
 
@@ -290,104 +1053,139 @@ fn main() {
         .apply(GlyphBrushBuilder::using_fonts_bytes)
         .build(factory.clone());
 
-    let mut scene = {
-        let models = {
-            let mut monkey_model = Model::load(
-                &mut factory,
-                &backend,
-                main_color.clone(),
-                main_depth.clone(),
-                "suzanne",
-                "img/checker.png",
-            ).expect("Could not load model");
-
-            let mut cube_model = Model::load(
-                &mut factory,
-                &backend,
-                main_color.clone(),
-                main_depth.clone(),
-                "cube",
-                "img/checker.png",
-            ).expect("Could not load model");
-
-            /*
-            let mut floor_model = Model::load(
-                &mut factory,
-                &backend,
-                main_color.clone(),
-                main_depth.clone(),
-                "floor",
-                "img/checker.png",
-            ).expect("Could not load model");
-            */
-
-            monkey_model.similarity.isometry.translation.vector[1] += 2.0f32;
-            cube_model.similarity.isometry.translation.vector[1] -= 2.0f32;
-            //floor_model.similarity.isometry.translation.vector[1] -= 6.0f32;
-
-            vec![monkey_model, cube_model, /* floor_model */]
-        };
-
-        let lights: Vec<ShaderLight> = {
-            let l1 = Light::new(Point3::new(0.0, 3.0, -2.0), [1.0, 0.0, 0.0, 1.0], 300.0);
-            let l2 = Light::new(Point3::new(0.0, 1.6, 0.0), [1.0, 0.0, 0.0, 1.0], 400.0);
-            let l3 = Light::new(Point3::new(1.5, -3.0, 0.0), [1.0, 0.0, 1.0, 0.3], 300.0);
-            let l4 = Light::new(Point3::new(0.0, -1.8, 0.0), [1.0, 0.0, 1.0, 1.0], 400.0);
+    // The scene is drawn offscreen and then copied to the window, so that the
+    // frame can also be read back for screenshots.
+    let mut target = {
+        let (w, h, _, _) = main_color.get_dimensions();
+        OffscreenTarget::new(&mut factory, w, h, options.samples)
+            .expect("Could not create offscreen target")
+    };
+    let mut resolve =
+        Resolve::new(&mut factory, &backend, &target).expect("Could not create resolve");
+    let mut blit = Blit::new(&mut factory, &backend, &target, main_color.clone())
+        .expect("Could not create blit");
+    let mut capture = FrameCapture::new(CAPTURE_DIRECTORY);
 
-            [l1, l2, l3, l4].iter().map(Into::into).collect()
-        };
+    let mut scene =
+        load_scene(&mut factory, &backend, options, config, target.rtv(), target.dsv());
 
-        Scene::new(lights, models)
+    // A replay steps the camera with the settings it was recorded with, so
+    // that it does not depend on the config.
+    let (camera, update_rate, gizmo) = match recording {
+        Some(recording) => (
+            recording.camera.clone(),
+            recording.update_rate,
+            recording.gizmo.clone(),
+        ),
+        None => (config.camera.clone(), config.graphics.update_rate, config.gizmo.clone()),
     };
-
-    let mut cam_controller = CameraController::new(window.window(), MOUSE_SPEED, SPEED);
+    scene.gizmo = Gizmo::new(&gizmo);
+    // Keyframes are saved as they are added to the camera path given on the
+    // command line, except while replaying, which starts without a path so
+    // that it adds the same keyframes as it did when it was recorded. Nothing
+    // is loaded from the working directory, which a recording could not
+    // know about.
+    let camera_path_file = match recording {
+        Some(_) => None,
+        None => options.camera_path.clone(),
+    };
+    let mut cam_controller = CameraRig::new(window.window(), &camera);
+    if let Some(ref path) = camera_path_file {
+        if path.exists() {
+            cam_controller = cam_controller.with_camera_path(load_camera_path(path));
+        }
+    }
+    let mut previous_camera = cam_controller.clone();
+    let mut last_cursor = None;
+    // Where the cursor is in physical pixels, to select what is under it.
+    let mut cursor_position = (0.0, 0.0);
+    // Whether the select button is held, which drags what it grabbed.
+    let mut is_selecting = false;
+    // The free camera turns with the raw movement of the mouse while it has
+    // the cursor, and the orbit camera follows the cursor.
+    let mut is_cursor_grabbed = true;
+    let mut has_focus = true;
     let mut last = PreciseTime::now();
     let mut is_paused = false;
 
+    let mut replay = recording.map(|recording| recording.frames.iter());
+    let mut recorder = options.record.as_ref().map(|path| {
+        let window_size = window.window().windowext_get_inner_size::<u32>();
+        Recorder::create(path, window_size, &camera, update_rate, &gizmo)
+            .unwrap_or_else(|e| panic!("Could not record to {}: {}", path.display(), e))
+    });
+    let mut action_map = ActionMap::new(&config.key_bindings);
+    #[cfg(feature = "gamepad")]
+    let mut gamepads = if config.gamepad.enabled {
+        input::gamepad::open_gamepads(&config.gamepad)
+    } else {
+        None
+    };
+    let mut input_events = Vec::new();
+
     let mut is_running = true;
-    let mut fps = FpsCounter::new();
+    let mut fps = FpsCounter::new().with_show_fps(config.graphics.show_fps);
+    let mut timestep = FixedTimestep::new(update_rate.max(1.0));
+    let mut limiter = FrameLimiter::new(config.graphics.frame_rate_limit);
 
     while is_running {
         let current = PreciseTime::now();
-        let dt = last.to(current);
-        let dt_s = dt.as_seconds();
-        fps.update_fps(dt_s);
+        let mut dt_s = last.to(current).as_seconds() as f64;
+        fps.update_fps(dt_s as f32);
         last = current;
 
-        defer!({
-            let sleep_time = Duration::milliseconds(12)
-                .checked_sub(&dt)
-                .unwrap_or(Duration::zero())
-                .to_std()
-                .unwrap_or(StdDuration::from_millis(0));
-            std::thread::sleep(sleep_time);
-        });
-
+        let mut has_cursor_moved = false;
         events_loop.poll_events(|event| {
-            use winit::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+            use winit::{DeviceEvent, ElementState, Event, KeyboardInput, WindowEvent};
+            let events = &mut input_events;
             match event {
+                Event::DeviceEvent {
+                    event: DeviceEvent::MouseMotion { delta: (x, y) },
+                    ..
+                } => {
+                    // Motion is reported even when another window has focus.
+                    if is_cursor_grabbed {
+                        events.push(InputEvent::Look(x as f32, y as f32));
+                    }
+                }
                 Event::WindowEvent { event, .. } => {
                     match event {
-                        WindowEvent::Closed |
-                        WindowEvent::KeyboardInput {
-                            input: KeyboardInput {
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
-                        .. } => {
+                        WindowEvent::Closed => {
                             is_running = false;
                         }
-                        WindowEvent::Resized(..) => {
-                            scene.update_views(&window);
+                        WindowEvent::Resized(w, h) => {
+                            if !options.window.is_fullscreen {
+                                config.graphics.window_size = Some((w, h));
+                            }
+                            window.update_views(&mut main_color, &mut main_depth);
+                            let (w, h, _, _) = main_color.get_dimensions();
+                            target = OffscreenTarget::new(&mut factory, w, h, options.samples)
+                                .expect("Could not create offscreen target");
+                            resolve.set_target(&target);
+                            blit.set_source(&target);
+                            blit.set_target(main_color.clone());
+                            scene.set_targets(target.rtv(), target.dsv());
                             cam_controller.on_resize(window.window());
                         }
                         WindowEvent::CursorMoved { position: (x, y), .. } => {
-                            let (ww, wh) = window.windowext_get_inner_size::<i32>();
-                            let hidpi = window.hidpi_factor();
-                            cam_controller.on_cursor_moved(dt_s, (x, y), (ww, wh), hidpi);
-                            window.center_cursor().expect(
-                                "Could not set cursor position",
-                            );
+                            cursor_position = (x as f32, y as f32);
+                            has_cursor_moved = true;
+                            if !is_cursor_grabbed {
+                                let hidpi = window.window().hidpi_factor();
+                                let (x, y) = (x as f32 / hidpi, y as f32 / hidpi);
+                                if let Some((last_x, last_y)) = last_cursor {
+                                    events.push(InputEvent::Look(x - last_x, y - last_y));
+                                }
+                                last_cursor = Some((x, y));
+                            }
+                        }
+                        WindowEvent::MouseWheel { delta, .. } => {
+                            use winit::MouseScrollDelta;
+                            let lines = match delta {
+                                MouseScrollDelta::LineDelta(_, y) => y,
+                                MouseScrollDelta::PixelDelta(_, y) => y / PIXELS_PER_LINE,
+                            };
+                            events.push(InputEvent::Scroll(lines));
                         }
                         WindowEvent::KeyboardInput { input, .. } => {
                             match input {
@@ -397,22 +1195,21 @@ fn main() {
                                     ..
                                 } => {
                                     let is_pressed = state == ElementState::Pressed;
-                                    match vk_code {
-                                        VirtualKeyCode::Up | VirtualKeyCode::W => cam_controller.input.moving_forwards = is_pressed,
-                                        VirtualKeyCode::Down | VirtualKeyCode::S => cam_controller.input.moving_backwards = is_pressed,
-                                        VirtualKeyCode::Left | VirtualKeyCode::A => cam_controller.input.moving_left = is_pressed,
-                                        VirtualKeyCode::Right | VirtualKeyCode::D => cam_controller.input.moving_right = is_pressed,
-                                        VirtualKeyCode::E => cam_controller.input.moving_up = is_pressed,
-                                        VirtualKeyCode::Q => cam_controller.input.moving_down = is_pressed, 
-                                        VirtualKeyCode::Space if is_pressed => fps.toggle_show_fps(),
-                                        _ => {}
-                                    }
+                                    action_map.on_input(Input::Key(vk_code), is_pressed, events);
                                 }
                                 _ => {}
                             }
                         }
+                        WindowEvent::MouseInput { state, button, .. } => {
+                            let is_pressed = state == ElementState::Pressed;
+                            action_map.on_input(Input::Mouse(button), is_pressed, events);
+                        }
                         WindowEvent::Focused(gained) => {
-                            is_paused = !gained;
+                            has_focus = gained;
+                            if !gained {
+                                action_map.release_all(events);
+                            }
+                            events.push(InputEvent::Focus(gained));
                         }
                         _ => (),
                     }
@@ -421,31 +1218,163 @@ fn main() {
             }
         });
 
-        cam_controller.apply_input(dt_s);
+        #[cfg(feature = "gamepad")]
+        {
+            if let Some(ref mut gamepads) = gamepads {
+                gamepads.poll(&mut action_map, &mut input_events);
+            }
+        }
+
+        // Selecting and dragging are recorded with where they happened, so
+        // that replays select the same thing at any window size. While the
+        // cursor is grabbed, what is in the middle of the screen is selected.
+        // Each pick goes straight after the press which made it, so that a
+        // release in the same frame lets go of whatever it grabbed.
+        let (x, y) = if is_cursor_grabbed {
+            (0.0, 0.0)
+        } else {
+            let (width, height) = window.window().windowext_get_inner_size::<f32>();
+            (
+                2.0 * cursor_position.0 / width.max(1.0) - 1.0,
+                1.0 - 2.0 * cursor_position.1 / height.max(1.0),
+            )
+        };
+        let mut events = Vec::with_capacity(input_events.len() + 2);
+        for event in input_events.drain(..) {
+            events.push(event);
+            if let InputEvent::Action(Action::Select, is_pressed) = event {
+                is_selecting = is_pressed;
+                if is_pressed {
+                    events.push(InputEvent::Pick(x, y));
+                }
+            }
+        }
+        if is_selecting && has_cursor_moved && !is_cursor_grabbed {
+            events.push(InputEvent::Drag(x, y));
+        }
+        input_events = events;
+
+        // While a recording plays, the user can only quit.
+        if let Some(ref mut replay) = replay {
+            let quit = InputEvent::Action(Action::Quit, true);
+            let wants_to_quit = input_events.contains(&quit);
+            input_events.clear();
+            if wants_to_quit {
+                input_events.push(quit);
+            }
+            match replay.next() {
+                Some(frame) => {
+                    dt_s = frame.dt_s;
+                    input_events.extend(frame.events.iter().cloned());
+                }
+                None => {
+                    eprintln!("Finished replaying");
+                    break;
+                }
+            }
+        }
+
+        if let Some(ref mut recorder) = recorder {
+            recorder.record(dt_s, &input_events).expect("Could not record input");
+        }
+
+        let actions = apply_input(&input_events, &mut scene, &mut cam_controller, &mut is_paused);
+        // The cursor is let go while another window has focus, so that it can
+        // be used there.
+        let should_grab_cursor = has_focus && cam_controller.grabs_cursor();
+        if should_grab_cursor != is_cursor_grabbed {
+            if should_grab_cursor {
+                window.window().hide_and_grab_cursor().expect("Could not set cursor state");
+                window.window().center_cursor().expect("Could not set cursor position");
+            } else {
+                window.window().release_cursor().expect("Could not set cursor state");
+            }
+            is_cursor_grabbed = should_grab_cursor;
+            last_cursor = None;
+        }
+        for action in actions {
+            match action {
+                Action::ToggleFps => {
+                    fps.toggle_show_fps();
+                    config.graphics.show_fps = fps.is_showing_fps();
+                }
+                Action::Screenshot => capture.request_screenshot(),
+                Action::ToggleRecording => {
+                    capture.toggle_sequence();
+                    if capture.is_recording() {
+                        eprintln!("Recording frames to {}", capture.directory().display());
+                    }
+                }
+                Action::AddKeyframe => {
+                    if let Some(ref path) = camera_path_file {
+                        let keyframes = cam_controller.path.path.keyframes().len();
+                        match cam_controller.path.path.save(path) {
+                            Ok(()) => eprintln!(
+                                "Saved keyframe {} to {}",
+                                keyframes,
+                                path.display()
+                            ),
+                            Err(e) => eprintln!("Could not save the camera path: {}", e),
+                        }
+                    }
+                }
+                Action::Quit => is_running = false,
+                _ => (),
+            }
+        }
+        input_events.clear();
 
         if is_paused {
+            timestep.discard_remainder();
+            limiter.wait();
             continue;
         }
 
-        let rot = UnitQuaternion::from_euler_angles(0.0, Degrees(25.0 * dt_s).in_radians(), 0.0);
-        for model in &mut scene.models {
-            model.similarity.append_rotation_mut(&rot);
+        cam_controller.smooth_look(dt_s as f32);
+        for _ in 0..timestep.advance(dt_s) {
+            previous_camera = cam_controller.clone();
+            cam_controller.apply_input(timestep.step(), &scene.collision);
+            scene.update(timestep.step());
         }
+        let alpha = timestep.alpha();
+        let camera = cam_controller.interpolated(&previous_camera, alpha);
 
-        encoder.clear(&main_color, CLEAR_COLOR);
-        encoder.clear_depth(&main_depth, 1.0);
+        encoder.clear(target.rtv(), scene.clear_color());
+        encoder.clear_depth(target.dsv(), 1.0);
 
         let styling = Styling {
             screen_position: (5.0, 5.0),
-            scale: Scale::uniform(32.0f32 * window.hidpi_factor()),
+            scale: Scale::uniform(32.0f32 * window.window().hidpi_factor()),
             color: [1.0, 1.0, 1.0, 1.0],
             ..Default::default()
         };
         fps.queue_text(&styling, &mut glyph_brush);
 
         scene
-            .render(&mut encoder, &cam_controller)
+            .render(&mut encoder, &camera, timestep.time(), alpha)
             .expect("Could not render scene");
+        resolve.encode(&mut encoder);
+
+        // A frame which cannot be written is reported, and stops recording,
+        // rather than closing the viewer.
+        let texture = target.color_texture();
+        let captured = capture.capture(&mut factory, &mut encoder, &mut device, &backend, texture);
+        match captured {
+            Ok(ref captured) if !capture.is_recording() => {
+                for path in captured {
+                    eprintln!("Saved {}", path.display());
+                }
+            }
+            Ok(_) => (),
+            Err(e) => {
+                eprintln!("Could not write to {}: {}", capture.directory().display(), e);
+                if capture.is_recording() {
+                    capture.toggle_sequence();
+                }
+            }
+        }
+
+        blit.encode(&mut encoder);
 
         glyph_brush
             .draw_queued(&mut encoder, &main_color, &main_depth)
@@ -454,7 +1383,99 @@ fn main() {
         encoder.flush(&mut device);
         window.swap_buffers().unwrap();
         device.cleanup();
+        limiter.wait();
+    }
+}
+
+/// Applies input to the cameras and pausing, and returns the other actions
+/// which were started, for the caller to carry out. Live and replayed input
+/// both go through here, so that a replay behaves exactly like the session it
+/// was recorded from.
+fn apply_input<R: Resources>(
+    events: &[InputEvent],
+    scene: &mut Scene<R>,
+    cam_controller: &mut CameraRig,
+    is_paused: &mut bool,
+) -> Vec<Action> {
+    let mut actions = Vec::new();
+    for event in events {
+        match *event {
+            InputEvent::Action(action, is_pressed) => {
+                if scene.gizmo.on_action(action, is_pressed) {
+                    continue;
+                }
+                if cam_controller.on_action(action, is_pressed) || !is_pressed {
+                    continue;
+                }
+                match action {
+                    Action::ToggleCameraMode => cam_controller.toggle_mode(),
+                    Action::ToggleWalking => cam_controller.toggle_walking(),
+                    Action::FrameSelected => {
+                        // Frames the whole scene when nothing is selected.
+                        let bounds = match scene.selected {
+                            Some(pick) => Some(scene.models[pick.model].bounds()),
+                            None => scene.bounds(),
+                        };
+                        if let Some(bounds) = bounds {
+                            cam_controller.frame(&bounds);
+                        }
+                    }
+                    Action::ToggleProjection => cam_controller.toggle_projection(),
+                    Action::ViewFront => cam_controller.view(ViewPreset::Front),
+                    Action::ViewBack => cam_controller.view(ViewPreset::Back),
+                    Action::ViewLeft => cam_controller.view(ViewPreset::Left),
+                    Action::ViewRight => cam_controller.view(ViewPreset::Right),
+                    Action::ViewTop => cam_controller.view(ViewPreset::Top),
+                    Action::ViewBottom => cam_controller.view(ViewPreset::Bottom),
+                    Action::WidenFov => cam_controller.widen_fov(Degrees(FOV_STEP)),
+                    Action::NarrowFov => cam_controller.widen_fov(Degrees(-FOV_STEP)),
+                    Action::PushNearPlane => cam_controller.scale_clip_planes(CLIP_PLANE_STEP, 1.0),
+                    Action::PullNearPlane => {
+                        cam_controller.scale_clip_planes(1.0 / CLIP_PLANE_STEP, 1.0)
+                    }
+                    Action::PushFarPlane => cam_controller.scale_clip_planes(1.0, CLIP_PLANE_STEP),
+                    Action::PullFarPlane => {
+                        cam_controller.scale_clip_planes(1.0, 1.0 / CLIP_PLANE_STEP)
+                    }
+                    Action::PlayCameraPath => cam_controller.toggle_path_playback(),
+                    Action::RewindCameraPath => cam_controller.rewind_path(),
+                    Action::AddKeyframe => {
+                        // The caller saves the path.
+                        cam_controller.add_keyframe();
+                        actions.push(action);
+                    }
+                    _ => actions.push(action),
+                }
+            }
+            InputEvent::Look(x, y) => cam_controller.look(x, y),
+            InputEvent::Scroll(lines) => cam_controller.scroll(lines),
+            InputEvent::Analogue(action, value) => cam_controller.on_analogue(action, value),
+            InputEvent::Pick(x, y) => {
+                // The gizmo over the selected model is in front of everything,
+                // so it is grabbed before anything else is picked.
+                let matrices = cam_controller.matrices();
+                let ray = Ray::from_screen(&matrices, x, y);
+                let grabbed = match (ray, scene.selected) {
+                    (Some(ray), Some(pick)) => {
+                        let transform = scene.models[pick.model].transform;
+                        scene.gizmo.grab(&ray, &matrices, &transform)
+                    }
+                    _ => false,
+                };
+                if !grabbed {
+                    scene.selected = ray.and_then(|ray| scene.pick(&ray));
+                }
+            }
+            InputEvent::Drag(x, y) => {
+                let ray = Ray::from_screen(&cam_controller.matrices(), x, y);
+                if let (Some(ray), Some(pick)) = (ray, scene.selected) {
+                    scene.gizmo.drag_to(&ray, &mut scene.models[pick.model].transform);
+                }
+            }
+            InputEvent::Focus(gained) => *is_paused = !gained,
+        }
     }
+    actions
 }
 
 trait GetSeconds {
@@ -470,6 +1491,7 @@ impl GetSeconds for Duration {
 pub trait WindowExt {
     fn center_cursor(&self) -> Result<(), ()>;
     fn hide_and_grab_cursor(&self) -> Result<(), String>;
+    fn release_cursor(&self) -> Result<(), String>;
     fn windowext_get_inner_size<N: NumCast + Zero + Default>(&self) -> (N, N);
     fn aspect<N: Default + Div<Output = N> + NumCast + Zero>(&self) -> N {
         let (w, h) = self.windowext_get_inner_size::<N>();
@@ -488,6 +1510,10 @@ impl WindowExt for winit::Window {
         self.set_cursor_state(winit::CursorState::Grab)
     }
 
+    fn release_cursor(&self) -> Result<(), String> {
+        self.set_cursor_state(winit::CursorState::Normal)
+    }
+
     fn windowext_get_inner_size<N: NumCast + Zero + Default>(&self) -> (N, N) {
         fn cast_pair<N: NumCast + Zero>((x, y): (u32, u32)) -> (N, N) {
             (
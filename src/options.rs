@@ -0,0 +1,456 @@
+use clap::{self, App, Arg, ArgMatches};
+use config::Config;
+use graphics::platform::Backend;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+use std::num::ParseIntError;
+use std::path::PathBuf;
+
+const DEFAULT_HEADLESS_FRAMES: u32 = 1;
+const DEFAULT_HEADLESS_SIZE: (u32, u32) = (800, 600);
+/// The largest window or frame, which is as large as textures are allowed to
+/// be on most graphics devices, and which fits the sizes gfx takes.
+const MAX_SIZE: u32 = 16384;
+
+const MSAA_SAMPLES: &[&str] = &["1", "2", "4", "8", "16"];
+const SCENES: &[&str] = &["demo", "materials"];
+
+/// The scenes which are built in, and shown when no models are given.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum SceneKind {
+    /// The spinning models the viewer starts with.
+    Demo,
+    /// Models from smooth to rough and from plastic to metal over a floor, as
+    /// a reference for the lighting.
+    Materials,
+}
+
+/// How the program was asked to run on the command line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Options {
+    /// The models to show, either as names of meshes in `data/mesh` or as
+    /// paths to `.obj` files. The built in `scene` is shown when there are
+    /// none.
+    pub models: Vec<String>,
+    pub scene: SceneKind,
+    /// A base colour texture for the models, instead of the checkerboard.
+    pub texture: Option<String>,
+    pub window: WindowOptions,
+    /// The graphics backend, or `None` for the default of the platform.
+    pub backend: Option<Backend>,
+    /// The number of samples per pixel the scene is drawn with.
+    pub samples: u8,
+    pub headless: Option<HeadlessOptions>,
+    /// Where to record the input to, so that the session can be replayed.
+    pub record: Option<PathBuf>,
+    /// A recording to play back instead of taking input from the user.
+    pub replay: Option<PathBuf>,
+    /// A camera path to add keyframes to, or to fly along when headless.
+    pub camera_path: Option<PathBuf>,
+}
+
+impl Default for Options {
+    #[inline]
+    fn default() -> Self {
+        Options {
+            models: Vec::new(),
+            scene: SceneKind::Demo,
+            texture: None,
+            window: Default::default(),
+            backend: None,
+            samples: 1,
+            headless: None,
+            record: None,
+            replay: None,
+            camera_path: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct WindowOptions {
+    /// The size of the window, or `None` to cover the primary monitor.
+    pub size: Option<(u32, u32)>,
+    pub is_fullscreen: bool,
+    pub is_vsync_enabled: bool,
+}
+
+impl Default for WindowOptions {
+    #[inline]
+    fn default() -> Self {
+        WindowOptions {
+            size: None,
+            is_fullscreen: false,
+            is_vsync_enabled: true,
+        }
+    }
+}
+
+/// Renders a fixed number of frames to an offscreen target instead of opening
+/// a window. Frames are stepped at a fixed rate rather than wall-clock time,
+/// so the same options always produce the same images.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct HeadlessOptions {
+    pub width: u32,
+    pub height: u32,
+    pub frames: u32,
+    /// Where to write the last frame as a PNG.
+    pub screenshot: Option<PathBuf>,
+    /// A directory to write every frame to, as a numbered sequence of PNGs.
+    pub capture_directory: Option<PathBuf>,
+}
+
+impl Default for HeadlessOptions {
+    #[inline]
+    fn default() -> Self {
+        HeadlessOptions {
+            width: DEFAULT_HEADLESS_SIZE.0,
+            height: DEFAULT_HEADLESS_SIZE.1,
+            frames: DEFAULT_HEADLESS_FRAMES,
+            screenshot: None,
+            capture_directory: None,
+        }
+    }
+}
+
+impl Options {
+    /// Parses the command line, including the name of the binary. Asking for
+    /// `--help` or `--version` is returned as a `Clap` error, which prints the
+    /// text when it exits.
+    ///
+    /// Anything which is not given on the command line is taken from the
+    /// config, except in headless mode, which always starts from the default
+    /// config so that its output does not depend on the user's settings.
+    pub fn parse<I, T>(args: I, config: &Config) -> Result<Self, OptionsError>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let backends = available_backends();
+        let matches = App::new("gfx_tutorial")
+            .version(env!("CARGO_PKG_VERSION"))
+            .about("Views obj models with physically based shading")
+            .arg(
+                Arg::with_name("models")
+                    .value_name("MODEL")
+                    .multiple(true)
+                    .help("Meshes to show, as names in data/mesh or paths to .obj files"),
+            )
+            .arg(
+                Arg::with_name("scene")
+                    .long("scene")
+                    .value_name("SCENE")
+                    .possible_values(SCENES)
+                    .conflicts_with("models")
+                    .help("A built in scene to show instead of the demo"),
+            )
+            .arg(
+                Arg::with_name("texture")
+                    .long("texture")
+                    .short("t")
+                    .value_name("FILE")
+                    .help("A base colour texture for the models"),
+            )
+            .arg(
+                Arg::with_name("size")
+                    .long("size")
+                    .value_name("WIDTHxHEIGHT")
+                    .help("The size of the window or of the headless frames"),
+            )
+            .arg(
+                Arg::with_name("fullscreen")
+                    .long("fullscreen")
+                    .short("f")
+                    .conflicts_with("headless")
+                    .help("Covers the primary monitor"),
+            )
+            .arg(
+                Arg::with_name("windowed")
+                    .long("windowed")
+                    .conflicts_with("fullscreen")
+                    .help("Opens a window even if the config asks for fullscreen"),
+            )
+            .arg(
+                Arg::with_name("vsync")
+                    .long("vsync")
+                    .conflicts_with("no-vsync")
+                    .help("Waits for the display before presenting each frame"),
+            )
+            .arg(
+                Arg::with_name("no-vsync")
+                    .long("no-vsync")
+                    .conflicts_with("headless")
+                    .help("Presents frames without waiting for the display"),
+            )
+            .arg(
+                Arg::with_name("backend")
+                    .long("backend")
+                    .short("b")
+                    .value_name("BACKEND")
+                    .possible_values(&backends)
+                    .help("The graphics backend to render with"),
+            )
+            .arg(
+                Arg::with_name("msaa")
+                    .long("msaa")
+                    .value_name("SAMPLES")
+                    .possible_values(MSAA_SAMPLES)
+                    .help("Multisamples the scene, except on the software backend"),
+            )
+            .arg(
+                Arg::with_name("headless")
+                    .long("headless")
+                    .help("Renders offscreen without opening a window"),
+            )
+            .arg(
+                Arg::with_name("record")
+                    .long("record")
+                    .value_name("FILE")
+                    .conflicts_with_all(&["headless", "replay"])
+                    .help("Records the input, and how long each frame took, to a file"),
+            )
+            .arg(
+                Arg::with_name("replay")
+                    .long("replay")
+                    .value_name("FILE")
+                    .help("Plays back a recording instead of taking input"),
+            )
+            .arg(
+                Arg::with_name("camera-path")
+                    .long("camera-path")
+                    .value_name("FILE")
+                    .conflicts_with("replay")
+                    .help("A camera path to add keyframes to, or to fly along when headless"),
+            )
+            .arg(
+                Arg::with_name("frames")
+                    .long("frames")
+                    .value_name("N")
+                    .requires("headless")
+                    .conflicts_with("replay")
+                    .help("How many frames to render headless, at 60 frames a second"),
+            )
+            .arg(
+                Arg::with_name("screenshot")
+                    .long("screenshot")
+                    .value_name("FILE")
+                    .requires("headless")
+                    .help("Writes the last headless frame to a PNG"),
+            )
+            .arg(
+                Arg::with_name("capture")
+                    .long("capture")
+                    .value_name("DIR")
+                    .requires("headless")
+                    .help("Writes every headless frame to a numbered sequence of PNGs"),
+            )
+            .get_matches_from_safe(args)?;
+
+        if matches.is_present("headless") {
+            Options::from_matches(&matches, &Config::default())
+        } else {
+            Options::from_matches(&matches, config)
+        }
+    }
+
+    fn from_matches(matches: &ArgMatches, config: &Config) -> Result<Self, OptionsError> {
+        let graphics = &config.graphics;
+        let size = match matches.value_of("size") {
+            Some(size) => Some(parse_size(size)?),
+            None => None,
+        };
+        let backend = match matches.value_of("backend") {
+            Some(name) => Some(parse_backend(name)),
+            None => match graphics.backend {
+                Some(ref name) if available_backends().contains(&name.as_str()) => {
+                    Some(parse_backend(name))
+                }
+                Some(ref name) => {
+                    eprintln!(
+                        "Using the default backend, the config names {}, which is not one of {}",
+                        name,
+                        available_backends().join(", ")
+                    );
+                    None
+                }
+                None => None,
+            },
+        };
+
+        let headless = if matches.is_present("headless") {
+            let can_render_headless = match backend {
+                Some(Backend::Software) => true,
+                None | Some(Backend::Gl) => cfg!(feature = "gl"),
+                _ => false,
+            };
+            if !can_render_headless {
+                return Err(OptionsError::HeadlessRequiresGl);
+            }
+            let (width, height) = size.unwrap_or(DEFAULT_HEADLESS_SIZE);
+            let frames = match matches.value_of("frames") {
+                Some(frames) => frames.parse()?,
+                None => DEFAULT_HEADLESS_FRAMES,
+            };
+            if frames == 0 {
+                return Err(OptionsError::NoFrames);
+            }
+            Some(HeadlessOptions {
+                width,
+                height,
+                frames,
+                screenshot: matches.value_of_os("screenshot").map(PathBuf::from),
+                capture_directory: matches.value_of_os("capture").map(PathBuf::from),
+            })
+        } else {
+            if backend == Some(Backend::Software) {
+                return Err(OptionsError::SoftwareRequiresHeadless);
+            }
+            None
+        };
+        if backend == Some(Backend::Software) && matches.is_present("replay") {
+            return Err(OptionsError::SoftwareCannotReplay);
+        }
+
+        Ok(Options {
+            models: matches
+                .values_of("models")
+                .map_or(Vec::new(), |models| models.map(String::from).collect()),
+            scene: match matches.value_of("scene") {
+                Some("materials") => SceneKind::Materials,
+                _ => SceneKind::Demo,
+            },
+            texture: matches.value_of("texture").map(String::from),
+            window: WindowOptions {
+                size: size.or(graphics.window_size),
+                is_fullscreen: matches.is_present("fullscreen") ||
+                    (graphics.fullscreen && !matches.is_present("windowed")),
+                is_vsync_enabled: matches.is_present("vsync") ||
+                    (graphics.vsync && !matches.is_present("no-vsync")),
+            },
+            backend,
+            samples: match matches.value_of("msaa") {
+                Some(samples) => samples.parse()?,
+                None => graphics.msaa,
+            },
+            headless,
+            record: matches.value_of_os("record").map(PathBuf::from),
+            replay: matches.value_of_os("replay").map(PathBuf::from),
+            camera_path: matches.value_of_os("camera-path").map(PathBuf::from),
+        })
+    }
+}
+
+/// The names of the backends which were compiled in.
+fn available_backends() -> Vec<&'static str> {
+    let mut backends = Vec::new();
+    if cfg!(feature = "gl") {
+        backends.push("gl");
+    }
+    if cfg!(all(target_os = "macos", feature = "metal")) {
+        backends.push("metal");
+    }
+    if cfg!(all(target_os = "windows", feature = "dx11")) {
+        backends.push("dx11");
+    }
+    backends.push("software");
+    backends
+}
+
+/// Only called with one of the `available_backends`.
+fn parse_backend(name: &str) -> Backend {
+    match name {
+        "gl" => Backend::Gl,
+        "metal" => Backend::Metal,
+        "dx11" => Backend::D3d11,
+        _ => Backend::Software,
+    }
+}
+
+fn parse_size(value: &str) -> Result<(u32, u32), OptionsError> {
+    let mut parts = value.splitn(2, 'x');
+    match (parts.next(), parts.next()) {
+        (Some(width), Some(height)) => {
+            let size = (width.parse()?, height.parse()?);
+            if size.0 == 0 || size.1 == 0 || size.0 > MAX_SIZE || size.1 > MAX_SIZE {
+                Err(OptionsError::InvalidSize(value.to_owned()))
+            } else {
+                Ok(size)
+            }
+        }
+        _ => Err(OptionsError::InvalidSize(value.to_owned())),
+    }
+}
+
+#[derive(Debug)]
+pub enum OptionsError {
+    Clap(clap::Error),
+    InvalidNumber(ParseIntError),
+    InvalidSize(String),
+    NoFrames,
+    SoftwareRequiresHeadless,
+    HeadlessRequiresGl,
+    SoftwareCannotReplay,
+}
+
+impl From<clap::Error> for OptionsError {
+    #[inline]
+    fn from(e: clap::Error) -> Self {
+        OptionsError::Clap(e)
+    }
+}
+
+impl From<ParseIntError> for OptionsError {
+    #[inline]
+    fn from(e: ParseIntError) -> Self {
+        OptionsError::InvalidNumber(e)
+    }
+}
+
+impl fmt::Display for OptionsError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            OptionsError::Clap(ref e) => fmt::Display::fmt(e, fmtr),
+            OptionsError::InvalidNumber(ref e) => write!(fmtr, "{}: {}", desc, e),
+            OptionsError::InvalidSize(ref size) => write!(fmtr, "{}: {}", desc, size),
+            OptionsError::NoFrames |
+            OptionsError::SoftwareRequiresHeadless |
+            OptionsError::HeadlessRequiresGl |
+            OptionsError::SoftwareCannotReplay => write!(fmtr, "{}", desc),
+        }
+    }
+}
+
+impl Error for OptionsError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            OptionsError::Clap(ref e) => e.description(),
+            OptionsError::InvalidNumber(_) => "Expected a number",
+            OptionsError::InvalidSize(_) => {
+                "Expected a size like 800x600, of no more than 16384 each way"
+            }
+            OptionsError::NoFrames => "At least one frame must be rendered",
+            OptionsError::SoftwareRequiresHeadless => {
+                "The software backend can only be used with --headless"
+            }
+            OptionsError::HeadlessRequiresGl => {
+                "Headless rendering needs the gl or software backend"
+            }
+            OptionsError::SoftwareCannotReplay => {
+                "Recordings cannot be replayed with the software backend"
+            }
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            OptionsError::Clap(ref e) => Some(e),
+            OptionsError::InvalidNumber(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,635 @@
+//! A bounding volume hierarchy over the triangles of a mesh, so that rays,
+//! spheres and boxes only need to be tested against the triangles near them.
+//!
+//! The hierarchy is split by the surface area heuristic, which puts each split
+//! where the triangles on either side are cheapest to test, assuming that
+//! rays and shapes hit boxes in proportion to their surface area. Building
+//! one for a large mesh takes a while, so it can be cached as plain text:
+//!
+//! ```text
+//! gfx_tutorial bvh 1
+//! triangles 12 1234567890
+//! branch -1 -1 -1 1 1 1 1 2
+//! leaf -1 -1 -1 1 1 0 0 6
+//! leaf -1 -1 0 1 1 1 6 6
+//! order 0 1 2 3 4 5 6 7 8 9 10 11
+//! ```
+//!
+//! The `triangles` line gives how many triangles the hierarchy was built over
+//! and a hash of them, so that a cache is not used for a mesh which changed.
+//! Each node gives its bounds, and then either the range of `order` which
+//! holds its triangles or the indices of its children.
+
+use graphics::bounds::Aabb;
+use na::Point3;
+use physics::collision::Triangle;
+use physics::ray::{Ray, RayHit};
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::str::{FromStr, SplitWhitespace};
+
+const HEADER: &str = "gfx_tutorial bvh 1";
+/// How many buckets the triangles are sorted into along each axis when
+/// looking for the best split.
+const BINS: usize = 12;
+/// The most triangles a leaf holds when splitting it would be cheaper.
+const MAX_LEAF_TRIANGLES: usize = 8;
+/// The cost of visiting a node, relative to testing a triangle.
+const TRAVERSAL_COST: f32 = 1.0;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bvh {
+    triangles: Vec<Triangle>,
+    /// Indices into `triangles`, arranged so that each leaf's are together.
+    order: Vec<usize>,
+    /// The root is first, if there are any triangles.
+    nodes: Vec<Node>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Node {
+    bounds: Aabb,
+    kind: NodeKind,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum NodeKind {
+    /// The triangles `order[first..first + count]`.
+    Leaf { first: usize, count: usize },
+    Branch { left: usize, right: usize },
+}
+
+impl Bvh {
+    pub fn new(triangles: Vec<Triangle>) -> Self {
+        let bounds: Vec<Aabb> = triangles.iter().map(Triangle::bounds).collect();
+        let centroids: Vec<Point3<f32>> = triangles
+            .iter()
+            .map(|t| Point3::from_coordinates((t.a.coords + t.b.coords + t.c.coords) / 3.0))
+            .collect();
+        let mut order: Vec<usize> = (0..triangles.len()).collect();
+        let mut nodes = Vec::new();
+        if !triangles.is_empty() {
+            build(&bounds, &centroids, &mut order, 0, &mut nodes);
+        }
+        Bvh {
+            triangles,
+            order,
+            nodes,
+        }
+    }
+
+    /// Loads the hierarchy over `triangles` which was cached at `path`, or
+    /// builds it and caches it there if there is no cache or it was made from
+    /// other triangles.
+    pub fn load_or_build<P: AsRef<Path>>(triangles: Vec<Triangle>, path: P) -> Self {
+        let path = path.as_ref();
+        if let Ok(bvh) = Bvh::load(path, &triangles) {
+            return bvh;
+        }
+        let bvh = Bvh::new(triangles);
+        if let Err(e) = bvh.save(path) {
+            eprintln!("Could not cache a bvh at {}: {}", path.display(), e);
+        }
+        bvh
+    }
+
+    /// Loads a hierarchy which was saved for `triangles`.
+    pub fn load<P: AsRef<Path>>(path: P, triangles: &[Triangle]) -> Result<Self, BvhError> {
+        let file = BufReader::new(File::open(path)?);
+        let mut lines = file.lines();
+        match lines.next() {
+            Some(Ok(ref line)) if line.trim() == HEADER => (),
+            Some(Err(e)) => return Err(e.into()),
+            _ => return Err(BvhError::NotABvh),
+        }
+
+        let mut bvh = Bvh {
+            triangles: triangles.to_vec(),
+            order: Vec::new(),
+            nodes: Vec::new(),
+        };
+        let mut source = None;
+        for (index, line) in lines.enumerate() {
+            let line = line?;
+            if bvh.parse_line(&line, &mut source).is_none() {
+                // The header is line 1.
+                return Err(BvhError::Parse(index + 2, line));
+            }
+        }
+
+        if source != Some((triangles.len(), fingerprint(triangles))) {
+            return Err(BvhError::Stale);
+        }
+        if !bvh.is_valid() {
+            return Err(BvhError::NotABvh);
+        }
+        Ok(bvh)
+    }
+
+    /// Adds what the line says to the hierarchy, or returns `None` if the
+    /// line is not valid.
+    fn parse_line(&mut self, line: &str, source: &mut Option<(usize, u64)>) -> Option<()> {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            None => (),
+            Some("triangles") => *source = Some((parse(&mut words)?, parse(&mut words)?)),
+            Some("order") => {
+                for word in words {
+                    self.order.push(word.parse().ok()?);
+                }
+            }
+            Some(kind) => {
+                let bounds = Aabb::new(
+                    Point3::new(parse(&mut words)?, parse(&mut words)?, parse(&mut words)?),
+                    Point3::new(parse(&mut words)?, parse(&mut words)?, parse(&mut words)?),
+                );
+                let (a, b) = (parse(&mut words)?, parse(&mut words)?);
+                let kind = match kind {
+                    "leaf" => NodeKind::Leaf { first: a, count: b },
+                    "branch" => NodeKind::Branch { left: a, right: b },
+                    _ => return None,
+                };
+                self.nodes.push(Node { bounds, kind });
+            }
+        }
+        Some(())
+    }
+
+    /// Whether every index in the hierarchy is in range, and every child
+    /// comes after its parent so that there are no loops.
+    fn is_valid(&self) -> bool {
+        let count = self.triangles.len();
+        if self.order.len() != count || self.order.iter().any(|&i| i >= count) {
+            return false;
+        }
+        if count > 0 && self.nodes.is_empty() {
+            return false;
+        }
+        self.nodes.iter().enumerate().all(|(index, node)| match node.kind {
+            NodeKind::Leaf { first, count: leaf_count } => first + leaf_count <= count,
+            NodeKind::Branch { left, right } => {
+                index < left && index < right && left < self.nodes.len()
+                    && right < self.nodes.len()
+            }
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), BvhError> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+        writeln!(file, "{}", HEADER)?;
+        writeln!(
+            file,
+            "triangles {} {}",
+            self.triangles.len(),
+            fingerprint(&self.triangles)
+        )?;
+        for node in &self.nodes {
+            let (min, max) = (node.bounds.min, node.bounds.max);
+            let (kind, a, b) = match node.kind {
+                NodeKind::Leaf { first, count } => ("leaf", first, count),
+                NodeKind::Branch { left, right } => ("branch", left, right),
+            };
+            writeln!(
+                file,
+                "{} {} {} {} {} {} {} {} {}",
+                kind, min.x, min.y, min.z, max.x, max.y, max.z, a, b
+            )?;
+        }
+        write!(file, "order")?;
+        for index in &self.order {
+            write!(file, " {}", index)?;
+        }
+        writeln!(file)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// The triangles in the order they were given.
+    #[inline]
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
+    }
+
+    /// The nearest triangle the ray hits, if it hits any.
+    pub fn cast_ray(&self, ray: &Ray) -> Option<RayHit> {
+        let mut nearest: Option<RayHit> = None;
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            match ray.intersect_aabb(&node.bounds) {
+                Some(entry) if nearest.map_or(true, |hit| entry < hit.distance) => (),
+                _ => continue,
+            }
+            match node.kind {
+                NodeKind::Leaf { first, count } => {
+                    for &triangle in &self.order[first..first + count] {
+                        if let Some(distance) = ray.intersect_triangle(&self.triangles[triangle]) {
+                            if nearest.map_or(true, |hit| distance < hit.distance) {
+                                nearest = Some(RayHit { triangle, distance });
+                            }
+                        }
+                    }
+                }
+                NodeKind::Branch { left, right } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+        nearest
+    }
+
+    /// The indices of the triangles whose bounds overlap the box.
+    pub fn triangles_in_aabb(&self, aabb: &Aabb) -> Vec<usize> {
+        self.query(
+            |bounds| bounds.intersects(aabb),
+            |triangle| triangle.bounds().intersects(aabb),
+        )
+    }
+
+    /// The indices of the triangles which are at least partly inside the
+    /// sphere.
+    #[allow(dead_code)]
+    pub fn triangles_in_sphere(&self, center: &Point3<f32>, radius: f32) -> Vec<usize> {
+        let radius_squared = radius * radius;
+        self.query(
+            |bounds| (bounds.closest_point(center) - center).norm_squared() <= radius_squared,
+            |triangle| (triangle.closest_point(center) - center).norm_squared() <= radius_squared,
+        )
+    }
+
+    /// The indices of the triangles in the leaves whose bounds pass
+    /// `visit`, which themselves pass `keep`.
+    fn query<V, K>(&self, visit: V, keep: K) -> Vec<usize>
+    where
+        V: Fn(&Aabb) -> bool,
+        K: Fn(&Triangle) -> bool,
+    {
+        let mut found = Vec::new();
+        let mut stack = Vec::new();
+        if !self.nodes.is_empty() {
+            stack.push(0);
+        }
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !visit(&node.bounds) {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf { first, count } => found.extend(
+                    self.order[first..first + count]
+                        .iter()
+                        .cloned()
+                        .filter(|&triangle| keep(&self.triangles[triangle])),
+                ),
+                NodeKind::Branch { left, right } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Adds the node over the triangles in `order`, which start at `first` in the
+/// whole order, and the nodes beneath it. Returns the index of the node.
+fn build(
+    bounds: &[Aabb],
+    centroids: &[Point3<f32>],
+    order: &mut [usize],
+    first: usize,
+    nodes: &mut Vec<Node>,
+) -> usize {
+    let node_bounds = union_all(order.iter().map(|&i| bounds[i])).expect("A node has triangles");
+    let index = nodes.len();
+    nodes.push(Node {
+        bounds: node_bounds,
+        kind: NodeKind::Leaf {
+            first,
+            count: order.len(),
+        },
+    });
+    if order.len() == 1 {
+        return index;
+    }
+
+    let centroid_bounds =
+        union_all(order.iter().map(|&i| Aabb::new(centroids[i], centroids[i])))
+            .expect("A node has triangles");
+    let (axis, boundary, cost) =
+        match best_split(&node_bounds, &centroid_bounds, bounds, centroids, order) {
+            Some(split) => split,
+            // Every centroid is in the same place, so they cannot be split.
+            None => return index,
+        };
+    if cost >= order.len() as f32 && order.len() <= MAX_LEAF_TRIANGLES {
+        return index;
+    }
+
+    let min = centroid_bounds.min[axis];
+    let extent = centroid_bounds.max[axis] - min;
+    order.sort_by(|&a, &b| {
+        centroids[a][axis]
+            .partial_cmp(&centroids[b][axis])
+            .unwrap_or(Ordering::Equal)
+    });
+    let middle = order
+        .iter()
+        .take_while(|&&i| bin(centroids[i][axis], min, extent) < boundary)
+        .count();
+
+    let (left_order, right_order) = order.split_at_mut(middle);
+    let left = build(bounds, centroids, left_order, first, nodes);
+    let right = build(bounds, centroids, right_order, first + middle, nodes);
+    nodes[index].kind = NodeKind::Branch { left, right };
+    index
+}
+
+/// The axis and the bin boundary along it which split the triangles most
+/// cheaply by the surface area heuristic, with the cost of the split relative
+/// to testing one triangle, or `None` if they cannot be split.
+fn best_split(
+    node_bounds: &Aabb,
+    centroid_bounds: &Aabb,
+    bounds: &[Aabb],
+    centroids: &[Point3<f32>],
+    order: &[usize],
+) -> Option<(usize, usize, f32)> {
+    let area = node_bounds.surface_area().max(1.0e-12);
+    let mut best: Option<(usize, usize, f32)> = None;
+    for axis in 0..3 {
+        let min = centroid_bounds.min[axis];
+        let extent = centroid_bounds.max[axis] - min;
+        if extent <= 0.0 {
+            continue;
+        }
+
+        let mut counts = [0usize; BINS];
+        let mut bin_bounds: [Option<Aabb>; BINS] = [None; BINS];
+        for &i in order {
+            let b = bin(centroids[i][axis], min, extent);
+            counts[b] += 1;
+            bin_bounds[b] = Some(bin_bounds[b].map_or(bounds[i], |all| all.union(&bounds[i])));
+        }
+
+        for boundary in 1..BINS {
+            let left_count: usize = counts[..boundary].iter().sum();
+            let right_count: usize = counts[boundary..].iter().sum();
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            let left = union_all(bin_bounds[..boundary].iter().filter_map(|&b| b));
+            let right = union_all(bin_bounds[boundary..].iter().filter_map(|&b| b));
+            let (left, right) = match (left, right) {
+                (Some(left), Some(right)) => (left, right),
+                _ => continue,
+            };
+            let cost = TRAVERSAL_COST
+                + (left_count as f32 * left.surface_area()
+                    + right_count as f32 * right.surface_area()) / area;
+            if best.map_or(true, |(_, _, best_cost)| cost < best_cost) {
+                best = Some((axis, boundary, cost));
+            }
+        }
+    }
+    best
+}
+
+#[inline]
+fn bin(value: f32, min: f32, extent: f32) -> usize {
+    (((value - min) / extent * BINS as f32) as usize).min(BINS - 1)
+}
+
+fn union_all<I: Iterator<Item = Aabb>>(boxes: I) -> Option<Aabb> {
+    boxes.fold(None, |all: Option<Aabb>, b| Some(all.map_or(b, |all| all.union(&b))))
+}
+
+/// A hash of the triangles, to tell whether a cache was made from them.
+fn fingerprint(triangles: &[Triangle]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    triangles.len().hash(&mut hasher);
+    for triangle in triangles {
+        for point in &[triangle.a, triangle.b, triangle.c] {
+            point.x.to_bits().hash(&mut hasher);
+            point.y.to_bits().hash(&mut hasher);
+            point.z.to_bits().hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+fn parse<T: FromStr>(words: &mut SplitWhitespace) -> Option<T> {
+    words.next().and_then(|word| word.parse().ok())
+}
+
+#[derive(Debug)]
+pub enum BvhError {
+    Io(io::Error),
+    NotABvh,
+    /// A line which is not valid, with its line number.
+    Parse(usize, String),
+    /// The hierarchy was built over other triangles.
+    Stale,
+}
+
+impl From<io::Error> for BvhError {
+    #[inline]
+    fn from(e: io::Error) -> Self {
+        BvhError::Io(e)
+    }
+}
+
+impl fmt::Display for BvhError {
+    #[inline]
+    fn fmt(&self, fmtr: &mut fmt::Formatter) -> fmt::Result {
+        let desc = self.description();
+        match *self {
+            BvhError::Io(ref e) => write!(fmtr, "{}: {}", desc, e),
+            BvhError::Parse(number, ref line) => {
+                write!(fmtr, "{} on line {}: {}", desc, number, line)
+            }
+            BvhError::NotABvh | BvhError::Stale => write!(fmtr, "{}", desc),
+        }
+    }
+}
+
+impl Error for BvhError {
+    #[inline]
+    fn description(&self) -> &str {
+        match *self {
+            BvhError::Io(_) => "An io error occurred while accessing the bvh",
+            BvhError::NotABvh => "The file is not a valid bvh",
+            BvhError::Parse(..) => "The bvh contains a line which is not valid",
+            BvhError::Stale => "The bvh was built for a different mesh",
+        }
+    }
+
+    #[inline]
+    fn cause(&self) -> Option<&Error> {
+        match *self {
+            BvhError::Io(ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use graphics::load::load_obj;
+    use na::Vector3;
+    use std::env;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn mesh(name: &str) -> Vec<Triangle> {
+        let path = format!("{}/data/mesh/{}.obj", env!("CARGO_MANIFEST_DIR"), name);
+        let (vertices, indices) = load_obj(&path).unwrap();
+        Triangle::from_mesh(&vertices, &indices)
+    }
+
+    fn meshes() -> Vec<(&'static str, Vec<Triangle>)> {
+        vec![
+            ("cube", mesh("cube")),
+            ("suzanne", mesh("suzanne")),
+            ("floor", mesh("floor")),
+        ]
+    }
+
+    fn bounds(triangles: &[Triangle]) -> Aabb {
+        union_all(triangles.iter().map(Triangle::bounds)).unwrap()
+    }
+
+    /// Rays from all around the mesh towards points spread through it.
+    fn rays(aabb: &Aabb) -> Vec<Ray> {
+        let center = aabb.center();
+        let radius = aabb.radius().max(1.0);
+        let mut rays = Vec::new();
+        for i in 0..8 {
+            for j in 0..8 {
+                // Aims between the edges of the mesh, where hits on either
+                // side of an edge can round differently.
+                let s = (i as f32 + 0.5) / 8.0;
+                let t = (j as f32 + 0.5) / 8.0;
+                let target = Point3::new(
+                    aabb.min.x + (aabb.max.x - aabb.min.x) * s,
+                    center.y,
+                    aabb.min.z + (aabb.max.z - aabb.min.z) * t,
+                );
+                for &offset in &[Vector3::new(0.3, 1.0, 0.2), Vector3::new(-1.0, 0.1, 0.4)] {
+                    let origin = target + offset.normalize() * radius * 3.0;
+                    rays.push(Ray::new(origin, (target - origin).normalize()));
+                }
+            }
+        }
+        rays
+    }
+
+    fn sorted(mut indices: Vec<usize>) -> Vec<usize> {
+        indices.sort();
+        indices
+    }
+
+    #[test]
+    fn every_triangle_is_in_exactly_one_leaf() {
+        for (name, triangles) in meshes() {
+            let bvh = Bvh::new(triangles.clone());
+            assert!(!triangles.is_empty(), "{} has no triangles", name);
+            assert!(bvh.is_valid(), "{} is not valid", name);
+            assert_eq!(sorted(bvh.order.clone()), (0..triangles.len()).collect::<Vec<_>>());
+            let mut leaf_total = 0;
+            for node in &bvh.nodes {
+                if let NodeKind::Leaf { count, .. } = node.kind {
+                    leaf_total += count;
+                }
+            }
+            assert_eq!(leaf_total, triangles.len(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn ray_casts_match_testing_every_triangle() {
+        for (name, triangles) in meshes() {
+            let bvh = Bvh::new(triangles.clone());
+            let mut hits = 0;
+            for ray in rays(&bounds(&triangles)) {
+                let expected = triangles
+                    .iter()
+                    .filter_map(|triangle| ray.intersect_triangle(triangle))
+                    .fold(None, |nearest: Option<f32>, d| {
+                        Some(nearest.map_or(d, |nearest| nearest.min(d)))
+                    });
+                let actual = bvh.cast_ray(&ray).map(|hit| hit.distance);
+                match (expected, actual) {
+                    (Some(expected), Some(actual)) => {
+                        assert!((expected - actual).abs() < 1.0e-4, "{}", name);
+                        hits += 1;
+                    }
+                    (None, None) => (),
+                    _ => panic!("{}: expected {:?}, got {:?}", name, expected, actual),
+                }
+            }
+            assert!(hits > 0, "{} was never hit", name);
+        }
+    }
+
+    #[test]
+    fn box_and_sphere_queries_match_testing_every_triangle() {
+        for (name, triangles) in meshes() {
+            let bvh = Bvh::new(triangles.clone());
+            let aabb = bounds(&triangles);
+            let center = aabb.center();
+            let radius = aabb.radius() / 3.0;
+
+            let query = Aabb::new(center, aabb.max);
+            let expected: Vec<usize> = (0..triangles.len())
+                .filter(|&i| triangles[i].bounds().intersects(&query))
+                .collect();
+            assert_eq!(sorted(bvh.triangles_in_aabb(&query)), expected, "{}", name);
+
+            let expected: Vec<usize> = (0..triangles.len())
+                .filter(|&i| {
+                    let offset = triangles[i].closest_point(&center) - center;
+                    offset.norm_squared() <= radius * radius
+                })
+                .collect();
+            assert_eq!(sorted(bvh.triangles_in_sphere(&center, radius)), expected, "{}", name);
+
+            let margin = Vector3::from_element(1.0);
+            let everything = Aabb::new(aabb.min - margin, aabb.max + margin);
+            assert_eq!(bvh.triangles_in_aabb(&everything).len(), triangles.len(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn cache_loads_only_for_the_same_triangles() {
+        // Another run of the tests may be using the temporary directory at the
+        // same time.
+        let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let name = format!("cache_loads_only_for_the_same_triangles_{}.bvh", time.subsec_nanos());
+        let path = env::temp_dir().join(name);
+        let suzanne = mesh("suzanne");
+        let bvh = Bvh::new(suzanne.clone());
+        bvh.save(&path).unwrap();
+
+        assert_eq!(Bvh::load(&path, &suzanne).unwrap(), bvh);
+        match Bvh::load(&path, &mesh("cube")) {
+            Err(BvhError::Stale) => (),
+            other => panic!("expected a stale cache, got {:?}", other),
+        }
+        fs::remove_file(&path).unwrap();
+    }
+}
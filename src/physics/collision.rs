@@ -0,0 +1,308 @@
+//! Collision between an upright capsule and the triangles of the scene, for
+//! walking around it.
+
+use ::Vertex;
+use graphics::bounds::Aabb;
+use graphics::load::Index;
+use graphics::transform::Transform;
+use na::{Point3, Vector3};
+use physics::bvh::Bvh;
+use std::rc::Rc;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Triangle {
+    pub a: Point3<f32>,
+    pub b: Point3<f32>,
+    pub c: Point3<f32>,
+}
+
+impl Triangle {
+    #[inline]
+    pub fn new(a: Point3<f32>, b: Point3<f32>, c: Point3<f32>) -> Self {
+        Triangle { a, b, c }
+    }
+
+    /// The triangles of an indexed mesh, as `load_obj` returns it.
+    pub fn from_mesh(vertices: &[Vertex], indices: &[Index]) -> Vec<Triangle> {
+        let point = |i: Index| {
+            let pos = vertices[i as usize].pos;
+            Point3::new(pos[0], pos[1], pos[2])
+        };
+        indices
+            .chunks(3)
+            .filter(|triangle| triangle.len() == 3)
+            .map(|t| Triangle::new(point(t[0]), point(t[1]), point(t[2])))
+            .collect()
+    }
+
+    /// The triangle once it has been transformed. Mirroring it would turn it
+    /// inside out, so then two of its corners are swapped to keep its front
+    /// the same side.
+    pub fn transformed(&self, transform: &Transform) -> Triangle {
+        let (a, b, c) = (
+            transform.transform_point(&self.a),
+            transform.transform_point(&self.b),
+            transform.transform_point(&self.c),
+        );
+        if transform.is_mirrored() {
+            Triangle::new(a, c, b)
+        } else {
+            Triangle::new(a, b, c)
+        }
+    }
+
+    /// The unit normal of the front, which has its corners anticlockwise, or
+    /// zero if the triangle has no area.
+    pub fn normal(&self) -> Vector3<f32> {
+        let normal = (self.b - self.a).cross(&(self.c - self.a));
+        normal.try_normalize(1.0e-12).unwrap_or_else(Vector3::zeros)
+    }
+
+    #[inline]
+    pub fn bounds(&self) -> Aabb {
+        Aabb::from_points(vec![self.a, self.b, self.c]).expect("A triangle has corners")
+    }
+
+    /// The point on the triangle nearest to `p`, found by working out which
+    /// corner, edge or the face it is nearest, as in Ericson's Real-Time
+    /// Collision Detection.
+    pub fn closest_point(&self, p: &Point3<f32>) -> Point3<f32> {
+        let (a, b, c) = (self.a, self.b, self.c);
+        let ab = b - a;
+        let ac = c - a;
+        let ap = p - a;
+        let d1 = ab.dot(&ap);
+        let d2 = ac.dot(&ap);
+        if d1 <= 0.0 && d2 <= 0.0 {
+            return a;
+        }
+
+        let bp = p - b;
+        let d3 = ab.dot(&bp);
+        let d4 = ac.dot(&bp);
+        if d3 >= 0.0 && d4 <= d3 {
+            return b;
+        }
+
+        let vc = d1 * d4 - d3 * d2;
+        if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+            return a + ab * (d1 / (d1 - d3));
+        }
+
+        let cp = p - c;
+        let d5 = ab.dot(&cp);
+        let d6 = ac.dot(&cp);
+        if d6 >= 0.0 && d5 <= d6 {
+            return c;
+        }
+
+        let vb = d5 * d2 - d1 * d6;
+        if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+            return a + ac * (d2 / (d2 - d6));
+        }
+
+        let va = d3 * d6 - d5 * d4;
+        if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+            return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+        }
+
+        let denom = 1.0 / (va + vb + vc);
+        a + ab * (vb * denom) + ac * (vc * denom)
+    }
+}
+
+/// The triangles which can be collided with, as the hierarchies over each
+/// mesh and where the mesh is placed, so that moving a model does not mean
+/// rebuilding its hierarchy.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CollisionMesh {
+    meshes: Vec<(Rc<Bvh>, Transform)>,
+}
+
+impl CollisionMesh {
+    pub fn new<I: IntoIterator<Item = (Rc<Bvh>, Transform)>>(meshes: I) -> Self {
+        CollisionMesh {
+            meshes: meshes.into_iter().collect(),
+        }
+    }
+
+    /// The triangles in world space which might overlap `bounds`.
+    pub fn triangles_near(&self, bounds: &Aabb) -> Vec<Triangle> {
+        let mut triangles = Vec::new();
+        for &(ref bvh, ref transform) in &self.meshes {
+            let local_bounds = bounds.inverse_transformed(transform);
+            let mesh = bvh.triangles();
+            triangles.extend(
+                bvh.triangles_in_aabb(&local_bounds)
+                    .into_iter()
+                    .map(|i| mesh[i].transformed(transform)),
+            );
+        }
+        triangles
+    }
+}
+
+/// A capsule standing upright, which is how a person is usually approximated
+/// since it slides over steps and edges rather than catching on them.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capsule {
+    pub radius: f32,
+    /// From the bottom to the top, including the rounded ends.
+    pub height: f32,
+}
+
+impl Capsule {
+    /// The centres of the spheres at its ends, when it stands on `base`.
+    #[inline]
+    pub fn segment(&self, base: &Point3<f32>) -> (Point3<f32>, Point3<f32>) {
+        let radius = self.radius.min(self.height / 2.0);
+        (
+            base + Vector3::y() * radius,
+            base + Vector3::y() * (self.height - radius),
+        )
+    }
+
+    pub fn bounds(&self, base: &Point3<f32>) -> Aabb {
+        let r = self.radius;
+        Aabb::new(
+            base + Vector3::new(-r, 0.0, -r),
+            base + Vector3::new(r, self.height, r),
+        )
+    }
+
+    /// How far the capsule standing on `base` overlaps the triangle, if it
+    /// does, and which way to move it to stop overlapping.
+    pub fn collide(&self, base: &Point3<f32>, triangle: &Triangle) -> Option<Contact> {
+        let (start, end) = self.segment(base);
+
+        // Finds the point on the capsule's axis nearest the triangle, by way
+        // of where the axis meets the triangle's plane, and then treats the
+        // capsule as a sphere there.
+        let normal = triangle.normal();
+        let axis = end - start;
+        let along_normal = normal.dot(&axis);
+        let reference = if along_normal.abs() > 1.0e-6 {
+            let t = normal.dot(&(triangle.a - start)) / along_normal;
+            let on_plane = start + axis * t;
+            triangle.closest_point(&on_plane)
+        } else {
+            triangle.closest_point(&start)
+        };
+        let center = closest_point_on_segment(&reference, &start, &end);
+
+        let closest = triangle.closest_point(&center);
+        let offset = center - closest;
+        let distance = offset.norm();
+        let radius = self.radius.min(self.height / 2.0);
+        if distance >= radius {
+            return None;
+        }
+
+        let normal = if distance > 1.0e-6 {
+            offset / distance
+        } else if normal != Vector3::zeros() {
+            // The axis passes through the triangle, so it is pushed out of
+            // whichever side it is mostly on.
+            if normal.dot(&(center - triangle.a)) < 0.0 {
+                -normal
+            } else {
+                normal
+            }
+        } else {
+            return None;
+        };
+        Some(Contact {
+            normal,
+            depth: radius - distance,
+        })
+    }
+}
+
+/// Where a capsule overlaps a triangle.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    /// The way to move the capsule to separate them.
+    pub normal: Vector3<f32>,
+    /// How far to move it.
+    pub depth: f32,
+}
+
+fn closest_point_on_segment(p: &Point3<f32>, a: &Point3<f32>, b: &Point3<f32>) -> Point3<f32> {
+    let ab = b - a;
+    let length_squared = ab.norm_squared();
+    if length_squared <= 0.0 {
+        return *a;
+    }
+    let t = ((p - a).dot(&ab) / length_squared).max(0.0).min(1.0);
+    a + ab * t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn floor() -> Triangle {
+        Triangle::new(
+            Point3::new(-10.0, 0.0, 10.0),
+            Point3::new(10.0, 0.0, 10.0),
+            Point3::new(0.0, 0.0, -10.0),
+        )
+    }
+
+    #[test]
+    fn closest_point_is_inside_or_on_the_edge() {
+        let triangle = floor();
+        let above = triangle.closest_point(&Point3::new(0.0, 5.0, 0.0));
+        assert!((above - Point3::new(0.0, 0.0, 0.0)).norm() < 1.0e-5);
+        let beyond_edge = triangle.closest_point(&Point3::new(0.0, 0.0, 20.0));
+        assert_eq!(beyond_edge, Point3::new(0.0, 0.0, 10.0));
+        assert_eq!(triangle.closest_point(&Point3::new(-20.0, 1.0, 20.0)), triangle.a);
+    }
+
+    #[test]
+    fn mirrored_triangle_still_faces_the_same_way() {
+        let transform = Transform {
+            scale: Vector3::new(-2.0, 1.0, 1.0),
+            ..Default::default()
+        };
+        let mirrored = floor().transformed(&transform);
+        assert!((mirrored.normal() - Vector3::y()).norm() < 1.0e-5);
+        assert_eq!(mirrored.a, Point3::new(20.0, 0.0, 10.0));
+    }
+
+    #[test]
+    fn capsule_sunk_into_the_floor_is_pushed_up() {
+        let capsule = Capsule {
+            radius: 0.5,
+            height: 2.0,
+        };
+        let contact = capsule.collide(&Point3::new(0.0, -0.25, 0.0), &floor()).unwrap();
+        assert!((contact.normal - Vector3::y()).norm() < 1.0e-5);
+        assert!((contact.depth - 0.25).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn capsule_above_the_floor_does_not_touch_it() {
+        let capsule = Capsule {
+            radius: 0.5,
+            height: 2.0,
+        };
+        assert_eq!(capsule.collide(&Point3::new(0.0, 0.1, 0.0), &floor()), None);
+    }
+
+    #[test]
+    fn capsule_against_a_wall_is_pushed_sideways() {
+        let wall = Triangle::new(
+            Point3::new(1.0, -10.0, -10.0),
+            Point3::new(1.0, -10.0, 10.0),
+            Point3::new(1.0, 10.0, 0.0),
+        );
+        let capsule = Capsule {
+            radius: 0.5,
+            height: 2.0,
+        };
+        let contact = capsule.collide(&Point3::new(0.75, 0.0, 0.0), &wall).unwrap();
+        assert!((contact.normal + Vector3::x()).norm() < 1.0e-5);
+        assert!((contact.depth - 0.25).abs() < 1.0e-5);
+    }
+}
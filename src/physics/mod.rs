@@ -0,0 +1,3 @@
+pub mod bvh;
+pub mod collision;
+pub mod ray;
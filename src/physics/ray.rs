@@ -0,0 +1,223 @@
+//! Rays, for finding what is under the cursor.
+
+use graphics::bounds::Aabb;
+use graphics::camera::CameraMatrices;
+use graphics::transform::Transform;
+use na::{Point3, Vector3, Vector4};
+use physics::collision::Triangle;
+use std::f32;
+
+/// A half line from `origin` along `direction`, where a point on it is
+/// `origin + direction * t` for a `t` of zero or more.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+/// Where a ray hits a triangle of a mesh.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// The index of the triangle in the mesh.
+    pub triangle: usize,
+    /// How far along the ray it is hit, in lengths of its direction.
+    pub distance: f32,
+}
+
+impl Ray {
+    #[inline]
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Ray { origin, direction }
+    }
+
+    /// The ray from the camera through a point on the screen, given in
+    /// normalized device coordinates, where the corners of the screen are at
+    /// -1 and 1 and y is up. Its direction has unit length, so distances
+    /// along it are in world units.
+    pub fn from_screen(matrices: &CameraMatrices, x: f32, y: f32) -> Option<Ray> {
+        let inverse = (matrices.projection * matrices.view).try_inverse()?;
+        let unproject = |z: f32| {
+            let point = inverse * Vector4::new(x, y, z, 1.0);
+            Point3::new(point.x, point.y, point.z) / point.w
+        };
+        let near = unproject(-1.0);
+        let far = unproject(1.0);
+        let direction = (far - near).try_normalize(1.0e-12)?;
+        Some(Ray::new(near, direction))
+    }
+
+    #[inline]
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// The ray in the space `transform` maps from, such as a model's own
+    /// space. Its direction is not normalized, so that distances along it in
+    /// lengths of its direction are the same as along this one, and hits in a
+    /// model's own space can be compared with hits in the world.
+    #[inline]
+    pub fn inverse_transformed(&self, transform: &Transform) -> Ray {
+        Ray::new(
+            transform.inverse_transform_point(&self.origin),
+            transform.inverse_transform_vector(&self.direction),
+        )
+    }
+
+    /// How far along the ray it enters the box, or zero if it starts inside
+    /// it.
+    pub fn intersect_aabb(&self, aabb: &Aabb) -> Option<f32> {
+        let mut entry = 0.0f32;
+        let mut exit = f32::INFINITY;
+        for axis in 0..3 {
+            let inverse = 1.0 / self.direction[axis];
+            let mut near = (aabb.min[axis] - self.origin[axis]) * inverse;
+            let mut far = (aabb.max[axis] - self.origin[axis]) * inverse;
+            if inverse < 0.0 {
+                ::std::mem::swap(&mut near, &mut far);
+            }
+            entry = entry.max(near);
+            exit = exit.min(far);
+            if exit < entry {
+                return None;
+            }
+        }
+        Some(entry)
+    }
+
+    /// How far along the ray it hits the plane through `point` which faces
+    /// `normal`, from either side.
+    pub fn intersect_plane(&self, point: &Point3<f32>, normal: &Vector3<f32>) -> Option<f32> {
+        let along_normal = normal.dot(&self.direction);
+        if along_normal.abs() < 1.0e-6 {
+            return None;
+        }
+        let distance = normal.dot(&(point - self.origin)) / along_normal;
+        if distance >= 0.0 {
+            Some(distance)
+        } else {
+            None
+        }
+    }
+
+    /// How far along the line through `point` in the direction `axis`, in
+    /// lengths of `axis`, is the point on it nearest to the ray. Lines
+    /// parallel to the ray have no nearest point.
+    pub fn closest_on_line(&self, point: &Point3<f32>, axis: &Vector3<f32>) -> Option<f32> {
+        let offset = point - self.origin;
+        let a = axis.dot(axis);
+        let b = axis.dot(&self.direction);
+        let c = self.direction.dot(&self.direction);
+        let d = axis.dot(&offset);
+        let e = self.direction.dot(&offset);
+        let denominator = a * c - b * b;
+        if denominator.abs() < 1.0e-6 * a * c {
+            return None;
+        }
+        Some((b * e - c * d) / denominator)
+    }
+
+    /// How far the point is from the nearest point on the ray.
+    pub fn distance_to_point(&self, point: &Point3<f32>) -> f32 {
+        let length_squared = self.direction.norm_squared();
+        if length_squared <= 0.0 {
+            return (point - self.origin).norm();
+        }
+        let t = ((point - self.origin).dot(&self.direction) / length_squared).max(0.0);
+        (point - self.at(t)).norm()
+    }
+
+    /// How far along the ray it hits either side of the triangle, using the
+    /// Möller–Trumbore test.
+    pub fn intersect_triangle(&self, triangle: &Triangle) -> Option<f32> {
+        let edge1 = triangle.b - triangle.a;
+        let edge2 = triangle.c - triangle.a;
+        let p = self.direction.cross(&edge2);
+        let determinant = edge1.dot(&p);
+        if determinant.abs() < 1.0e-12 {
+            // The ray is parallel to the triangle.
+            return None;
+        }
+        let inverse = 1.0 / determinant;
+
+        let s = self.origin - triangle.a;
+        let u = s.dot(&p) * inverse;
+        if u < 0.0 || u > 1.0 {
+            return None;
+        }
+        let q = s.cross(&edge1);
+        let v = self.direction.dot(&q) * inverse;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let distance = edge2.dot(&q) * inverse;
+        if distance >= 0.0 {
+            Some(distance)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use na::Matrix4;
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            Point3::new(-1.0, -1.0, 0.0),
+            Point3::new(1.0, -1.0, 0.0),
+            Point3::new(0.0, 1.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn ray_hits_the_triangle_in_front_of_it() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), -Vector3::z());
+        let distance = ray.intersect_triangle(&triangle()).unwrap();
+        assert!((distance - 5.0).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn ray_misses_triangles_beside_and_behind_it() {
+        let beside = Ray::new(Point3::new(3.0, 0.0, 5.0), -Vector3::z());
+        assert_eq!(beside.intersect_triangle(&triangle()), None);
+        let away = Ray::new(Point3::new(0.0, 0.0, 5.0), Vector3::z());
+        assert_eq!(away.intersect_triangle(&triangle()), None);
+    }
+
+    #[test]
+    fn ray_enters_box_at_its_near_side() {
+        let aabb = Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Point3::new(-5.0, 0.0, 0.0), Vector3::x());
+        assert_eq!(ray.intersect_aabb(&aabb), Some(4.0));
+        let inside = Ray::new(Point3::origin(), Vector3::y());
+        assert_eq!(inside.intersect_aabb(&aabb), Some(0.0));
+        let past = Ray::new(Point3::new(-5.0, 2.0, 0.0), Vector3::x());
+        assert_eq!(past.intersect_aabb(&aabb), None);
+    }
+
+    #[test]
+    fn ray_meets_planes_and_lines() {
+        let ray = Ray::new(Point3::new(0.0, 0.0, 5.0), -Vector3::z());
+        let plane = ray.intersect_plane(&Point3::new(3.0, 2.0, 1.0), &Vector3::z());
+        assert_eq!(plane, Some(4.0));
+        assert_eq!(ray.intersect_plane(&Point3::origin(), &Vector3::x()), None);
+
+        let line = ray.closest_on_line(&Point3::new(-2.0, 1.0, 0.0), &(Vector3::x() * 2.0));
+        assert!((line.unwrap() - 1.0).abs() < 1.0e-5);
+        assert_eq!(ray.closest_on_line(&Point3::new(1.0, 0.0, 0.0), &Vector3::z()), None);
+
+        let distance = ray.distance_to_point(&Point3::new(0.0, 3.0, 2.0));
+        assert!((distance - 3.0).abs() < 1.0e-5);
+    }
+
+    #[test]
+    fn ray_from_screen_centre_looks_down_the_view_direction() {
+        let matrices = CameraMatrices::new(Matrix4::identity(), Matrix4::identity(), -1.0, 1.0);
+        let ray = Ray::from_screen(&matrices, 0.5, 0.0).unwrap();
+        assert!((ray.origin - Point3::new(0.5, 0.0, -1.0)).norm() < 1.0e-5);
+        assert!((ray.direction - Vector3::z()).norm() < 1.0e-5);
+    }
+}
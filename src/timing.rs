@@ -0,0 +1,129 @@
+//! Keeps the simulation independent of how fast frames are drawn.
+//!
+//! The simulation always advances in steps of the same length, as many as fit
+//! into the time which has passed, and frames are drawn in between two steps
+//! by interpolating between their results.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How many steps one frame may run at most. When frames take longer than
+/// this, the simulation slows down rather than falling further and further
+/// behind.
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+/// How long before the end of a frame the limiter stops sleeping and starts
+/// yielding instead, since sleeps can overshoot by about a millisecond.
+const SPIN_TIME_MS: u64 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixedTimestep {
+    step: f64,
+    accumulator: f64,
+    time: f64,
+}
+
+impl FixedTimestep {
+    /// Steps `rate` times a second.
+    #[inline]
+    pub fn new(rate: f64) -> Self {
+        FixedTimestep {
+            step: 1.0 / rate,
+            accumulator: 0.0,
+            time: 0.0,
+        }
+    }
+
+    /// The length of a step, in seconds.
+    #[inline]
+    pub fn step(&self) -> f32 {
+        self.step as f32
+    }
+
+    /// Adds the time a frame took, and returns how many steps to run now.
+    pub fn advance(&mut self, dt_s: f64) -> u32 {
+        self.accumulator += dt_s.max(0.0);
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            self.time += self.step;
+            steps += 1;
+            if steps == MAX_STEPS_PER_FRAME {
+                self.accumulator = self.accumulator.min(self.step);
+                break;
+            }
+        }
+        steps
+    }
+
+    /// How far the frame is between the previous and the latest step, from 0
+    /// to 1.
+    #[inline]
+    pub fn alpha(&self) -> f32 {
+        (self.accumulator / self.step).min(1.0) as f32
+    }
+
+    /// The time which has been simulated, including the part of a step which
+    /// the current frame is drawn at.
+    #[inline]
+    pub fn time(&self) -> f32 {
+        (self.time + self.accumulator) as f32
+    }
+
+    /// Forgets the time which has not been simulated yet, such as after the
+    /// viewer was paused.
+    #[inline]
+    pub fn discard_remainder(&mut self) {
+        self.accumulator = 0.0;
+    }
+}
+
+/// Sleeps at the end of each frame so that frames are not drawn more often
+/// than the target rate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameLimiter {
+    frame_time: Option<Duration>,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    /// Limits frames to `rate` a second, or not at all if the rate is zero.
+    pub fn new(rate: f32) -> Self {
+        let frame_time = if rate > 0.0 {
+            let nanos = (1_000_000_000.0 / rate as f64) as u64;
+            Some(Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32))
+        } else {
+            None
+        };
+        FrameLimiter {
+            frame_time,
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Waits until the next frame is due. Frames are scheduled from when the
+    /// previous one was due rather than from when it ended, so the rate does
+    /// not drift, but a frame which is late does not make the next ones hurry.
+    pub fn wait(&mut self) {
+        let frame_time = match self.frame_time {
+            Some(frame_time) => frame_time,
+            None => return,
+        };
+
+        self.next_frame += frame_time;
+        let now = Instant::now();
+        if self.next_frame <= now {
+            self.next_frame = now;
+            return;
+        }
+
+        let remaining = self.next_frame - now;
+        let spin_time = Duration::from_millis(SPIN_TIME_MS);
+        if remaining > spin_time {
+            thread::sleep(remaining - spin_time);
+        }
+        while Instant::now() < self.next_frame {
+            thread::yield_now();
+        }
+    }
+}
@@ -23,6 +23,17 @@ pub fn get_assets_folder() -> Result<&'static Path, GetAssetsFolderError> {
     }
 }
 
+/// Finds a file given on the command line or in the code. An existing path is
+/// used as it is, and anything else is looked up in the assets folder.
+pub fn find_asset<P: AsRef<Path>>(path: P) -> Result<PathBuf, GetAssetsFolderError> {
+    let path = path.as_ref();
+    if path.is_file() {
+        Ok(path.to_path_buf())
+    } else {
+        Ok(get_assets_folder()?.join(path))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct GetAssetsFolderError(&'static (Error + Send + Sync + 'static));
 
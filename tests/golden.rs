@@ -0,0 +1,222 @@
+//! Renders the built in scenes through the headless mode and compares the
+//! results against the reference images in `tests/golden`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to write the current output as the new reference
+//! images, after checking that the change in the rendering was intended. When a
+//! comparison fails, the rendered image and a diff are written next to the test
+//! binary, under `golden`.
+//!
+//! The tests which render with OpenGL need a headless OpenGL context, which
+//! many machines, such as most CI runners, do not have. They are ignored by
+//! default and run with `cargo test -- --ignored`. The software renderer's
+//! tests always run.
+
+extern crate image;
+
+use image::{Rgba, RgbaImage};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const SIZE: &str = "256x256";
+
+/// The largest colour difference, as CIE76 delta E, which is still treated as
+/// the same colour. A difference of around 2.3 is just noticeable.
+const MAX_DELTA_E: f32 = 3.0;
+
+/// The share of pixels which may differ by more than `MAX_DELTA_E`, to allow
+/// for drivers rasterizing edges slightly differently.
+const MAX_DIFFERING_FRACTION: f32 = 0.002;
+
+#[test]
+#[ignore]
+fn first_frame() {
+    check_golden("first_frame", &["--frames", "1"]);
+}
+
+#[test]
+#[ignore]
+fn after_one_second() {
+    check_golden("after_one_second", &["--frames", "60"]);
+}
+
+/// The scene made for checking the lighting, with smooth, rough and metal
+/// models casting shadows on a floor and reflecting the sky in
+/// `data/img/environment.hdr`, which also lights them.
+#[test]
+#[ignore]
+fn materials() {
+    check_golden("materials", &["--scene", "materials", "--frames", "1"]);
+}
+
+/// The software renderer needs no graphics device, so these also run on
+/// machines where the others cannot. It draws no shadows, so it has its own
+/// golden images rather than sharing the gpu's.
+#[test]
+fn software_first_frame() {
+    check_golden("software_first_frame", &["--backend", "software", "--frames", "1"]);
+}
+
+#[test]
+fn software_materials() {
+    let args = &["--backend", "software", "--scene", "materials", "--frames", "1"];
+    check_golden("software_materials", args);
+}
+
+fn check_golden(name: &str, args: &[&str]) {
+    let output_dir = output_dir();
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let actual_path = output_dir.join(format!("{}.png", name));
+    let actual = render(args, &actual_path);
+
+    let golden_path = golden_dir().join(format!("{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden_dir()).unwrap();
+        actual.save(&golden_path).unwrap();
+        return;
+    }
+
+    let expected = match image::open(&golden_path) {
+        Ok(img) => img.to_rgba(),
+        Err(e) => panic!(
+            "Could not open {}: {}. Run with UPDATE_GOLDEN=1 to create it.",
+            golden_path.display(),
+            e
+        ),
+    };
+    assert_eq!(
+        expected.dimensions(),
+        actual.dimensions(),
+        "{} was rendered at the wrong size",
+        name
+    );
+
+    let comparison = compare(&expected, &actual);
+    if comparison.differing_fraction > MAX_DIFFERING_FRACTION {
+        let diff_path = output_dir.join(format!("{}.diff.png", name));
+        comparison.diff.save(&diff_path).unwrap();
+        panic!(
+            "{:.3}% of the pixels in {} differ from {} (the largest delta E is {:.1}). \
+             See {} and {}.",
+            comparison.differing_fraction * 100.0,
+            name,
+            golden_path.display(),
+            comparison.max_delta_e,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+/// Runs the binary headless and reads back the screenshot it wrote.
+fn render(args: &[&str], path: &Path) -> RgbaImage {
+    let status = Command::new(binary_path())
+        .args(&["--headless", "--size", SIZE])
+        .args(args)
+        .arg("--screenshot")
+        .arg(path)
+        .status()
+        .expect("Could not run the renderer");
+    assert!(status.success(), "The renderer exited with {}", status);
+    image::open(path).unwrap().to_rgba()
+}
+
+struct Comparison {
+    differing_fraction: f32,
+    max_delta_e: f32,
+    diff: RgbaImage,
+}
+
+/// Compares the images in CIE L*a*b* space, where distances roughly match how
+/// different the colours look. The diff image is a faded copy of the expected
+/// image with every differing pixel in red.
+fn compare(expected: &RgbaImage, actual: &RgbaImage) -> Comparison {
+    let (width, height) = expected.dimensions();
+    let mut diff = RgbaImage::new(width, height);
+    let mut differing = 0;
+    let mut max_delta_e = 0.0f32;
+
+    for (x, y, expected_pixel) in expected.enumerate_pixels() {
+        let actual_pixel = actual.get_pixel(x, y);
+        let delta_e = delta_e(to_lab(expected_pixel), to_lab(actual_pixel));
+        max_delta_e = max_delta_e.max(delta_e);
+
+        let diff_pixel = if delta_e > MAX_DELTA_E {
+            differing += 1;
+            Rgba { data: [255, 0, 0, 255] }
+        } else {
+            let l = to_lab(expected_pixel)[0];
+            let faded = (l / 100.0 * 64.0) as u8 + 128;
+            Rgba { data: [faded, faded, faded, 255] }
+        };
+        diff.put_pixel(x, y, diff_pixel);
+    }
+
+    Comparison {
+        differing_fraction: differing as f32 / (width * height) as f32,
+        max_delta_e,
+        diff,
+    }
+}
+
+fn delta_e(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let (dl, da, db) = (a[0] - b[0], a[1] - b[1], a[2] - b[2]);
+    (dl * dl + da * da + db * db).sqrt()
+}
+
+/// Converts an sRGB pixel to L*a*b* with a D65 white point.
+fn to_lab(pixel: &Rgba<u8>) -> [f32; 3] {
+    fn to_linear(c: u8) -> f32 {
+        let c = c as f32 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    fn f(t: f32) -> f32 {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    }
+
+    let (r, g, b) = (
+        to_linear(pixel.data[0]),
+        to_linear(pixel.data[1]),
+        to_linear(pixel.data[2]),
+    );
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+/// Integration tests are built into `target/<profile>/deps`, next to the
+/// folder which holds the binary.
+fn target_dir() -> PathBuf {
+    let mut dir = env::current_exe().unwrap();
+    dir.pop();
+    if dir.ends_with("deps") {
+        dir.pop();
+    }
+    dir
+}
+
+fn binary_path() -> PathBuf {
+    target_dir().join(format!("gfx_tutorial{}", env::consts::EXE_SUFFIX))
+}
+
+fn output_dir() -> PathBuf {
+    target_dir().join("golden")
+}